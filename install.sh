@@ -188,7 +188,7 @@ After=network.target
 Wants=network.target
 
 [Service]
-Type=exec
+Type=notify
 User=${SERVICE_USER}
 Group=${SERVICE_USER}
 ExecStart=${INSTALL_DIR}/${BINARY_NAME}
@@ -196,6 +196,8 @@ ExecReload=/bin/kill -HUP \$MAINPID
 KillMode=mixed
 KillSignal=SIGTERM
 TimeoutStopSec=30
+# Service mengirim WATCHDOG=1 secara periodik; restart jika hang
+WatchdogSec=30
 RestartSec=5
 Restart=on-failure
 
@@ -1,17 +1,62 @@
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     Json,
 };
+use futures::{stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::{collections::HashMap, process::Command, sync::Arc, time::Duration};
-use tokio::sync::RwLock;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, RwLock};
 use tokio::time::sleep;
 use tracing::{error, info, warn, instrument};
 
-use crate::handlers::AppState;
+use crate::{config::Backend, handlers::AppState, pool::CONNECTION_MANAGER, settings::Settings};
+
+/// Drain mode: job print baru ditolak (503), job yang sedang berjalan tetap selesai
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Apakah proxy sedang dalam drain mode
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Job print yang sedang diproses (dilaporkan saat shutdown dipaksa)
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Penanda satu job print yang sedang berjalan; counter turun saat di-drop
+pub struct InFlightJob(());
+
+impl Drop for InFlightJob {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Tandai job print mulai diproses, sampai guard-nya di-drop
+pub fn job_started() -> InFlightJob {
+    IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+    InFlightJob(())
+}
+
+/// Jumlah job print yang belum selesai
+pub fn in_flight_jobs() -> usize {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Deserialize)]
 pub struct AdminQuery {
@@ -44,8 +89,8 @@ impl AdminResponse {
 }
 
 /// Validate admin token from environment variable
-fn validate_admin_token(provided_token: Option<&str>) -> bool {
-    let admin_token = std::env::var("ADMIN_TOKEN").unwrap_or_default();
+fn validate_admin_token(settings: &Settings, provided_token: Option<&str>) -> bool {
+    let admin_token = settings.admin_token.as_deref().unwrap_or_default();
     
     if admin_token.is_empty() {
         warn!("⚠️ ADMIN_TOKEN not set - admin endpoints disabled");
@@ -64,14 +109,14 @@ fn validate_admin_token(provided_token: Option<&str>) -> bool {
 }
 
 /// Admin shutdown endpoint
-#[instrument(skip(_state))]
+#[instrument(skip(state))]
 pub async fn admin_shutdown(
-    State(_state): State<Arc<RwLock<AppState>>>,
+    State(state): State<Arc<RwLock<AppState>>>,
     Query(query): Query<AdminQuery>,
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔒 Admin shutdown request received");
     
-    if !validate_admin_token(query.token.as_deref()) {
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
         warn!("❌ Invalid or missing admin token for shutdown");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -95,14 +140,14 @@ pub async fn admin_shutdown(
 }
 
 /// Admin restart endpoint  
-#[instrument(skip(_state))]
+#[instrument(skip(state))]
 pub async fn admin_restart(
-    State(_state): State<Arc<RwLock<AppState>>>,
+    State(state): State<Arc<RwLock<AppState>>>,
     Query(query): Query<AdminQuery>,
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔒 Admin restart request received");
     
-    if !validate_admin_token(query.token.as_deref()) {
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
         warn!("❌ Invalid or missing admin token for restart");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -145,14 +190,14 @@ pub async fn admin_restart(
 }
 
 /// Admin SSL renewal endpoint
-#[instrument(skip(_state))]
+#[instrument(skip(state))]
 pub async fn admin_renew_ssl(
-    State(_state): State<Arc<RwLock<AppState>>>,
+    State(state): State<Arc<RwLock<AppState>>>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔒 Admin SSL renewal request received");
     
-    if !validate_admin_token(params.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, params.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for SSL renewal");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -216,6 +261,318 @@ pub async fn admin_renew_ssl(
     ).into_response())
 }
 
+/// Admin drain mode toggle
+#[instrument(skip(state))]
+pub async fn admin_drain(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin drain request received");
+    
+    if !validate_admin_token(&state.read().await.settings, params.get("token").map(|s| s.as_str())) {
+        warn!("❌ Invalid or missing admin token for drain");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    let enable = match params.get("enable").map(|v| v.to_ascii_lowercase()) {
+        Some(v) if matches!(v.as_str(), "1" | "true" | "yes" | "on") => true,
+        Some(v) if matches!(v.as_str(), "0" | "false" | "no" | "off") => false,
+        _ => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(AdminResponse::error("Parameter 'enable' harus true atau false"))
+            ).into_response());
+        }
+    };
+    
+    DRAINING.store(enable, Ordering::Relaxed);
+    
+    let message = if enable {
+        warn!("🚰 Drain mode enabled - new print jobs will be rejected with 503");
+        "Drain mode enabled - new print jobs are rejected, in-flight jobs will finish"
+    } else {
+        info!("▶️ Drain mode disabled - accepting print jobs again");
+        "Drain mode disabled - accepting print jobs"
+    };
+    
+    Ok((StatusCode::OK, Json(AdminResponse::success(message))).into_response())
+}
+
+/// List job yang gagal permanen (dead letter)
+#[instrument(skip(state))]
+pub async fn admin_deadletter_list(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin dead letter list request received");
+    
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for dead letter list");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    match crate::deadletter::list() {
+        Ok(entries) => Ok((
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "total": entries.len(),
+                "dead_letters": entries,
+            }))
+        ).into_response()),
+        Err(e) => {
+            error!("❌ Failed to read dead letters: {}", e);
+            Ok((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AdminResponse::error(format!("Failed to read dead letters: {}", e)))
+            ).into_response())
+        }
+    }
+}
+
+/// Riwayat job terbaru dari ring buffer di memori (hilang saat restart)
+#[instrument(skip(state))]
+pub async fn admin_jobs(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin jobs request received");
+    
+    if !validate_admin_token(&state.read().await.settings, params.get("token").map(|s| s.as_str())) {
+        warn!("❌ Invalid or missing admin token for jobs");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    let printer_id = params.get("printer_id").map(|s| s.as_str()).filter(|s| !s.is_empty());
+    let limit = params.get("limit").and_then(|v| v.parse().ok()).unwrap_or(50);
+    let jobs = crate::jobs::recent(printer_id, limit);
+    
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "capacity": crate::jobs::capacity(),
+            "total": jobs.len(),
+            "jobs": jobs,
+        }))
+    ).into_response())
+}
+
+/// Counter job, pool, dan health cache sejak startup/reset terakhir
+#[instrument(skip(state))]
+pub async fn admin_stats(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin stats request received");
+    
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for stats");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    let mut stats = crate::stats::snapshot();
+    stats["success"] = json!(true);
+    stats["timestamp"] = json!(chrono::Utc::now().to_rfc3339());
+    Ok((StatusCode::OK, Json(stats)).into_response())
+}
+
+/// Setting efektif dari environment (default sudah diterapkan, secret disamarkan)
+#[instrument(skip(state))]
+pub async fn admin_runtime_config(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin runtime config request received");
+
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for runtime config");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "settings": &*state.read().await.settings,
+            "version": env!("CARGO_PKG_VERSION"),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })),
+    ).into_response())
+}
+
+/// Nolkan counter `/admin/stats`
+#[instrument(skip(state))]
+pub async fn admin_stats_reset(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin stats reset request received");
+    
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for stats reset");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    crate::stats::reset();
+    info!("🧮 Stats counters reset");
+    Ok((StatusCode::OK, Json(AdminResponse::success("Stats counters reset"))).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdhocPrintQuery {
+    token: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+/// Kirim job ke host:port sembarang tanpa menambah printer ke config (diagnostik).
+/// Body sama dengan print endpoint; koneksi tidak di-pool dan tidak ada health preflight.
+#[instrument(skip(state, headers, body))]
+pub async fn admin_print_adhoc(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<AdhocPrintQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin ad-hoc print request received ({} bytes)", body.len());
+
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for ad-hoc print");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+
+    let Some(host) = query.host.filter(|h| !h.trim().is_empty()) else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            Json(AdminResponse::error("Parameter 'host' is required"))
+        ).into_response());
+    };
+    let port = query.port.unwrap_or(9100);
+
+    let (mode, payload) = match crate::handlers::build_adhoc_payload(&headers, &body).await {
+        Ok(built) => built,
+        Err(e) => {
+            warn!("❌ Ad-hoc payload invalid: {}", e);
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(AdminResponse::error(format!("Invalid payload: {}", e)))
+            ).into_response());
+        }
+    };
+
+    let backend = Backend::Tcp9100 {
+        host: host.clone(),
+        port,
+        bind_addr: None,
+        tls: None,
+        tls_ca: None,
+        tls_skip_verify: None,
+    };
+    info!("🧪 Sending ad-hoc {} job ({} bytes) to {}:{}", mode, payload.len(), host, port);
+    match CONNECTION_MANAGER.test_write(&backend, &payload).await {
+        Ok(()) => {
+            info!("✅ Ad-hoc job sent to {}:{}", host, port);
+            Ok((
+                StatusCode::OK,
+                Json(AdminResponse::success(format!("Ad-hoc {} job ({} bytes) sent to {}:{}", mode, payload.len(), host, port)))
+            ).into_response())
+        }
+        Err(e) => {
+            warn!("❌ Ad-hoc job to {}:{} failed: {}", host, port, e);
+            Ok((
+                StatusCode::BAD_GATEWAY,
+                Json(AdminResponse::error(format!("Send to {}:{} failed: {}", host, port, e)))
+            ).into_response())
+        }
+    }
+}
+
+/// Kirim ulang dead letter ke printer aslinya; dihapus jika berhasil
+#[instrument(skip(state))]
+pub async fn admin_deadletter_retry(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Path(id): Path<String>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin dead letter retry request received for '{}'", id);
+    
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for dead letter retry");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    let Some(entry) = crate::deadletter::load(&id) else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(AdminResponse::error(format!("Dead letter '{}' not found", id)))
+        ).into_response());
+    };
+    
+    let printer = state.read().await.printers.get(&entry.printer_id).cloned();
+    let Some(printer) = printer else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(AdminResponse::error(format!("Printer '{}' no longer configured", entry.printer_id)))
+        ).into_response());
+    };
+    
+    let payload = match entry.payload() {
+        Ok(p) => p,
+        Err(e) => {
+            error!("❌ Dead letter '{}' has corrupt payload: {}", id, e);
+            return Ok((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(AdminResponse::error(format!("Dead letter payload corrupt: {}", e)))
+            ).into_response());
+        }
+    };
+    
+    match crate::queue::send_queued(&printer, &payload, crate::queue::Priority::Normal, crate::graphics::GraphicRefs::AsIs).await {
+        Ok(()) => {
+            info!("✅ Dead letter '{}' reprinted on '{}'", id, entry.printer_id);
+            crate::deadletter::remove(&id);
+            Ok((
+                StatusCode::OK,
+                Json(AdminResponse::success(format!("Dead letter '{}' sent to printer '{}'", id, entry.printer_id)))
+            ).into_response())
+        }
+        Err(e) => {
+            warn!("❌ Dead letter '{}' retry failed: {}", id, e);
+            Ok((
+                StatusCode::BAD_GATEWAY,
+                Json(AdminResponse::error(format!("Retry failed, dead letter kept: {}", e)))
+            ).into_response())
+        }
+    }
+}
+
 /// Admin status endpoint
 #[instrument(skip(state))]
 pub async fn admin_status(
@@ -224,7 +581,7 @@ pub async fn admin_status(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔒 Admin status request received");
     
-    if !validate_admin_token(query.token.as_deref()) {
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
         warn!("❌ Invalid or missing admin token for status");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -237,6 +594,8 @@ pub async fn admin_status(
         .unwrap_or_default()
         .as_secs();
     
+    let (pooled_connections, max_pooled_connections) = crate::pool::CONNECTION_MANAGER.pooled_stats();
+    
     let status = json!({
         "success": true,
         "timestamp": chrono::Utc::now().to_rfc3339(),
@@ -244,25 +603,88 @@ pub async fn admin_status(
             "name": "printer-proxy",
             "version": env!("CARGO_PKG_VERSION"),
             "uptime_seconds": uptime,
-            "printers_configured": state.read().await.printers.len()
+            "printers_configured": state.read().await.printers.len(),
+            "draining": is_draining()
         },
         "system": {
             "pid": std::process::id(),
             "memory_usage": get_memory_usage(),
         },
+        "pool": {
+            "pooled_connections": pooled_connections,
+            "max_total_pooled_connections": max_pooled_connections
+        },
         "endpoints": {
             "health": "/healthz",
             "printers_health": "/health/printers", 
             "admin_shutdown": "/admin/shutdown?token=TOKEN",
             "admin_restart": "/admin/restart?token=TOKEN",
             "admin_ssl_renew": "/admin/ssl/renew?token=TOKEN&domain=DOMAIN&port=PORT",
-            "admin_status": "/admin/status?token=TOKEN"
+            "admin_status": "/admin/status?token=TOKEN",
+            "admin_drain": "POST /admin/drain?token=TOKEN&enable=true|false",
+            "admin_deadletter": "/admin/deadletter?token=TOKEN",
+            "admin_deadletter_retry": "POST /admin/deadletter/{id}/retry?token=TOKEN",
+            "admin_jobs": "/admin/jobs?token=TOKEN&printer_id=ID&limit=N",
+            "admin_stats": "/admin/stats?token=TOKEN",
+            "admin_stats_reset": "POST /admin/stats/reset?token=TOKEN",
+            "admin_print_adhoc": "POST /admin/print/adhoc?token=TOKEN&host=HOST&port=PORT",
+            "admin_printer_trace": "/admin/printer/{id}/trace?token=TOKEN"
         }
     });
     
     Ok((StatusCode::OK, Json(status)).into_response())
 }
 
+/// Stream hex dump setiap payload yang ditulis ke printer selama client terhubung (SSE, event `payload`).
+/// Untuk debug mendalam, mis. melihat persis byte yang dihasilkan client.
+#[instrument(skip(state))]
+pub async fn admin_printer_trace(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Path(printer_id): Path<String>,
+    Query(query): Query<AdminQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔒 Admin trace request received for printer '{}'", printer_id);
+
+    if !validate_admin_token(&state.read().await.settings, query.token.as_deref()) {
+        warn!("❌ Invalid or missing admin token for trace");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminResponse::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+
+    let Some(printer_id) = state.read().await.printer(&printer_id).map(|p| p.id.clone()) else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(AdminResponse::error(format!("Printer '{}' not found", printer_id)))
+        ).into_response());
+    };
+
+    info!("🔍 Trace attached to printer '{}'", printer_id);
+    let rx = crate::trace::subscribe(&printer_id);
+    let events = stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("⚠️ Trace subscriber lagged, skipped {} payload(s)", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .map(|event| {
+        Ok::<_, Infallible>(Event::default()
+            .event("payload")
+            .json_data(&event)
+            .unwrap_or_else(|_| Event::default().comment("serialize error")))
+    });
+
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)).text("heartbeat"))
+        .into_response())
+}
+
 /// Get approximate memory usage (Linux only)
 fn get_memory_usage() -> String {
     #[cfg(target_os = "linux")]
@@ -0,0 +1,116 @@
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// Batas percobaan token admin yang salah (env `ADMIN_AUTH_*`)
+struct GuardConfig {
+    /// Jumlah token salah per IP sebelum dikunci (`ADMIN_AUTH_MAX_FAILURES`, default 5, 0 = nonaktif)
+    max_failures: u32,
+    /// Lama kunci sekaligus jendela penghitungan kegagalan (`ADMIN_AUTH_LOCKOUT_SECS`, default 300)
+    lockout: Duration,
+}
+
+static CONFIG: Lazy<GuardConfig> = Lazy::new(|| {
+    let s = crate::settings::settings();
+    GuardConfig {
+        max_failures: s.admin_auth_max_failures,
+        lockout: Duration::from_secs(s.admin_auth_lockout_secs),
+    }
+});
+
+/// Kegagalan token per IP client
+struct Failures {
+    count: u32,
+    first_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+static FAILURES: Lazy<DashMap<IpAddr, Failures>> = Lazy::new(DashMap::new);
+
+/// IP client; di belakang nginx lokal pakai `X-Real-IP` (lihat setup-ssl.sh), selain itu peer TCP
+fn client_ip(peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    if peer.ip().is_loopback() {
+        if let Some(ip) = headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+        {
+            return ip;
+        }
+    }
+    peer.ip()
+}
+
+/// Sisa waktu kunci untuk IP ini, `None` jika tidak dikunci
+fn locked_for(ip: &IpAddr) -> Option<Duration> {
+    let entry = FAILURES.get(ip)?;
+    let remaining = entry.locked_until?.checked_duration_since(Instant::now())?;
+    (!remaining.is_zero()).then_some(remaining)
+}
+
+fn record_failure(ip: IpAddr, path: &str) {
+    let cfg = &*CONFIG;
+    let now = Instant::now();
+    // Entry lama yang sudah lewat jendelanya dibuang supaya map tidak tumbuh terus
+    FAILURES.retain(|_, f| now.duration_since(f.first_at) < cfg.lockout || f.locked_until.is_some_and(|t| t > now));
+
+    let mut entry = FAILURES.entry(ip).or_insert(Failures { count: 0, first_at: now, locked_until: None });
+    if entry.locked_until.is_some_and(|t| t <= now) || now.duration_since(entry.first_at) >= cfg.lockout {
+        *entry = Failures { count: 0, first_at: now, locked_until: None };
+    }
+    entry.count += 1;
+    if entry.count >= cfg.max_failures {
+        entry.locked_until = Some(now + cfg.lockout);
+        warn!("🚫 Admin token salah {} kali dari {} (terakhir {}), dikunci {:?}", entry.count, ip, path, cfg.lockout);
+    } else if entry.count > 1 {
+        warn!("⚠️ Admin token salah {} kali dari {} ({})", entry.count, ip, path);
+    }
+}
+
+/// Middleware untuk endpoint admin/CRUD: IP yang terlalu sering mengirim token salah dijawab 429
+/// sampai masa kunci habis. Handler tetap memvalidasi token sendiri; guard hanya membaca status 401.
+pub async fn guard(ConnectInfo(peer): ConnectInfo<SocketAddr>, request: Request, next: Next) -> Response {
+    if CONFIG.max_failures == 0 {
+        return next.run(request).await;
+    }
+
+    let ip = client_ip(peer, request.headers());
+    let path = request.uri().path().to_string();
+    if let Some(remaining) = locked_for(&ip) {
+        warn!("🚫 Request {} dari {} ditolak, masih dikunci {}s", path, ip, remaining.as_secs() + 1);
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({
+                "success": false,
+                "message": "Too many invalid admin token attempts, try again later",
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            })),
+        )
+            .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(remaining.as_secs() + 1));
+        return response;
+    }
+
+    let had_token = request.uri().query().is_some_and(|q| q.split('&').any(|kv| kv.starts_with("token=")));
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        record_failure(ip, &path);
+    } else if had_token && response.status().is_success() && FAILURES.remove(&ip).is_some() {
+        info!("🔓 Admin token valid dari {}, counter kegagalan direset", ip);
+    }
+    response
+}
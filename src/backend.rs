@@ -1,8 +1,323 @@
-use crate::{config::Printer, errors::ProxyError, pool::CONNECTION_MANAGER};
-use tracing::{instrument};
+use crate::{config::Printer, errors::ProxyError, graphics::GraphicRefs, pool::{CONNECTION_MANAGER, HEALTH_CACHE}};
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use once_cell::sync::Lazy;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        pki_types::{CertificateDer, ServerName, UnixTime},
+        crypto::CryptoProvider,
+        ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme, SupportedProtocolVersion,
+    },
+    TlsConnector,
+};
+use tracing::{debug, info, instrument, warn};
 
 /// Send payload to printer backend using connection pool
 #[instrument(skip(payload), fields(payload_size = payload.len()))]
-pub async fn send_to_backend(printer: &Printer, payload: &[u8]) -> Result<(), ProxyError> {
-    CONNECTION_MANAGER.send_to_printer(printer, payload).await
+pub async fn send_to_backend(printer: &Printer, payload: &[u8], refs: GraphicRefs) -> Result<(), ProxyError> {
+    if !printer.verbose() {
+        return CONNECTION_MANAGER.send_to_printer(printer, payload, refs).await
+            .inspect_err(|_| HEALTH_CACHE.invalidate(printer));
+    }
+    info!("🔬 [{}] sending {} bytes to {:?}", printer.id, payload.len(), printer.backend);
+    let started = Instant::now();
+    let result = CONNECTION_MANAGER.send_to_printer(printer, payload, refs).await
+        .inspect_err(|_| HEALTH_CACHE.invalidate(printer));
+    info!("🔬 [{}] send finished in {:?}: {:?}", printer.id, started.elapsed(), result.as_ref().err());
+    result
+}
+
+/// Send payload within an interactive session, keeping the connection pinned until `end`
+#[instrument(skip(payload), fields(payload_size = payload.len()))]
+pub async fn send_to_backend_in_session(
+    printer: &Printer,
+    session_id: &str,
+    payload: &[u8],
+    end: bool,
+    refs: GraphicRefs,
+) -> Result<(), ProxyError> {
+    if printer.verbose() {
+        info!("🔬 [{}] session '{}' sending {} bytes (end: {})", printer.id, session_id, payload.len(), end);
+    }
+    CONNECTION_MANAGER.send_to_printer_in_session(printer, session_id, payload, end, refs).await
+        .inspect_err(|_| HEALTH_CACHE.invalidate(printer))
+}
+
+/// Entry DNS cache: waktu resolve dan alamat hasil resolve
+type DnsEntry = (Instant, Vec<SocketAddr>);
+
+/// Cache resolusi DNS host printer per (host, port)
+static DNS_CACHE: Lazy<DashMap<(String, u16), DnsEntry>> = Lazy::new(DashMap::new);
+
+/// DNS cache aktif kecuali `DNS_CACHE=false`; TTL dari `DNS_CACHE_TTL_SECS` (default 60)
+fn dns_cache_ttl() -> Option<Duration> {
+    let s = crate::settings::settings();
+    s.dns_cache.then(|| Duration::from_secs(s.dns_cache_ttl_secs))
+}
+
+async fn resolve_live(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+    if dns_cache_ttl().is_some() && !addrs.is_empty() {
+        DNS_CACHE.insert((host.to_string(), port), (Instant::now(), addrs.clone()));
+    }
+    Ok(addrs)
+}
+
+/// Lookup DNS yang sedang berjalan; error disimpan sebagai (kind, pesan) karena `io::Error` tidak Clone
+type Lookup = Shared<BoxFuture<'static, Result<Vec<SocketAddr>, (io::ErrorKind, String)>>>;
+
+/// Lookup yang sedang berjalan per (host, port): connect bersamaan ke host yang sama menunggu satu lookup
+static LOOKUPS: Lazy<DashMap<(String, u16), Lookup>> = Lazy::new(DashMap::new);
+
+/// Lookup bersama untuk host ini; `true` jika lookup baru dimulai oleh pemanggil ini
+fn shared_lookup(host: &str, port: u16) -> (Lookup, bool) {
+    let key = (host.to_string(), port);
+    match LOOKUPS.entry(key.clone()) {
+        Entry::Occupied(entry) => (entry.get().clone(), false),
+        Entry::Vacant(entry) => {
+            let lookup = async move {
+                let result = resolve_live(&key.0, key.1).await.map_err(|e| (e.kind(), e.to_string()));
+                LOOKUPS.remove(&key);
+                result
+            }
+            .boxed()
+            .shared();
+            entry.insert(lookup.clone());
+            (lookup, true)
+        }
+    }
+}
+
+/// Resolve host printer lewat cache. Entry yang sudah lewat TTL tetap dipakai
+/// sambil di-refresh di background, jadi DNS yang lambat/flapping tidak menahan connect.
+/// Refresh dan cache miss untuk host yang sama berbagi satu lookup.
+async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let Some(ttl) = dns_cache_ttl() else {
+        return resolve_live(host, port).await;
+    };
+
+    let cached = DNS_CACHE
+        .get(&(host.to_string(), port))
+        .map(|e| (e.0.elapsed() > ttl, e.1.clone()));
+    match cached {
+        Some((stale, addrs)) => {
+            if stale {
+                if let (lookup, true) = shared_lookup(host, port) {
+                    let host = host.to_string();
+                    debug!("🔄 Refreshing DNS cache for {}", host);
+                    tokio::spawn(async move {
+                        if let Err((_, e)) = lookup.await {
+                            warn!("⚠️ DNS refresh for {} failed, keeping cached addresses: {}", host, e);
+                        }
+                    });
+                }
+            }
+            Ok(addrs)
+        }
+        None => shared_lookup(host, port).0.await.map_err(|(kind, e)| io::Error::new(kind, e)),
+    }
+}
+
+/// Open TCP connection to printer, optionally bound to a specific local address
+pub async fn connect_tcp(host: &str, port: u16, bind_addr: Option<IpAddr>) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for remote in resolve(host, port).await? {
+        let result = match bind_addr {
+            None => TcpStream::connect(remote).await,
+            Some(local) => {
+                // Only try remote addresses from the same family as the bind address
+                if remote.is_ipv4() != local.is_ipv4() {
+                    continue;
+                }
+                let socket = if remote.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+                socket.bind(SocketAddr::new(local, 0))?;
+                debug!("🔗 Connecting {} -> {}", local, remote);
+                socket.connect(remote).await
+            }
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    // Semua alamat gagal: mungkin IP printer berubah, resolve ulang pada connect berikutnya
+    DNS_CACHE.remove(&(host.to_string(), port));
+
+    Err(last_err.unwrap_or_else(|| {
+        let detail = match bind_addr {
+            Some(local) => format!("No address for {}:{} matches bind address {}", host, port, local),
+            None => format!("No address for {}:{}", host, port),
+        };
+        io::Error::new(io::ErrorKind::AddrNotAvailable, detail)
+    }))
+}
+
+/// Kosongkan DNS cache, host di-resolve ulang pada connect berikutnya
+pub fn clear_dns_cache() {
+    DNS_CACHE.clear();
+}
+
+/// Key cache TLS config: (CA file, skip_verify)
+type TlsConfigKey = (Option<String>, bool);
+
+/// TLS client config per CA/verification setting, dibangun sekali lalu dipakai ulang
+static TLS_CONFIGS: Lazy<DashMap<TlsConfigKey, Arc<ClientConfig>>> = Lazy::new(DashMap::new);
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Kebijakan TLS koneksi ke printer dari env:
+/// `TLS_MIN_VERSION` ("1.2" default atau "1.3") dan `TLS_CIPHER_SUITES` (nama suite rustls dipisah koma,
+/// mis. `TLS13_AES_256_GCM_SHA384,TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384`; kosong = default rustls)
+pub fn tls_policy() -> io::Result<(CryptoProvider, &'static [&'static SupportedProtocolVersion])> {
+    let versions: &'static [&'static SupportedProtocolVersion] =
+        match crate::settings::settings().tls_min_version.as_str() {
+            "1.2" => rustls::DEFAULT_VERSIONS,
+            "1.3" => TLS13_ONLY,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("TLS_MIN_VERSION '{}' tidak didukung (1.2 atau 1.3)", other),
+                ))
+            }
+        };
+
+    let mut provider = rustls::crypto::ring::default_provider();
+    if let Some(list) = &crate::settings::settings().tls_cipher_suites {
+        let wanted: Vec<String> = list
+            .split(',')
+            .map(|s| s.trim().to_ascii_uppercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if let Some(unknown) = wanted
+            .iter()
+            .find(|w| !provider.cipher_suites.iter().any(|s| s.suite().as_str() == Some(w.as_str())))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("TLS_CIPHER_SUITES: cipher suite '{}' tidak dikenal", unknown),
+            ));
+        }
+        if !wanted.is_empty() {
+            provider
+                .cipher_suites
+                .retain(|s| s.suite().as_str().is_some_and(|name| wanted.iter().any(|w| w == name)));
+        }
+    }
+
+    // Gagal jika tidak ada cipher suite yang cocok dengan versi minimum (mis. 1.3 + suite TLS 1.2 saja)
+    ClientConfig::builder_with_provider(Arc::new(provider.clone()))
+        .with_protocol_versions(versions)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Kebijakan TLS tidak bisa dipenuhi: {}", e)))?;
+
+    Ok((provider, versions))
+}
+
+/// Build (atau ambil dari cache) TLS client config untuk koneksi ke printer
+pub fn tls_client_config(ca_file: Option<&str>, skip_verify: bool) -> io::Result<Arc<ClientConfig>> {
+    let key = (ca_file.map(str::to_string), skip_verify);
+    if let Some(config) = TLS_CONFIGS.get(&key) {
+        return Ok(config.clone());
+    }
+
+    let (provider, versions) = tls_policy()?;
+    let provider = Arc::new(provider);
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(versions)
+        .map_err(io::Error::other)?;
+
+    let config = if skip_verify {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        match ca_file {
+            Some(path) => {
+                let pem = std::fs::read(path)?;
+                for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                    roots.add(cert?).map_err(io::Error::other)?;
+                }
+                if roots.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No certificate found in {}", path)));
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+
+    let config = Arc::new(config);
+    TLS_CONFIGS.insert(key, config.clone());
+    Ok(config)
+}
+
+/// TLS handshake di atas koneksi TCP yang sudah terbuka
+pub async fn connect_tls(
+    stream: TcpStream,
+    host: &str,
+    ca_file: Option<&str>,
+    skip_verify: bool,
+) -> io::Result<TlsStream<TcpStream>> {
+    let config = tls_client_config(ca_file, skip_verify)?;
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if skip_verify {
+        warn!("⚠️ TLS certificate verification disabled for {}", host);
+    }
+    debug!("🔐 TLS handshake with {}", host);
+    TlsConnector::from(config).connect(server_name, stream).await
+}
+
+/// Verifier yang menerima sertifikat apa pun (`tls_skip_verify: true`); signature handshake tetap dicek
+#[derive(Debug)]
+struct NoVerification(Arc<rustls::crypto::CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
 }
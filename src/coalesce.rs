@@ -0,0 +1,134 @@
+use crate::{config::Printer, errors::ProxyError, graphics::GraphicRefs, queue::{send_queued, Priority}};
+use dashmap::{mapref::entry::Entry, DashMap};
+use once_cell::sync::Lazy;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, info};
+
+/// Job yang sedang dikumpulkan untuk satu printer
+struct Batch {
+    /// Penanda batch untuk timer flush-nya; batch yang sudah ditutup lebih awal tidak di-flush dua kali
+    seq: u64,
+    payload: Vec<u8>,
+    jobs: usize,
+    /// Batch hanya disisipi definisi graphic jika semua job di dalamnya dibangun dari ops
+    refs: GraphicRefs,
+    waiters: Vec<oneshot::Sender<Result<(), ProxyError>>>,
+}
+
+impl Batch {
+    fn open(payload: &[u8], refs: GraphicRefs, waiter: oneshot::Sender<Result<(), ProxyError>>) -> Self {
+        static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+        Batch {
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            payload: payload.to_vec(),
+            jobs: 1,
+            refs,
+            waiters: vec![waiter],
+        }
+    }
+
+    /// Job berikutnya tidak muat lagi (`COALESCE_MAX_BATCH_JOBS` / `COALESCE_MAX_BATCH_BYTES`)
+    fn is_full_for(&self, next: usize) -> bool {
+        let settings = crate::settings::settings();
+        self.jobs >= settings.max_batch_jobs || self.payload.len() + next > settings.max_batch_bytes
+    }
+}
+
+/// Batch terbuka per printer id
+static BATCHES: Lazy<DashMap<String, Batch>> = Lazy::new(DashMap::new);
+
+/// Satu flush per printer pada satu waktu, supaya batch berikutnya tidak menyalip
+/// batch sebelumnya lewat koneksi pool lain
+static FLUSH_PERMITS: Lazy<DashMap<String, Arc<Semaphore>>> = Lazy::new(DashMap::new);
+
+/// Tambahkan payload ke batch printer dan tunggu sampai batch ditulis ke printer.
+/// Job pertama membuka batch dan menjadwalkan flush setelah `window`; job berikutnya
+/// dalam jendela itu ditempel berurutan dan ikut menerima hasil tulis yang sama.
+/// Batch yang penuh langsung dikirim dan job ini membuka batch baru.
+pub async fn send_coalesced(printer: &Printer, payload: &[u8], window: Duration, refs: GraphicRefs) -> Result<(), ProxyError> {
+    let (tx, rx) = oneshot::channel();
+    let mut full = None;
+    let opened = match BATCHES.entry(printer.id.clone()) {
+        Entry::Occupied(mut entry) if entry.get().is_full_for(payload.len()) => {
+            let batch = Batch::open(payload, refs, tx);
+            let seq = batch.seq;
+            full = Some(entry.insert(batch));
+            Some(seq)
+        }
+        Entry::Occupied(mut entry) => {
+            let batch = entry.get_mut();
+            batch.payload.extend_from_slice(payload);
+            batch.jobs += 1;
+            if refs == GraphicRefs::AsIs {
+                batch.refs = GraphicRefs::AsIs;
+            }
+            batch.waiters.push(tx);
+            debug!("🧺 Job appended to batch for '{}' ({} jobs)", printer.id, batch.jobs);
+            None
+        }
+        Entry::Vacant(entry) => {
+            let batch = Batch::open(payload, refs, tx);
+            let seq = batch.seq;
+            entry.insert(batch);
+            Some(seq)
+        }
+    };
+
+    let previous = full.map(|batch| {
+        info!("🧺 Batch for '{}' full ({} jobs, {} bytes), flushing early", printer.id, batch.jobs, batch.payload.len());
+        let printer = printer.clone();
+        tokio::spawn(async move {
+            let permit = flush_permit(&printer.id).await;
+            write_batch(&printer, batch, permit).await;
+        })
+    });
+    if let Some(seq) = opened {
+        // Flush di task terpisah supaya batch tetap terkirim walau request pembukanya dibatalkan
+        let printer = printer.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            // Batch penuh sebelumnya harus sudah ditulis sebelum batch ini diambil
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            flush(&printer, seq).await;
+        });
+    }
+
+    rx.await.unwrap_or(Err(ProxyError::Internal))
+}
+
+async fn flush_permit(printer_id: &str) -> OwnedSemaphorePermit {
+    let permits = FLUSH_PERMITS
+        .entry(printer_id.to_string())
+        .or_insert_with(|| Arc::new(Semaphore::new(1)))
+        .clone();
+    permits.acquire_owned().await.expect("flush semaphore tidak pernah ditutup")
+}
+
+async fn flush(printer: &Printer, seq: u64) {
+    // Batch diambil setelah permit didapat: selama flush sebelumnya menulis, batch ini tetap
+    // terbuka dan job baru ikut ditempel di belakangnya
+    let permit = flush_permit(&printer.id).await;
+    let Some((_, batch)) = BATCHES.remove_if(&printer.id, |_, b| b.seq == seq) else {
+        return;
+    };
+    write_batch(printer, batch, permit).await;
+}
+
+async fn write_batch(printer: &Printer, batch: Batch, _permit: OwnedSemaphorePermit) {
+    if batch.jobs > 1 {
+        info!("🧺 Coalesced {} jobs ({} bytes) for printer '{}'", batch.jobs, batch.payload.len(), printer.id);
+    }
+    let result = send_queued(printer, &batch.payload, Priority::Normal, batch.refs).await;
+    for waiter in batch.waiters {
+        let _ = waiter.send(result.clone());
+    }
+}
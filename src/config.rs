@@ -1,6 +1,7 @@
+use crate::escpos::{BitOrder, CjkEncoding, PrintOp};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
-use tracing::{info, debug, instrument};
+use std::{collections::{BTreeMap, HashMap}, net::IpAddr, path::{Path, PathBuf}};
+use tracing::{info, debug, instrument, warn};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
@@ -12,39 +13,546 @@ pub struct Printer {
     #[allow(dead_code)]
     pub name: String,
     pub id: String,
+    /// Nama alternatif untuk `:printer_id` di URL (mis. "kitchen"); id tetap immutable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Metadata bebas untuk mengelompokkan printer (mis. store=jakarta, floor=2); tidak memengaruhi cetak
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
     pub backend: Backend,
+    #[serde(flatten)]
+    pub options: PrinterOptions,
+}
+
+/// Opsi tambahan per printer (semua field opsional, di YAML ditulis sejajar dengan `id`/`backend`)
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PrinterOptions {
+    /// Printer dipasang terbalik: aktifkan upside-down mode (ESC { 1) dan putar gambar 180°
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate_180: Option<bool>,
+    /// Model/keluarga printer untuk memilih perintah vendor ("epson" default, "dc2")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Jumlah koneksi yang dibuka ke pool saat startup (override `POOL_PREWARM`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_prewarm: Option<usize>,
+    /// Format response sukses print endpoint (override `RESPONSE_FORMAT`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Simpan job terakhir yang berhasil di memori untuk reprint (opt-in, default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retain_last_job: Option<bool>,
+    /// Mode payload yang diterima printer ini ("epos", "raw", "json", "multipart"); default semua
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_modes: Option<Vec<String>>,
+    /// Resolusi printer dalam dpi untuk estimasi waktu cetak (default 203)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<u32>,
+    /// Kecepatan cetak dalam mm/detik untuk estimasi waktu cetak (default 150)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub print_speed_mm_s: Option<u32>,
+    /// Ops yang dicetak di awal setiap job (nama toko, id terminal, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_ops: Option<Vec<PrintOp>>,
+    /// Ops yang dicetak di akhir setiap job, sebelum cut
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer_ops: Option<Vec<PrintOp>>,
+    /// Aktifkan ASB (GS a) dan baca status setelah job untuk mendeteksi paper out/cover open di tengah cetak
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor_asb: Option<bool>,
+    /// Program eksternal yang menerima ESC/POS di stdin dan mengembalikan payload final di stdout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform_command: Option<String>,
+    /// Program yang dijalankan sebelum job dikirim (mis. membangunkan printer); gagal = job dibatalkan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_hook: Option<String>,
+    /// Program yang dijalankan setelah job berhasil dikirim (mis. notifikasi POS); gagal hanya dicatat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_hook: Option<String>,
+    /// Verbositas log khusus printer ini ("info" default, "debug", "trace"), terlepas dari `RUST_LOG`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    /// Gabungkan job yang datang dalam jendela ini (ms) menjadi satu tulis ke printer; default nonaktif
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coalesce_ms: Option<u64>,
+    /// Printer satu-socket: koneksi ditolak dianggap "sibuk" dan connect diulang sebanyak ini (default 0 = offline)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busy_retries: Option<u32>,
+    /// Jeda antar percobaan connect saat printer sibuk, dalam ms (default 250)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busy_retry_ms: Option<u64>,
+    /// Batas waktu mengambil koneksi dari pool (termasuk connect dan retry sibuk), dalam ms (default 5000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acquire_timeout_ms: Option<u64>,
+    /// Printer menjawab GS I: health per printer menampilkan model/firmware (default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identify: Option<bool>,
+    /// Invert bitmap ePOS untuk firmware yang warnanya terbalik; override `?invert=` per request menang
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_invert: Option<bool>,
+    /// Urutan bit bitmap ePOS ("msb_first"/"lsb_first"); override `?bit=` per request menang
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_bit_order: Option<BitOrder>,
+    /// Encoding multibyte printer CJK ("shift_jis", "gbk", "big5", "euc_kr"): teks op `text` non-ASCII
+    /// dikirim dalam kanji mode (FS &) dengan encoding ini
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cjk_encoding: Option<CjkEncoding>,
+    /// Feed + cut otomatis di akhir job ePOS yang tidak punya `<cut>` (default true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_cut: Option<bool>,
+    /// Lebar baris dalam karakter untuk wrap teks op `text` (default tanpa wrap)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chars_per_line: Option<u16>,
+    /// Printer offline: jawab sukses dan kirim job di background saat printer kembali (default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub async_ok: Option<bool>,
+    /// Tolak bitmap `<image>` ePOS yang ukurannya tidak sama dengan width x height (default false: pad/potong)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_image: Option<bool>,
+    /// Kirim DLE EOT 1 di akhir job dan tunggu jawaban printer sebagai bukti job diterima (default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_delivery: Option<bool>,
+    /// Batas waktu menunggu jawaban konfirmasi, dalam ms (default 2000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_timeout_ms: Option<u64>,
+    /// Kirim ulang job sebanyak ini di koneksi baru jika tulis/connect gagal sebelum konfirmasi (default 0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_retries: Option<u32>,
+    /// `confirm_retries` juga berlaku untuk job yang tertulis tapi tidak terkonfirmasi; bisa mencetak ganda (default false)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_retry_unconfirmed: Option<bool>,
+    /// Printer cadangan yang dipakai saat printer ini offline (satu lompatan, tidak berantai)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_printer_id: Option<String>,
+}
+
+impl PrinterOptions {
+    /// Opsi yang menjalankan program eksternal; hanya boleh diatur lewat file config
+    pub fn external_commands(&self) -> [(&'static str, &Option<String>); 3] {
+        [
+            ("transform_command", &self.transform_command),
+            ("pre_hook", &self.pre_hook),
+            ("post_hook", &self.post_hook),
+        ]
+    }
+
+    /// Timpa field yang di-set pada `other` (untuk partial update)
+    pub fn merge(&mut self, other: PrinterOptions) {
+        if other.rotate_180.is_some() {
+            self.rotate_180 = other.rotate_180;
+        }
+        if other.model.is_some() {
+            self.model = other.model;
+        }
+        if other.pool_prewarm.is_some() {
+            self.pool_prewarm = other.pool_prewarm;
+        }
+        if other.response_format.is_some() {
+            self.response_format = other.response_format;
+        }
+        if other.retain_last_job.is_some() {
+            self.retain_last_job = other.retain_last_job;
+        }
+        if other.allowed_modes.is_some() {
+            self.allowed_modes = other.allowed_modes;
+        }
+        if other.dpi.is_some() {
+            self.dpi = other.dpi;
+        }
+        if other.print_speed_mm_s.is_some() {
+            self.print_speed_mm_s = other.print_speed_mm_s;
+        }
+        if other.header_ops.is_some() {
+            self.header_ops = other.header_ops;
+        }
+        if other.footer_ops.is_some() {
+            self.footer_ops = other.footer_ops;
+        }
+        if other.monitor_asb.is_some() {
+            self.monitor_asb = other.monitor_asb;
+        }
+        if other.transform_command.is_some() {
+            self.transform_command = other.transform_command;
+        }
+        if other.pre_hook.is_some() {
+            self.pre_hook = other.pre_hook;
+        }
+        if other.post_hook.is_some() {
+            self.post_hook = other.post_hook;
+        }
+        if other.log_level.is_some() {
+            self.log_level = other.log_level;
+        }
+        if other.coalesce_ms.is_some() {
+            self.coalesce_ms = other.coalesce_ms;
+        }
+        if other.busy_retries.is_some() {
+            self.busy_retries = other.busy_retries;
+        }
+        if other.busy_retry_ms.is_some() {
+            self.busy_retry_ms = other.busy_retry_ms;
+        }
+        if other.acquire_timeout_ms.is_some() {
+            self.acquire_timeout_ms = other.acquire_timeout_ms;
+        }
+        if other.identify.is_some() {
+            self.identify = other.identify;
+        }
+        if other.default_invert.is_some() {
+            self.default_invert = other.default_invert;
+        }
+        if other.default_bit_order.is_some() {
+            self.default_bit_order = other.default_bit_order;
+        }
+        if other.cjk_encoding.is_some() {
+            self.cjk_encoding = other.cjk_encoding;
+        }
+        if other.auto_cut.is_some() {
+            self.auto_cut = other.auto_cut;
+        }
+        if other.chars_per_line.is_some() {
+            self.chars_per_line = other.chars_per_line;
+        }
+        if other.async_ok.is_some() {
+            self.async_ok = other.async_ok;
+        }
+        if other.strict_image.is_some() {
+            self.strict_image = other.strict_image;
+        }
+        if other.confirm_delivery.is_some() {
+            self.confirm_delivery = other.confirm_delivery;
+        }
+        if other.confirm_timeout_ms.is_some() {
+            self.confirm_timeout_ms = other.confirm_timeout_ms;
+        }
+        if other.confirm_retries.is_some() {
+            self.confirm_retries = other.confirm_retries;
+        }
+        if other.confirm_retry_unconfirmed.is_some() {
+            self.confirm_retry_unconfirmed = other.confirm_retry_unconfirmed;
+        }
+        if other.fallback_printer_id.is_some() {
+            self.fallback_printer_id = other.fallback_printer_id;
+        }
+    }
+}
+
+impl Printer {
+    /// Cocokkan semua pasangan `key=value` filter (AND)
+    pub fn has_tags(&self, filter: &[(&str, &str)]) -> bool {
+        filter.iter().all(|(k, v)| self.tags.get(*k).is_some_and(|t| t == v))
+    }
+
+    /// Jumlah retry dan jeda saat printer sibuk (koneksi ditolak); `None` = fitur nonaktif
+    pub fn busy_retry(&self) -> Option<(u32, std::time::Duration)> {
+        let retries = self.options.busy_retries.filter(|n| *n > 0)?;
+        let delay = std::time::Duration::from_millis(self.options.busy_retry_ms.unwrap_or(250));
+        Some((retries, delay))
+    }
+
+    /// Batas waktu mengambil koneksi; default 5 detik, diperpanjang supaya retry sibuk sempat habis
+    pub fn acquire_timeout(&self) -> std::time::Duration {
+        if let Some(ms) = self.options.acquire_timeout_ms {
+            return std::time::Duration::from_millis(ms);
+        }
+        let default = std::time::Duration::from_secs(5);
+        match self.busy_retry() {
+            Some((retries, delay)) => default.max(delay * retries + std::time::Duration::from_secs(1)),
+            None => default,
+        }
+    }
+
+    /// `log_level` printer ini debug/trace: event tambahan dicatat di level info supaya lolos filter global
+    pub fn verbose(&self) -> bool {
+        self.options
+            .log_level
+            .as_deref()
+            .is_some_and(|l| matches!(l.to_ascii_lowercase().as_str(), "debug" | "trace"))
+    }
+}
+
+/// Format body response sukses print endpoint
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// `<response success="true" code="0"/>` kompatibel Epson ePOS
+    #[default]
+    #[serde(rename = "epos-xml")]
+    EposXml,
+    /// JSON dengan job id
+    #[serde(rename = "json")]
+    Json,
+    /// 200 tanpa body
+    #[serde(rename = "empty-200")]
+    Empty200,
+}
+
+impl ResponseFormat {
+    /// Format untuk printer: opsi per printer, lalu env `RESPONSE_FORMAT`, lalu `epos-xml`
+    pub fn for_printer(printer: &Printer) -> Self {
+        printer.options.response_format.unwrap_or(crate::settings::settings().response_format)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum Backend {
     #[serde(rename = "tcp9100")]
-    Tcp9100 { host: String, port: u16 },
+    Tcp9100 {
+        host: String,
+        port: u16,
+        /// Alamat lokal sumber koneksi (untuk host multi-homed / VLAN printer terpisah)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bind_addr: Option<IpAddr>,
+        /// Bungkus koneksi raw dengan TLS (printer yang menerima job lewat port TLS)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls: Option<bool>,
+        /// File PEM CA untuk verifikasi sertifikat printer (default: webpki root CAs)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls_ca: Option<String>,
+        /// Lewati verifikasi sertifikat (hanya untuk printer dengan self-signed cert di jaringan tepercaya)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tls_skip_verify: Option<bool>,
+    },
     #[serde(rename = "usb")]
-    Usb { device: String, baud_rate: Option<u32> },
+    Usb {
+        device: String,
+        baud_rate: Option<u32>,
+        /// Data bits 5..8 (default 8)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data_bits: Option<u8>,
+        /// "none" (default), "odd", "even"
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parity: Option<String>,
+        /// 1 (default) atau 2
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stop_bits: Option<u8>,
+        /// "none" (default), "software" (XON/XOFF), "hardware" (RTS/CTS)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        flow_control: Option<String>,
+    },
+}
+
+/// Builder port serial untuk backend USB, lengkap dengan setting line (data bits, parity, stop bits, flow control)
+pub fn serial_builder(backend: &Backend) -> Result<tokio_serial::SerialPortBuilder, String> {
+    use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+
+    let Backend::Usb { device, baud_rate, data_bits, parity, stop_bits, flow_control } = backend else {
+        return Err("bukan backend USB/serial".to_string());
+    };
+    let data_bits = match data_bits.unwrap_or(8) {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        8 => DataBits::Eight,
+        n => return Err(format!("data_bits {} tidak valid (5-8)", n)),
+    };
+    let parity = match parity.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("none") => Parity::None,
+        Some("odd") => Parity::Odd,
+        Some("even") => Parity::Even,
+        Some(p) => return Err(format!("parity '{}' tidak valid (none|odd|even)", p)),
+    };
+    let stop_bits = match stop_bits.unwrap_or(1) {
+        1 => StopBits::One,
+        2 => StopBits::Two,
+        n => return Err(format!("stop_bits {} tidak valid (1|2)", n)),
+    };
+    let flow_control = match flow_control.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("none") => FlowControl::None,
+        Some("software" | "xonxoff") => FlowControl::Software,
+        Some("hardware" | "rtscts") => FlowControl::Hardware,
+        Some(f) => return Err(format!("flow_control '{}' tidak valid (none|software|hardware)", f)),
+    };
+    // 5 data bits + 2 stop bits tidak didukung UART (jadi 1.5 stop bit)
+    if data_bits == DataBits::Five && stop_bits == StopBits::Two {
+        return Err("data_bits 5 dengan stop_bits 2 tidak didukung".to_string());
+    }
+
+    Ok(tokio_serial::new(device, baud_rate.unwrap_or(9600)) // Default baud rate for ESC/POS
+        .data_bits(data_bits)
+        .parity(parity)
+        .stop_bits(stop_bits)
+        .flow_control(flow_control))
+}
+
+/// Format file config, ditentukan dari ekstensi file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// `.json` / `.toml`; selain itu (`.yaml`, `.yml`, tanpa ekstensi) dianggap YAML
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Toml => "TOML",
+        }
+    }
+
+    pub fn parse(self, bytes: &[u8]) -> anyhow::Result<Config> {
+        Ok(match self {
+            ConfigFormat::Yaml => serde_yaml::from_slice(bytes)?,
+            ConfigFormat::Json => serde_json::from_slice(bytes)?,
+            ConfigFormat::Toml => toml::from_str(std::str::from_utf8(bytes)?)?,
+        })
+    }
+
+    pub fn serialize(self, config: &Config) -> anyhow::Result<String> {
+        Ok(match self {
+            ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(config)? + "\n",
+            ConfigFormat::Toml => toml::to_string(config)?,
+        })
+    }
+}
+
+/// Config inline dari env (`PRINTERS_CONFIG_JSON` atau `PRINTERS_CONFIG_YAML`)
+fn inline_config_env() -> Option<(&'static str, String)> {
+    ["PRINTERS_CONFIG_JSON", "PRINTERS_CONFIG_YAML"]
+        .into_iter()
+        .find_map(|key| std::env::var(key).ok().filter(|v| !v.trim().is_empty()).map(|v| (key, v)))
+}
+
+/// Config dikelola lewat env: file tidak ada tapi config inline tersedia (read-only)
+pub fn is_env_managed(path: &str) -> bool {
+    !PathBuf::from(path).exists() && inline_config_env().is_some()
+}
+
+/// Parse config inline dari env
+fn load_inline_config() -> anyhow::Result<Config> {
+    let (key, content) = inline_config_env()
+        .ok_or_else(|| anyhow::anyhow!("PRINTERS_CONFIG_JSON/PRINTERS_CONFIG_YAML tidak di-set"))?;
+    debug!("📦 Reading inline config from {}", key);
+    // YAML adalah superset JSON, jadi satu parser cukup untuk kedua env
+    let cfg: Config = serde_yaml::from_str(&content)?;
+    info!("✅ Successfully parsed inline config from {} with {} printer(s)", key, cfg.printers.len());
+    Ok(cfg)
 }
 
 #[instrument]
 pub fn load_config(path: &str) -> anyhow::Result<Config> {
-    debug!("📂 Reading config file: {}", path);
+    if is_env_managed(path) {
+        info!("🌱 Config file {} tidak ada, memakai config dari environment", path);
+        return load_inline_config();
+    }
+
+    let format = ConfigFormat::from_path(path);
+    debug!("📂 Reading config file: {} ({})", path, format.name());
     let p = PathBuf::from(path);
     let bytes = std::fs::read(&p)?;
     debug!("📊 Config file size: {} bytes", bytes.len());
     
-    let cfg: Config = serde_yaml::from_slice(&bytes)?;
-    info!("✅ Successfully parsed config with {} printer(s)", cfg.printers.len());
+    let cfg = format.parse(&bytes)?;
+    info!("✅ Successfully parsed {} config with {} printer(s)", format.name(), cfg.printers.len());
     
     Ok(cfg)
 }
 
+/// Alias harus unik di semua printer dan tidak boleh sama dengan id printer lain
+pub fn validate_aliases(config: &Config) -> anyhow::Result<()> {
+    let mut seen: HashMap<&str, &str> = config.printers.iter().map(|p| (p.id.as_str(), p.id.as_str())).collect();
+    for p in &config.printers {
+        for alias in &p.aliases {
+            if alias.is_empty() {
+                anyhow::bail!("Alias kosong untuk printer '{}'", p.id);
+            }
+            if let Some(owner) = seen.insert(alias.as_str(), p.id.as_str()) {
+                anyhow::bail!("Alias '{}' untuk printer '{}' bentrok dengan printer '{}'", alias, p.id, owner);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validasi config sebelum dipakai (dijalankan saat startup)
+#[instrument(skip(config))]
+pub fn validate_config(config: &Config) -> anyhow::Result<()> {
+    validate_aliases(config)?;
+    if let Err(e) = crate::backend::tls_policy() {
+        let uses_tls = config
+            .printers
+            .iter()
+            .any(|p| matches!(p.backend, Backend::Tcp9100 { tls: Some(true), .. }));
+        if uses_tls {
+            anyhow::bail!("Konfigurasi TLS tidak valid: {}", e);
+        }
+        warn!("⚠️ Konfigurasi TLS tidak valid ({}), diabaikan karena tidak ada printer TLS", e);
+    }
+    for p in &config.printers {
+        if let Backend::Tcp9100 { bind_addr: Some(ip), .. } = &p.backend {
+            // Bind ke port 0 hanya berhasil jika IP tersebut milik host ini
+            std::net::TcpListener::bind((*ip, 0)).map_err(|e| {
+                anyhow::anyhow!("bind_addr {} untuk printer '{}' bukan alamat lokal: {}", ip, p.id, e)
+            })?;
+            debug!("🔗 Printer '{}' akan connect dari {}", p.id, ip);
+        }
+        if let Some(modes) = &p.options.allowed_modes {
+            if let Some(bad) = modes.iter().find(|m| !matches!(m.to_ascii_lowercase().as_str(), "epos" | "raw" | "json" | "multipart")) {
+                anyhow::bail!("allowed_modes '{}' untuk printer '{}' tidak dikenal (epos|raw|json|multipart)", bad, p.id);
+            }
+        }
+        if let Backend::Tcp9100 { tls: Some(true), tls_ca, tls_skip_verify, .. } = &p.backend {
+            crate::backend::tls_client_config(tls_ca.as_deref(), tls_skip_verify.unwrap_or(false)).map_err(|e| {
+                anyhow::anyhow!("TLS config untuk printer '{}' tidak valid: {}", p.id, e)
+            })?;
+            debug!("🔐 Printer '{}' memakai TLS", p.id);
+        }
+        if let Backend::Usb { .. } = &p.backend {
+            serial_builder(&p.backend)
+                .map_err(|e| anyhow::anyhow!("Setting serial printer '{}' tidak valid: {}", p.id, e))?;
+        }
+        if let Some(fallback) = &p.options.fallback_printer_id {
+            let target = config.printers.iter().find(|f| f.id == *fallback || f.aliases.contains(fallback));
+            match target {
+                None => anyhow::bail!("fallback_printer_id '{}' untuk printer '{}' tidak ditemukan", fallback, p.id),
+                Some(f) if f.id == p.id => anyhow::bail!("Printer '{}' tidak bisa menjadi fallback dirinya sendiri", p.id),
+                Some(_) => {}
+            }
+        }
+        if let Some(cpl) = p.options.chars_per_line {
+            if !(8..=255).contains(&cpl) {
+                anyhow::bail!("chars_per_line {} untuk printer '{}' di luar rentang 8-255", cpl, p.id);
+            }
+        }
+        if let Some(level) = &p.options.log_level {
+            if !matches!(level.to_ascii_lowercase().as_str(), "info" | "debug" | "trace") {
+                anyhow::bail!("log_level '{}' untuk printer '{}' tidak dikenal (info|debug|trace)", level, p.id);
+            }
+        }
+        if p.options.dpi == Some(0) || p.options.print_speed_mm_s == Some(0) {
+            anyhow::bail!("dpi/print_speed_mm_s untuk printer '{}' harus lebih dari 0", p.id);
+        }
+    }
+    Ok(())
+}
+
+/// Map id -> printer dan map alias -> id
 #[instrument(skip(config))]
-pub fn build_printers_map(config: Config) -> HashMap<String, Printer> {
+pub fn build_printers_map(config: Config) -> (HashMap<String, Printer>, HashMap<String, String>) {
     let printer_count = config.printers.len();
+    let mut aliases = HashMap::new();
     let map = config.printers.into_iter().map(|p| {
         debug!("📋 Mapping printer: {} -> {}", p.id, p.name);
+        for alias in &p.aliases {
+            debug!("🏷️  Alias '{}' -> {}", alias, p.id);
+            aliases.insert(alias.clone(), p.id.clone());
+        }
         (p.id.clone(), p)
-    }).collect();
+    }).collect::<HashMap<_, _>>();
+    crate::pool::warn_shared_pools(map.values());
     
-    info!("🗺️  Built printer map with {} entries", printer_count);
-    map
+    info!("🗺️  Built printer map with {} entries ({} aliases)", printer_count, aliases.len());
+    (map, aliases)
 }
@@ -0,0 +1,180 @@
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::PathBuf,
+};
+use tracing::{error, info, warn};
+
+/// Job yang gagal dikirim ke printer, disimpan supaya operator bisa mencetak ulang
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: String,
+    pub printer_id: String,
+    pub timestamp: String,
+    pub error: String,
+    pub bytes_len: usize,
+    /// ESC/POS bytes yang gagal dikirim (base64)
+    pub payload_base64: String,
+    /// "gzip" jika payload dikompres sebelum di-base64 (`DEAD_LETTER_COMPRESS`); kosong = mentah
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+}
+
+/// Ringkasan dead letter untuk listing (tanpa payload)
+#[derive(Debug, Serialize)]
+pub struct DeadLetterSummary {
+    pub id: String,
+    pub printer_id: String,
+    pub timestamp: String,
+    pub error: String,
+    pub bytes_len: usize,
+}
+
+impl DeadLetter {
+    pub fn payload(&self) -> std::io::Result<Vec<u8>> {
+        let stored = BASE64_STANDARD.decode(&self.payload_base64).map_err(std::io::Error::other)?;
+        match self.compression.as_deref() {
+            None => Ok(stored),
+            Some("gzip") => {
+                let mut payload = Vec::with_capacity(self.bytes_len);
+                GzDecoder::new(stored.as_slice()).read_to_end(&mut payload)?;
+                Ok(payload)
+            }
+            Some(other) => Err(std::io::Error::other(format!("unknown compression '{}'", other))),
+        }
+    }
+
+    fn summary(&self) -> DeadLetterSummary {
+        DeadLetterSummary {
+            id: self.id.clone(),
+            printer_id: self.printer_id.clone(),
+            timestamp: self.timestamp.clone(),
+            error: self.error.clone(),
+            bytes_len: self.bytes_len,
+        }
+    }
+}
+
+/// Direktori dead letter (env `DEAD_LETTER_DIR`, default `dead_letter`)
+fn dead_letter_dir() -> PathBuf {
+    PathBuf::from(&crate::settings::settings().dead_letter_dir)
+}
+
+/// Kompres payload dead letter dengan gzip (env `DEAD_LETTER_COMPRESS`, default false)
+fn compress_enabled() -> bool {
+    crate::settings::settings().dead_letter_compress
+}
+
+fn gzip(payload: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(payload)?;
+    encoder.finish()
+}
+
+/// ID hanya boleh berisi karakter aman supaya tidak bisa keluar dari direktori dead letter
+fn entry_path(id: &str) -> Option<PathBuf> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return None;
+    }
+    Some(dead_letter_dir().join(format!("{}.json", id)))
+}
+
+/// Simpan job yang gagal permanen ke direktori dead letter
+pub fn record(printer_id: &str, err: &str, payload: &[u8]) {
+    let now = chrono::Utc::now();
+    let base = format!("{}-{}", now.format("%Y%m%d%H%M%S%3f"), sanitize(printer_id));
+    let dir = dead_letter_dir();
+    // Beberapa job bisa gagal pada milidetik yang sama (mis. job async_ok saat shutdown): beri suffix
+    // supaya tidak saling menimpa
+    let file = fs::create_dir_all(&dir).and_then(|_| create_entry_file(&dir, &base));
+    let id = file.as_ref().map(|(id, _)| id.clone()).unwrap_or(base);
+    // Gagal kompres bukan alasan kehilangan job: simpan mentah saja
+    let compressed = compress_enabled()
+        .then(|| gzip(payload).inspect_err(|e| warn!("⚠️ Failed to compress dead letter '{}', storing raw: {}", id, e)).ok())
+        .flatten();
+    let entry = DeadLetter {
+        id: id.clone(),
+        printer_id: printer_id.to_string(),
+        timestamp: now.to_rfc3339(),
+        error: err.to_string(),
+        bytes_len: payload.len(),
+        payload_base64: BASE64_STANDARD.encode(compressed.as_deref().unwrap_or(payload)),
+        compression: compressed.is_some().then(|| "gzip".to_string()),
+    };
+
+    error!("💀 Job for printer '{}' failed permanently ({} bytes): {} - saved as dead letter '{}'", printer_id, payload.len(), err, id);
+
+    let result = file.and_then(|(_, mut f)| {
+        let json = serde_json::to_vec_pretty(&entry).map_err(std::io::Error::other)?;
+        f.write_all(&json)
+    });
+
+    if let Err(e) = result {
+        error!("❌ Failed to write dead letter '{}' to {}: {}", id, dir.display(), e);
+    }
+}
+
+/// Buat file dead letter baru tanpa menimpa yang sudah ada (`<base>`, `<base>-2`, ...)
+fn create_entry_file(dir: &std::path::Path, base: &str) -> std::io::Result<(String, fs::File)> {
+    let mut n = 1;
+    loop {
+        let id = if n == 1 { base.to_string() } else { format!("{}-{}", base, n) };
+        match fs::OpenOptions::new().write(true).create_new(true).open(dir.join(format!("{}.json", id))) {
+            Ok(f) => return Ok((id, f)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Daftar dead letter, terbaru dulu
+pub fn list() -> std::io::Result<Vec<DeadLetterSummary>> {
+    let dir = dead_letter_dir();
+    let mut entries = Vec::new();
+
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+
+    for file in read_dir {
+        let path = file?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        match fs::read(&path).ok().and_then(|b| serde_json::from_slice::<DeadLetter>(&b).ok()) {
+            Some(entry) => entries.push(entry.summary()),
+            None => warn!("⚠️ Skipping unreadable dead letter file: {}", path.display()),
+        }
+    }
+
+    entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(entries)
+}
+
+/// Baca satu dead letter berdasarkan ID
+pub fn load(id: &str) -> Option<DeadLetter> {
+    let path = entry_path(id)?;
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Hapus dead letter setelah berhasil dicetak ulang
+pub fn remove(id: &str) {
+    if let Some(path) = entry_path(id) {
+        match fs::remove_file(&path) {
+            Ok(()) => info!("🗑️ Dead letter '{}' removed", id),
+            Err(e) => warn!("⚠️ Failed to remove dead letter '{}': {}", id, e),
+        }
+    }
+}
+
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
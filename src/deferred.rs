@@ -0,0 +1,224 @@
+use crate::{
+    admin::InFlightJob,
+    config::Printer,
+    errors::ProxyError,
+    graphics::GraphicRefs,
+    handlers::AppState,
+    queue::{send_queued, Priority},
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{sync::RwLock, time::Instant};
+use tracing::{debug, error, info, warn};
+
+/// Dipanggil sekali setelah job async_ok berhasil ditulis (reprint, shadow, riwayat, post_hook)
+pub type OnDelivered = Box<dyn FnOnce(&Printer, &[u8]) + Send>;
+
+/// Job async_ok yang menunggu printer kembali online
+pub struct DeferredJob {
+    pub job_id: String,
+    pub mode: &'static str,
+    pub payload: Vec<u8>,
+    pub priority: Priority,
+    pub refs: GraphicRefs,
+    pub on_delivered: OnDelivered,
+}
+
+struct Pending {
+    job: DeferredJob,
+    deadline: Instant,
+    /// Sedang ditulis ke printer: dilewati saat shutdown supaya tidak tercetak dua kali lewat dead letter
+    sending: bool,
+    /// Job tertunda tetap dihitung sebagai job yang berjalan (drain/shutdown)
+    _in_flight: InFlightJob,
+}
+
+/// Antrean FIFO satu printer; satu worker per antrean mencoba job terdepan lewat `send_queued`.
+/// Printer dibaca ulang dari state sebelum setiap percobaan, jadi perubahan config ikut terpakai.
+struct DeferredQueue {
+    printer_id: String,
+    state: Arc<RwLock<AppState>>,
+    jobs: Mutex<VecDeque<Pending>>,
+}
+
+impl DeferredQueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Pending>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Antrean job async_ok per id printer
+static QUEUES: Lazy<DashMap<String, Arc<DeferredQueue>>> = Lazy::new(DashMap::new);
+
+/// Interval percobaan kirim job async_ok (`ASYNC_OK_RETRY_SECS`, default 5)
+static RETRY: Lazy<Duration> = Lazy::new(|| Duration::from_secs(crate::settings::settings().async_ok_retry_secs));
+
+/// Batas waktu job async_ok menunggu printer kembali online (`ASYNC_OK_MAX_WAIT_SECS`, default 600)
+static MAX_WAIT: Lazy<Duration> = Lazy::new(|| Duration::from_secs(crate::settings::settings().async_ok_max_wait_secs));
+
+/// Kapasitas antrean async_ok per printer (`ASYNC_OK_MAX_PENDING`, default 64)
+static MAX_PENDING: Lazy<usize> = Lazy::new(|| crate::settings::settings().async_ok_max_pending);
+
+/// Diset `dead_letter_pending`: worker berhenti mencoba dan job yang gagal langsung ke dead letter
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Tunda job sampai printer kembali online. Job untuk printer yang sama dikirim berurutan;
+/// antrean penuh ditolak (`QueueFull`, 503) supaya printer yang lama mati tidak menumpuk job di memori.
+pub fn defer(state: &Arc<RwLock<AppState>>, printer: &Printer, job: DeferredJob, in_flight: InFlightJob) -> Result<(), ProxyError> {
+    let pending = Pending {
+        job,
+        deadline: Instant::now() + *MAX_WAIT,
+        sending: false,
+        _in_flight: in_flight,
+    };
+    // Push dilakukan selama entry dipegang, jadi worker tidak bisa menghapus antrean di antaranya
+    let waiting = match QUEUES.entry(printer.id.clone()) {
+        Entry::Occupied(entry) => {
+            let mut jobs = entry.get().lock();
+            if jobs.len() >= *MAX_PENDING {
+                warn!("📪 Deferred queue for '{}' is full ({} jobs), rejecting job '{}'", printer.id, *MAX_PENDING, pending.job.job_id);
+                return Err(ProxyError::QueueFull(printer.id.clone()));
+            }
+            jobs.push_back(pending);
+            jobs.len()
+        }
+        Entry::Vacant(entry) => {
+            let queue = Arc::new(DeferredQueue {
+                printer_id: printer.id.clone(),
+                state: state.clone(),
+                jobs: Mutex::new(VecDeque::from([pending])),
+            });
+            entry.insert(queue.clone());
+            tokio::spawn(run_worker(queue));
+            1
+        }
+    };
+    debug!("📮 Job deferred for '{}' ({} waiting)", printer.id, waiting);
+    Ok(())
+}
+
+async fn run_worker(queue: Arc<DeferredQueue>) {
+    let printer_id = queue.printer_id.as_str();
+    info!("📮 Deferred worker for '{}' started", printer_id);
+    let mut attempts = 0u32;
+    loop {
+        tokio::time::sleep(*RETRY).await;
+        if SHUTTING_DOWN.load(Ordering::Relaxed) {
+            return;
+        }
+        // Kirim sebanyak mungkin selama printer menerima; berhenti di kegagalan pertama
+        loop {
+            let Some(printer) = queue.state.read().await.printers.get(printer_id).cloned() else {
+                // Printer dihapus dari config selama job menunggu
+                let e = ProxyError::NotFound(printer_id.to_string());
+                let removed: Vec<Pending> = queue.lock().drain(..).collect();
+                for pending in removed {
+                    error!("❌ Deferred job '{}' dropped: printer '{}' no longer exists", pending.job.job_id, printer_id);
+                    fail(printer_id, pending.job, &e);
+                }
+                break;
+            };
+            // Payload disalin supaya job tetap di antrean selama dikirim; `sending` mencegah shutdown
+            // memindahkannya ke dead letter di tengah pengiriman
+            let next = queue.lock().front_mut().map(|p| {
+                p.sending = true;
+                (p.job.payload.clone(), p.job.priority, p.job.refs)
+            });
+            let Some((payload, priority, refs)) = next else {
+                break;
+            };
+            attempts += 1;
+            match send_queued(&printer, &payload, priority, refs).await {
+                Ok(()) => {
+                    let Some(pending) = queue.lock().pop_front() else {
+                        return;
+                    };
+                    info!("📮 Deferred job '{}' delivered to '{}' after {} attempt(s)", pending.job.job_id, printer_id, attempts);
+                    attempts = 0;
+                    (pending.job.on_delivered)(&printer, &payload);
+                }
+                Err(e) if SHUTTING_DOWN.load(Ordering::Relaxed) => {
+                    // Shutdown melewati job ini karena sedang dikirim; gagal berarti dead letter sekarang
+                    if let Some(pending) = queue.lock().pop_front() {
+                        fail(printer_id, pending.job, &e);
+                    }
+                    return;
+                }
+                Err(e) => {
+                    debug!("📮 Deferred job for '{}' not delivered yet: {}", printer_id, e);
+                    if let Some(front) = queue.lock().front_mut() {
+                        front.sending = false;
+                    }
+                    expire(&queue, &e);
+                    break;
+                }
+            }
+        }
+        if QUEUES.remove_if(printer_id, |_, q| Arc::ptr_eq(q, &queue) && q.lock().is_empty()).is_some() {
+            break;
+        }
+    }
+    info!("📭 Deferred worker for '{}' stopped (queue empty)", printer_id);
+}
+
+/// Job yang sudah melewati `ASYNC_OK_MAX_WAIT_SECS` masuk dead letter seperti job biasa yang gagal
+fn expire(queue: &DeferredQueue, last_error: &ProxyError) {
+    let now = Instant::now();
+    let expired: VecDeque<Pending> = {
+        let mut jobs = queue.lock();
+        let (expired, waiting) = jobs.drain(..).partition(|p| p.deadline <= now);
+        *jobs = waiting;
+        expired
+    };
+    for pending in expired {
+        error!("❌ Deferred job '{}' for '{}' gave up: {}", pending.job.job_id, queue.printer_id, last_error);
+        fail(&queue.printer_id, pending.job, last_error);
+    }
+}
+
+fn fail(printer_id: &str, job: DeferredJob, e: &ProxyError) {
+    crate::jobs::record(&job.job_id, printer_id, Some(job.mode), job.payload.len(), Err(e));
+    crate::deadletter::record(printer_id, &e.to_string(), &job.payload);
+}
+
+/// Saat shutdown: job async_ok yang belum terkirim dipindah ke dead letter supaya bisa dicetak ulang.
+/// Job yang sedang ditulis ke printer dibiarkan; worker-nya yang mencatat jika pengiriman itu gagal.
+pub fn dead_letter_pending() -> usize {
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    let mut moved = 0;
+    for queue in QUEUES.iter() {
+        let pending: Vec<Pending> = {
+            let mut jobs = queue.lock();
+            let (sending, waiting) = jobs.drain(..).partition(|p| p.sending);
+            *jobs = sending;
+            waiting.into_iter().collect()
+        };
+        for p in pending {
+            warn!("📮 Deferred job '{}' for '{}' not delivered before shutdown", p.job.job_id, queue.printer_id);
+            fail(&queue.printer_id, p.job, &ProxyError::PrinterOffline(queue.printer_id.clone()));
+            moved += 1;
+        }
+        if let Some(p) = queue.lock().front() {
+            warn!("📮 Deferred job '{}' for '{}' still being sent at shutdown, not moved to dead letter", p.job.job_id, queue.printer_id);
+        }
+    }
+    moved
+}
+
+/// Jumlah job async_ok yang menunggu per printer (`/admin/stats`)
+pub fn depths() -> Vec<(String, usize)> {
+    let mut depths: Vec<(String, usize)> = QUEUES
+        .iter()
+        .map(|e| (e.key().clone(), e.value().lock().len()))
+        .collect();
+    depths.sort();
+    depths
+}
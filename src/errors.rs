@@ -2,15 +2,22 @@ use axum::{
     http::{HeaderMap, HeaderValue, StatusCode},
     response::IntoResponse,
 };
+use crate::config::ResponseFormat;
 use thiserror::Error;
 use tracing::{error, debug};
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ProxyError {
     #[error("Printer '{0}' tidak ditemukan")]
     NotFound(String),
     #[error("Printer '{0}' sedang offline dan tidak dapat menerima request")]
     PrinterOffline(String),
+    #[error("Printer sedang sibuk (koneksi ditolak): {0}")]
+    PrinterBusy(String),
+    #[error("Tidak mendapat koneksi ke printer dalam batas waktu: {0}")]
+    PoolTimeout(String),
+    #[error("Antrean job printer '{0}' penuh")]
+    QueueFull(String),
     #[error("Backend tidak didukung untuk printer '{0}'")]
     #[allow(dead_code)]
     Unsupported(String),
@@ -18,11 +25,52 @@ pub enum ProxyError {
     Io(String),
     #[error("Payload tidak valid: {0}")]
     BadPayload(String),
+    #[error("Ditolak: {0}")]
+    Forbidden(String),
+    #[error("Printer melaporkan error saat mencetak: {0}")]
+    PrinterFault(String),
+    #[error("Job tidak terkonfirmasi: {0}")]
+    Unconfirmed(String),
+    #[error("Proxy sedang drain mode, tidak menerima job baru")]
+    Draining,
     #[error("Kesalahan internal")]
-    #[allow(dead_code)]
     Internal,
 }
 
+impl ProxyError {
+    /// Nama jenis error untuk counter (`/admin/stats`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProxyError::NotFound(_) => "not_found",
+            ProxyError::PrinterOffline(_) => "printer_offline",
+            ProxyError::PrinterBusy(_) => "printer_busy",
+            ProxyError::PoolTimeout(_) => "pool_timeout",
+            ProxyError::QueueFull(_) => "queue_full",
+            ProxyError::Unsupported(_) => "unsupported",
+            ProxyError::Io(_) => "io",
+            ProxyError::BadPayload(_) => "bad_payload",
+            ProxyError::Forbidden(_) => "forbidden",
+            ProxyError::PrinterFault(_) => "printer_fault",
+            ProxyError::Unconfirmed(_) => "unconfirmed",
+            ProxyError::Draining => "draining",
+            ProxyError::Internal => "internal",
+        }
+    }
+
+    /// Gagal karena printer/jalur kirim (offline, I/O, sibuk, timeout termasuk konfirmasi yang tidak
+    /// datang), bukan karena job-nya: hanya job seperti ini yang layak disimpan ke dead letter
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ProxyError::Io(_)
+                | ProxyError::PrinterOffline(_)
+                | ProxyError::PrinterBusy(_)
+                | ProxyError::PoolTimeout(_)
+                | ProxyError::Unconfirmed(_)
+        )
+    }
+}
+
 /* === Uniform XML responses (persis seperti Python) === */
 
 fn cors_headers_xml() -> HeaderMap {
@@ -50,11 +98,55 @@ pub fn xml_success() -> impl IntoResponse {
     )
 }
 
+/// Response sukses print sesuai `ResponseFormat` printer
+pub fn job_success(format: ResponseFormat, job_id: &str, printer_id: &str, bytes: usize) -> axum::response::Response {
+    match format {
+        ResponseFormat::EposXml => xml_success().into_response(),
+        ResponseFormat::Json => {
+            debug!("✅ Returning JSON success response");
+            let mut headers = cors_headers_xml();
+            headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+            let body = serde_json::json!({
+                "success": true,
+                "code": 0,
+                "job_id": job_id,
+                "printer_id": printer_id,
+                "bytes": bytes,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            });
+            (StatusCode::OK, headers, body.to_string()).into_response()
+        }
+        ResponseFormat::Empty200 => {
+            debug!("✅ Returning empty success response");
+            let mut headers = cors_headers_xml();
+            headers.remove("Content-Type");
+            (StatusCode::OK, headers).into_response()
+        }
+    }
+}
+
 pub fn xml_error() -> impl IntoResponse {
-    debug!("❌ Returning XML error response");
+    xml_error_status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// XML error dengan status custom
+pub fn xml_error_status(status: StatusCode) -> impl IntoResponse {
+    debug!("❌ Returning XML error response ({})", status);
     let headers = cors_headers_xml();
     (
-        StatusCode::INTERNAL_SERVER_ERROR,
+        status,
+        headers,
+        "<?xml version=\"1.0\"?><response success=\"false\" code=\"1\"/>",
+    )
+}
+
+/// XML error dengan status custom dan header `Retry-After` (untuk 503)
+pub fn xml_error_retry_after(status: StatusCode, retry_after_secs: u64) -> impl IntoResponse {
+    debug!("❌ Returning XML error response ({}, retry after {}s)", status, retry_after_secs);
+    let mut headers = cors_headers_xml();
+    headers.insert("Retry-After", HeaderValue::from(retry_after_secs));
+    (
+        status,
         headers,
         "<?xml version=\"1.0\"?><response success=\"false\" code=\"1\"/>",
     )
@@ -66,10 +158,28 @@ pub fn xml_options_no_content() -> impl IntoResponse {
     (StatusCode::NO_CONTENT, headers, "")
 }
 
+/// Hint `Retry-After` untuk printer offline: `PRINTER_OFFLINE_RETRY_AFTER` (detik),
+/// default TTL health cache karena status offline tidak berubah sebelum cache expire
+fn offline_retry_after_secs() -> u64 {
+    crate::settings::settings()
+        .printer_offline_retry_after
+        .unwrap_or_else(|| crate::pool::HEALTH_CACHE.ttl().as_secs().max(1))
+}
+
 /* Return error ke client SELALU dengan XML error seperti Python */
 impl IntoResponse for ProxyError {
     fn into_response(self) -> axum::response::Response {
         error!("Request error: {self}");
-        xml_error().into_response()
+        match self {
+            ProxyError::Draining => xml_error_retry_after(StatusCode::SERVICE_UNAVAILABLE, 30).into_response(),
+            ProxyError::Forbidden(_) => xml_error_status(StatusCode::FORBIDDEN).into_response(),
+            ProxyError::PrinterBusy(_) | ProxyError::PoolTimeout(_) | ProxyError::QueueFull(_) => {
+                xml_error_retry_after(StatusCode::SERVICE_UNAVAILABLE, 1).into_response()
+            }
+            ProxyError::PrinterOffline(_) => {
+                xml_error_retry_after(StatusCode::SERVICE_UNAVAILABLE, offline_retry_after_secs()).into_response()
+            }
+            _ => xml_error().into_response(),
+        }
     }
 }
@@ -1,7 +1,13 @@
-use crate::errors::ProxyError;
+use crate::{
+    errors::ProxyError,
+    image::{downscale_gray, gray_to_mono, unpack_gray16, Dither, GrayImage, PixelFormat},
+};
+use std::{collections::HashMap, sync::Arc};
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
-use quick_xml::{events::Event, Reader};
+use once_cell::sync::Lazy;
+use quick_xml::{events::{BytesStart, Event}, Reader};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
 
 /* ===================== JSON Job (ops optional) ===================== */
 
@@ -9,34 +15,441 @@ use serde::{Deserialize, Serialize};
 #[serde(untagged)]
 pub enum JsonJob {
     RawBase64 { base64: String },
-    Ops { ops: Vec<PrintOp> },
+    Ops {
+        ops: Vec<PrintOp>,
+        /// Variabel untuk op `if`
+        #[serde(default)]
+        vars: JobVars,
+    },
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Variabel job JSON (`"vars": {...}`), dipakai kondisi op `if`
+pub type JobVars = HashMap<String, serde_json::Value>;
+
+impl JsonJob {
+    /// Parse body JSON job. Error serde untuk enum untagged tidak menyebut op mana yang salah,
+    /// jadi saat gagal body diperiksa ulang sebagai JSON generik untuk pesan yang menunjuk `ops[i]`.
+    pub fn parse(body: &[u8]) -> Result<Self, ProxyError> {
+        serde_json::from_slice(body).map_err(|e| {
+            let detail = describe_json_job_error(body).unwrap_or_else(|| e.to_string());
+            ProxyError::BadPayload(format!("JSON invalid: {}", detail))
+        })
+    }
+}
+
+/// Pecah data URI `data:<mime>;base64,<data>` menjadi (mime, data base64); `None` jika bukan data URI base64
+pub fn split_data_uri(s: &str) -> Option<(&str, &str)> {
+    let rest = s.trim_start().strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let mime = meta.strip_suffix(";base64")?;
+    // Parameter lain (mis. `;charset=...`) diabaikan, yang dipakai hanya media type
+    Some((mime.split(';').next().unwrap_or("").trim(), data))
+}
+
+/// Cari bagian job JSON yang tidak valid; `None` jika tidak ketemu (pakai error serde apa adanya)
+fn describe_json_job_error(body: &[u8]) -> Option<String> {
+    let value: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(v) => v,
+        Err(e) => return Some(e.to_string()),
+    };
+    let Some(obj) = value.as_object() else {
+        return Some("body harus object berisi 'ops' (array) atau 'base64' (string)".into());
+    };
+    if let Some(b64) = obj.get("base64") {
+        if !b64.is_string() {
+            return Some("base64: harus string".into());
+        }
+    }
+    let Some(ops) = obj.get("ops") else {
+        return Some("body harus berisi 'ops' (array) atau 'base64' (string)".into());
+    };
+    let Some(ops) = ops.as_array() else {
+        return Some("ops: harus array".into());
+    };
+
+    let known = || OP_SCHEMAS.iter().map(|s| s.name).collect::<Vec<_>>().join(", ");
+    ops.iter().enumerate().find_map(|(i, op)| {
+        let err = serde_json::from_value::<PrintOp>(op.clone()).err()?;
+        Some(match op.get("type") {
+            _ if !op.is_object() => format!("ops[{}]: harus object dengan field 'type'", i),
+            None => format!("ops[{}]: field 'type' wajib, salah satu dari [{}]", i, known()),
+            Some(serde_json::Value::String(t)) if !OP_SCHEMAS.iter().any(|s| s.name == t) => {
+                format!("ops[{}]: unknown type '{}', expected one of [{}]", i, t, known())
+            }
+            Some(serde_json::Value::String(t)) => format!("ops[{}] (type '{}'): {}", i, t, err),
+            Some(_) => format!("ops[{}]: field 'type' harus string", i),
+        })
+    })
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum PrintOp {
     #[serde(rename = "init")]
     Init,
     #[serde(rename = "text")]
-    Text { data: String, newline: Option<bool> },
+    Text {
+        data: String,
+        newline: Option<bool>,
+        /// Balik urutan visual per baris (teks kanan-ke-kiri); default true untuk `lang` RTL
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rtl: Option<bool>,
+        /// Bahasa teks ("he", "ar", "fa"): pilih code page printer dan encode teks ke code page itu
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lang: Option<String>,
+    },
     #[serde(rename = "feed")]
     Feed { lines: u8 },
+    /// Feed dalam dot (ESC J n), bukan baris
+    #[serde(rename = "feed_dots")]
+    FeedDots { dots: u8 },
     #[serde(rename = "cut")]
-    Cut { mode: Option<String> },
+    Cut {
+        mode: Option<String>,
+        /// Feed ke posisi cut + n motion unit lalu potong dalam satu perintah (GS V 65/66 n)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        feed: Option<u8>,
+    },
+    #[serde(rename = "command")]
+    Command { name: String, args: Vec<u8> },
+    #[serde(rename = "density")]
+    Density { level: i8 },
+    /// Gambar PNG dari URL (host harus ada di `IMAGE_URL_ALLOWED_HOSTS`) atau data URI `data:image/png;base64,...`
+    #[serde(rename = "image_url")]
+    ImageUrl {
+        url: String,
+        max_width: Option<u32>,
+        #[serde(skip)]
+        image: Option<Arc<GrayImage>>,
+    },
+    /// Masuk page mode (ESC L); elemen diposisikan bebas sampai `page_print`
+    #[serde(rename = "page_begin")]
+    PageBegin,
+    /// Area cetak page mode dalam dot (ESC W)
+    #[serde(rename = "set_print_area")]
+    SetPrintArea { x: u16, y: u16, w: u16, h: u16 },
+    /// Posisi absolut dalam area cetak, dalam dot (ESC $ / GS $)
+    #[serde(rename = "set_position")]
+    SetPosition { x: u16, y: u16 },
+    /// Cetak isi page dan kembali ke standard mode (FF)
+    #[serde(rename = "page_print")]
+    PagePrint,
+    /// Barcode 1D (GS k): "upc_a" atau "itf"
+    #[serde(rename = "barcode")]
+    Barcode {
+        symbology: String,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        height: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        width: Option<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hri: Option<String>,
+        /// EAN/UPC: `data` tanpa check digit, proxy menghitung dan menambahkannya
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        auto_check_digit: Option<bool>,
+    },
+    /// Simpan bitmap 1bpp (base64, row-major, MSB kiri) sebagai graphic bernama di proxy dan RAM printer
+    #[serde(rename = "define_graphic")]
+    DefineGraphic { id: String, base64: String, width: u32, height: u32 },
+    /// Cetak graphic yang sudah didefinisikan dengan `define_graphic`
+    #[serde(rename = "print_graphic")]
+    PrintGraphic { id: String },
+    /// Pilih font karakter (ESC M n): "A" (default printer), "B" (lebih kecil), "C"
+    #[serde(rename = "font")]
+    Font { name: String },
+    /// Orientasi karakter untuk teks berikutnya: "none", "180" (ESC {), atau "90cw" (ESC V)
+    #[serde(rename = "rotate")]
+    Rotate { mode: String },
+    /// Layout diagnostik bawaan: "chars", "alignment", "density", atau "all" (default)
+    #[serde(rename = "test_pattern")]
+    TestPattern { kind: Option<String> },
+    /// Cabang kondisional berdasarkan `vars` job: `then` jika kondisi terpenuhi, selain itu `else`
+    #[serde(rename = "if")]
+    If {
+        var: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        equals: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        not_empty: Option<bool>,
+        then: Vec<PrintOp>,
+        #[serde(rename = "else", default, skip_serializing_if = "Option::is_none")]
+        else_: Option<Vec<PrintOp>>,
+    },
+}
+
+/* ===================== Op Schema (GET /api/ops) ===================== */
+
+/// Deskripsi satu field op
+#[derive(Debug, Serialize)]
+pub struct OpField {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+/// Deskripsi satu op JSON job
+#[derive(Debug, Serialize)]
+pub struct OpSchema {
+    #[serde(rename = "type")]
+    pub name: &'static str,
+    pub description: &'static str,
+    pub fields: &'static [OpField],
+    /// Kapabilitas printer/proxy yang dibutuhkan op ini
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub requires: &'static [&'static str],
+}
+
+const fn field(name: &'static str, ty: &'static str, required: bool, default: Option<&'static str>, description: &'static str) -> OpField {
+    OpField { name, ty, required, default, description }
+}
+
+/// Semua op yang didukung `PrintOp`; tambahkan entri di sini setiap ada variant baru
+pub static OP_SCHEMAS: &[OpSchema] = &[
+    OpSchema { name: "init", description: "Reset printer (ESC @)", fields: &[], requires: &[] },
+    OpSchema {
+        name: "text",
+        description: "Cetak teks",
+        fields: &[
+            field("data", "string", true, None, "Teks yang dicetak"),
+            field("newline", "bool", false, Some("true"), "Tambahkan LF setelah teks"),
+            field("rtl", "bool", false, None, "Balik urutan visual per baris (kanan-ke-kiri); default true untuk lang he/ar/fa"),
+            field("lang", "string", false, None, "Bahasa teks: he (WPC1255), ar/fa (WPC1256); pilih code page dengan ESC t"),
+        ],
+        requires: &[],
+    },
+    OpSchema {
+        name: "feed",
+        description: "Feed kertas n baris (ESC d n)",
+        fields: &[field("lines", "u8", true, None, "Jumlah baris")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "feed_dots",
+        description: "Cetak buffer lalu feed n dot (ESC J n) untuk spasi vertikal presisi",
+        fields: &[field("dots", "u8", true, None, "Jumlah dot (motion unit, ~1 dot)")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "cut",
+        description: "Potong kertas (GS V)",
+        fields: &[
+            field("mode", "string", false, Some("full"), "\"full\" atau \"partial\""),
+            field("feed", "u8", false, None, "Feed ke posisi cut + n lalu potong (GS V 65/66 n)"),
+        ],
+        requires: &["cutter"],
+    },
+    OpSchema {
+        name: "command",
+        description: "Perintah ESC/POS terdaftar berdasarkan nama (lihat `commands`)",
+        fields: &[
+            field("name", "string", true, None, "Nama perintah"),
+            field("args", "u8[]", true, None, "Argumen sesuai jumlah yang dibutuhkan perintah"),
+        ],
+        requires: &[],
+    },
+    OpSchema {
+        name: "density",
+        description: "Print density; perintah mengikuti `model` printer (epson: GS ( K, dc2: DC2 #)",
+        fields: &[field("level", "i8", true, None, "-4 (terang) .. 4 (gelap), 0 = default printer")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "image_url",
+        description: "Gambar PNG dari URL atau data URI, di-fetch/di-decode lalu di-dither ke 1bpp",
+        fields: &[
+            field("url", "string", true, None, "URL http/https, atau data:image/png;base64,... (tanpa allowlist)"),
+            field("max_width", "u32", false, Some("576"), "Lebar maksimum dalam dot"),
+        ],
+        requires: &["IMAGE_URL_ALLOWED_HOSTS"],
+    },
+    OpSchema { name: "page_begin", description: "Masuk page mode (ESC L)", fields: &[], requires: &["page_mode"] },
+    OpSchema {
+        name: "set_print_area",
+        description: "Area cetak page mode dalam dot (ESC W)",
+        fields: &[
+            field("x", "u16", true, None, "Posisi horizontal"),
+            field("y", "u16", true, None, "Posisi vertikal"),
+            field("w", "u16", true, None, "Lebar"),
+            field("h", "u16", true, None, "Tinggi"),
+        ],
+        requires: &["page_mode"],
+    },
+    OpSchema {
+        name: "set_position",
+        description: "Posisi absolut dalam area cetak, dalam dot (ESC $ / GS $)",
+        fields: &[
+            field("x", "u16", true, None, "Posisi horizontal"),
+            field("y", "u16", true, None, "Posisi vertikal"),
+        ],
+        requires: &["page_mode"],
+    },
+    OpSchema { name: "page_print", description: "Cetak isi page dan kembali ke standard mode (FF)", fields: &[], requires: &["page_mode"] },
+    OpSchema {
+        name: "barcode",
+        description: "Barcode 1D (GS k function B)",
+        fields: &[
+            field("symbology", "string", true, None, "\"upc_a\" (11-12 digit), \"ean8\" (7-8 digit), atau \"itf\" (digit genap)"),
+            field("data", "string", true, None, "Isi barcode (digit)"),
+            field("height", "u8", false, Some("162"), "Tinggi dalam dot (GS h)"),
+            field("width", "u8", false, Some("3"), "Lebar modul 2-6 (GS w)"),
+            field("hri", "string", false, Some("below"), "Posisi teks HRI: none, above, below, both (GS H)"),
+            field("auto_check_digit", "bool", false, Some("false"), "UPC-A/EAN-8: data tanpa check digit (11/7 digit), check digit dihitung proxy"),
+        ],
+        requires: &[],
+    },
+    OpSchema {
+        name: "define_graphic",
+        description: "Simpan logo 1bpp di proxy dan download graphics printer (GS ( L fn 83); job berikutnya cukup print_graphic",
+        fields: &[
+            field("id", "string", true, None, "Nama graphic (1-64 karakter)"),
+            field("base64", "string", true, None, "Bitmap 1bpp row-major, MSB = kiri, ceil(width/8) byte per baris"),
+            field("width", "u32", true, None, "Lebar dalam dot (maks. 8192)"),
+            field("height", "u32", true, None, "Tinggi dalam dot (maks. 2304)"),
+        ],
+        requires: &[],
+    },
+    OpSchema {
+        name: "print_graphic",
+        description: "Cetak graphic yang sudah didefinisikan (GS ( L fn 85); definisi dikirim ulang otomatis ke koneksi baru",
+        fields: &[field("id", "string", true, None, "Nama graphic")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "font",
+        description: "Pilih font karakter (ESC M n); berlaku untuk teks berikutnya sampai op font lain atau init",
+        fields: &[field("name", "string", true, None, "\"A\" (standar), \"B\" (lebih kecil, lebih banyak kolom), \"C\"")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "rotate",
+        description: "Orientasi karakter (ESC V / ESC {); berlaku untuk teks berikutnya sampai op rotate lain atau init",
+        fields: &[field("mode", "string", true, None, "\"none\", \"180\" (terbalik), \"90cw\" (karakter diputar 90° searah jarum jam)")],
+        requires: &[],
+    },
+    OpSchema {
+        name: "if",
+        description: "Cabang kondisional berdasarkan `vars` job (sama dengan / tidak kosong), tanpa expression engine",
+        fields: &[
+            field("var", "string", true, None, "Nama variabel di `vars`"),
+            field("equals", "string", false, None, "Terpenuhi jika nilai variabel (sebagai teks) sama persis"),
+            field("not_empty", "bool", false, None, "true: variabel ada dan tidak kosong; false: tidak ada/null/\"\""),
+            field("then", "op[]", true, None, "Ops jika kondisi terpenuhi"),
+            field("else", "op[]", false, None, "Ops jika kondisi tidak terpenuhi"),
+        ],
+        requires: &[],
+    },
+    OpSchema {
+        name: "test_pattern",
+        description: "Layout diagnostik bawaan untuk kalibrasi (karakter code page, alignment, gradien density)",
+        fields: &[field("kind", "string", false, Some("all"), "\"chars\", \"alignment\", \"density\", atau \"all\"")],
+        requires: &[],
+    },
+];
+
+/// Nama dan jumlah argumen perintah untuk op `command`
+pub fn named_commands() -> impl Iterator<Item = (&'static str, usize)> {
+    NAMED_COMMANDS.iter().map(|(name, _, argc)| (*name, *argc))
+}
+
+/// Lebar default gambar `image_url` (kertas 80mm @ 203dpi)
+const DEFAULT_IMAGE_URL_WIDTH: u32 = 576;
+
+/* ===================== Named Command Registry ===================== */
+
+/// Perintah ESC/POS yang boleh dikirim via op `command`: (nama, prefix, jumlah args).
+/// Density tidak ada di sini: op `density` sudah memilih perintah sesuai `model` printer.
+const NAMED_COMMANDS: &[(&str, &[u8], usize)] = &[
+    ("set_font", &[0x1B, b'M'], 1),                         // ESC M n
+    ("rotate", &[0x1B, b'V'], 1),                           // ESC V n
+    ("upside_down", &[0x1B, b'{'], 1),                      // ESC { n
+    ("bold", &[0x1B, b'E'], 1),                             // ESC E n
+    ("underline", &[0x1B, b'-'], 1),                        // ESC - n
+    ("char_size", &[0x1D, b'!'], 1),                        // GS ! n
+    ("code_page", &[0x1B, b't'], 1),                        // ESC t n
+    ("line_spacing", &[0x1B, b'3'], 1),                     // ESC 3 n
+    ("default_line_spacing", &[0x1B, b'2'], 0),             // ESC 2
+    ("drawer_kick", &[0x1B, b'p'], 3),                      // ESC p m t1 t2
+];
+
+/// Emit perintah ESC/POS terdaftar berdasarkan nama
+pub fn esc_named_command(buf: &mut Vec<u8>, name: &str, args: &[u8]) -> Result<(), ProxyError> {
+    let (_, prefix, argc) = NAMED_COMMANDS
+        .iter()
+        .find(|(n, _, _)| n.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let known: Vec<&str> = NAMED_COMMANDS.iter().map(|(n, _, _)| *n).collect();
+            ProxyError::BadPayload(format!(
+                "Command '{}' tidak dikenal, gunakan salah satu dari [{}]",
+                name,
+                known.join(", ")
+            ))
+        })?;
+
+    if args.len() != *argc {
+        return Err(ProxyError::BadPayload(format!(
+            "Command '{}' membutuhkan {} argumen (got {})",
+            name,
+            argc,
+            args.len()
+        )));
+    }
+
+    buf.extend_from_slice(prefix);
+    buf.extend_from_slice(args);
+    Ok(())
 }
 
 /* ===================== ESC/POS Helpers ===================== */
 
-#[derive(Debug, Clone, Copy)]
+/// Keluarga perintah vendor, ditentukan dari `model` printer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrinterFamily {
+    /// Epson & kompatibel (GS ( K)
+    #[default]
+    Epson,
+    /// Printer yang memakai DC2 # n untuk density
+    Dc2,
+}
+
+impl PrinterFamily {
+    pub fn from_model(model: Option<&str>) -> Self {
+        match model {
+            Some(m) if m.to_ascii_lowercase().starts_with("dc2") => PrinterFamily::Dc2,
+            _ => PrinterFamily::Epson,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Align {
     Left,
     Center,
     Right,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Align {
+    /// Perataan untuk printer `rotate_180`: kiri/kanan tertukar saat kertas dibaca dari sisi sebaliknya
+    pub fn mounted(self, rotate_180: bool) -> Align {
+        match (self, rotate_180) {
+            (Align::Left, true) => Align::Right,
+            (Align::Right, true) => Align::Left,
+            (align, _) => align,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BitOrder {
+    #[serde(alias = "msb")]
     MsbFirst,
+    #[serde(alias = "lsb")]
     LsbFirst,
 }
 
@@ -53,6 +466,37 @@ pub fn esc_align(buf: &mut Vec<u8>, a: Align) {
     buf.extend_from_slice(&[0x1B, b'a', n]);
 }
 
+/// ESC { n - upside-down mode (untuk printer yang dipasang terbalik)
+pub fn esc_upside_down(buf: &mut Vec<u8>, on: bool) {
+    buf.extend_from_slice(&[0x1B, b'{', on as u8]);
+}
+
+/// Awal job dan setelah ESC @ untuk printer `rotate_180`: upside-down mode dan perataan default
+/// (kiri) yang sudah ditukar, supaya teks dan barcode tanpa perataan eksplisit ikut sisi yang sama
+fn esc_mounted_defaults(buf: &mut Vec<u8>, rotate_180: bool) {
+    if rotate_180 {
+        esc_upside_down(buf, true);
+        esc_align(buf, Align::Left.mounted(true));
+    }
+}
+
+/// Print density, `level` -4 (paling terang) .. +4 (paling gelap), 0 = default printer
+pub fn esc_density(buf: &mut Vec<u8>, family: PrinterFamily, level: i8) {
+    let clamped = level.clamp(-4, 4);
+    if clamped != level {
+        warn!("⚠️ Density level {} di luar rentang -4..4, dipakai {}", level, clamped);
+    }
+    match family {
+        // GS ( K pL pH fn=49 m, m: 250..255 = -6..-1, 0 = default, 1..6
+        PrinterFamily::Epson => buf.extend_from_slice(&[0x1D, b'(', b'K', 0x02, 0x00, 0x31, clamped as u8]),
+        // DC2 # n, 5 bit rendah = density 0..31
+        PrinterFamily::Dc2 => {
+            let n = ((clamped as i16 + 4) * 4).min(31) as u8;
+            buf.extend_from_slice(&[0x12, b'#', n]);
+        }
+    }
+}
+
 pub fn esc_text_line(buf: &mut Vec<u8>, s: &str, newline: bool) {
     buf.extend_from_slice(s.as_bytes());
     if newline {
@@ -60,14 +504,719 @@ pub fn esc_text_line(buf: &mut Vec<u8>, s: &str, newline: bool) {
     }
 }
 
+/// Bahasa teks yang butuh code page khusus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLang {
+    Hebrew,
+    Arabic,
+}
+
+impl TextLang {
+    pub fn parse(val: &str) -> Result<Self, ProxyError> {
+        match val.to_ascii_lowercase().as_str() {
+            "he" | "iw" | "hebrew" => Ok(TextLang::Hebrew),
+            "ar" | "fa" | "ur" | "arabic" => Ok(TextLang::Arabic),
+            other => Err(ProxyError::BadPayload(format!("lang '{}' tidak didukung (he|ar|fa|ur)", other))),
+        }
+    }
+
+    /// Nomor tabel ESC t n (Epson) dan encoding yang cocok
+    fn code_page(self) -> (u8, &'static encoding_rs::Encoding) {
+        match self {
+            TextLang::Hebrew => (49, encoding_rs::WINDOWS_1255), // WPC1255
+            TextLang::Arabic => (50, encoding_rs::WINDOWS_1256), // WPC1256
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        matches!(self, TextLang::Hebrew | TextLang::Arabic)
+    }
+}
+
+/// Urutan visual satu baris RTL: run LTR (huruf latin/angka beserta tanda baca di antaranya)
+/// dipertahankan, sisanya dibalik, tanda kurung di luar run LTR di-mirror. Bukan BiDi penuh.
+fn rtl_visual_line(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let is_ltr = |c: char| c.is_ascii_alphanumeric();
+    let is_rtl = |c: char| !c.is_ascii();
+    let mirror = |c: char| match c {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        c => c,
+    };
+
+    // Unit visual: satu karakter RTL/netral atau satu run LTR utuh
+    let mut units: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if is_ltr(chars[i]) {
+            // Perpanjang run sampai karakter RTL berikutnya, lalu potong netral di ujung
+            let mut end = i;
+            let mut j = i;
+            while j < chars.len() && !is_rtl(chars[j]) {
+                if is_ltr(chars[j]) {
+                    end = j;
+                }
+                j += 1;
+            }
+            units.push(chars[i..=end].iter().collect());
+            i = end + 1;
+        } else {
+            units.push(mirror(chars[i]).to_string());
+            i += 1;
+        }
+    }
+    units.reverse();
+    units.concat()
+}
+
+/// Encode teks ke code page; karakter yang tidak ada di code page diganti `?`
+fn encode_code_page(s: &str, encoding: &'static encoding_rs::Encoding) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut buf = [0u8; 8];
+    for c in s.chars() {
+        let (bytes, _, unmappable) = encoding.encode(c.encode_utf8(&mut buf));
+        if unmappable {
+            out.push(b'?');
+        } else {
+            out.extend_from_slice(&bytes);
+        }
+    }
+    out
+}
+
+/// Lebar tampilan karakter dalam kolom: CJK dan fullwidth dicetak dua kolom
+fn char_columns(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Wrap setiap baris yang lebih lebar dari `width` kolom, di spasi terakhir yang muat
+/// (kata yang lebih panjang dari satu baris dipotong). Baris yang muat tidak diubah, jadi
+/// spasi untuk perataan kolom tetap utuh.
+fn wrap_text(s: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut out = String::with_capacity(s.len() + s.len() / width + 1);
+    for (i, line) in s.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let chars: Vec<char> = line.chars().collect();
+        let mut rest = &chars[..];
+        loop {
+            // Jumlah karakter yang muat dalam `width` kolom
+            let mut columns = 0;
+            let fit = rest
+                .iter()
+                .take_while(|c| {
+                    columns += char_columns(**c);
+                    columns <= width
+                })
+                .count();
+            if fit == rest.len() {
+                break;
+            }
+            let cut = rest[..=fit].iter().rposition(|c| *c == ' ').filter(|&i| i > 0).unwrap_or(fit.max(1));
+            out.extend(&rest[..cut]);
+            out.push('\n');
+            rest = &rest[cut..];
+            while rest.first() == Some(&' ') {
+                rest = &rest[1..];
+            }
+        }
+        out.extend(rest);
+    }
+    out
+}
+
+/// Teks dengan bahasa/arah khusus: pilih code page (ESC t n), balik urutan per baris jika RTL
+pub fn esc_text_lang(buf: &mut Vec<u8>, s: &str, newline: bool, rtl: bool, lang: Option<TextLang>) {
+    let visual = if rtl {
+        s.split('\n').map(rtl_visual_line).collect::<Vec<_>>().join("\n")
+    } else {
+        s.to_string()
+    };
+    match lang {
+        Some(lang) => {
+            let (table, encoding) = lang.code_page();
+            buf.extend_from_slice(&[0x1B, b't', table]);
+            buf.extend_from_slice(&encode_code_page(&visual, encoding));
+        }
+        None => buf.extend_from_slice(visual.as_bytes()),
+    }
+    if newline {
+        buf.push(b'\n');
+    }
+}
+
+/// Encoding multibyte untuk printer CJK yang punya kanji mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CjkEncoding {
+    /// Jepang
+    #[serde(alias = "sjis", alias = "shift-jis")]
+    ShiftJis,
+    /// Tionghoa sederhana
+    #[serde(alias = "gb2312", alias = "gb18030")]
+    Gbk,
+    /// Tionghoa tradisional
+    Big5,
+    /// Korea
+    #[serde(alias = "ksc5601", alias = "euc-kr")]
+    EucKr,
+}
+
+impl CjkEncoding {
+    fn encoding(self) -> &'static encoding_rs::Encoding {
+        match self {
+            CjkEncoding::ShiftJis => encoding_rs::SHIFT_JIS,
+            CjkEncoding::Gbk => encoding_rs::GBK,
+            CjkEncoding::Big5 => encoding_rs::BIG5,
+            CjkEncoding::EucKr => encoding_rs::EUC_KR,
+        }
+    }
+}
+
+/// Teks untuk printer CJK: jika ada karakter non-ASCII, aktifkan kanji mode (FS &), encode ke
+/// encoding multibyte printer, lalu kembali ke mode karakter satu byte (FS .).
+/// Model Jepang juga butuh FS C 1 supaya byte dibaca sebagai Shift-JIS, bukan JIS.
+pub fn esc_text_cjk(buf: &mut Vec<u8>, s: &str, newline: bool, encoding: CjkEncoding) {
+    if s.is_ascii() {
+        esc_text_line(buf, s, newline);
+        return;
+    }
+    if encoding == CjkEncoding::ShiftJis {
+        buf.extend_from_slice(&[0x1C, b'C', 1]); // FS C 1
+    }
+    buf.extend_from_slice(&[0x1C, b'&']);
+    buf.extend_from_slice(&encode_code_page(s, encoding.encoding()));
+    buf.extend_from_slice(&[0x1C, b'.']);
+    if newline {
+        buf.push(b'\n');
+    }
+}
+
+/// Font karakter ESC/POS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    A,
+    B,
+    C,
+}
+
+impl Font {
+    pub fn parse(name: &str) -> Result<Self, ProxyError> {
+        match name.trim().to_ascii_uppercase().as_str() {
+            "A" => Ok(Font::A),
+            "B" => Ok(Font::B),
+            "C" => Ok(Font::C),
+            other => Err(ProxyError::BadPayload(format!("font '{}' tidak dikenal (A, B, C)", other))),
+        }
+    }
+
+    /// Kolom per baris untuk font ini dari lebar dalam kolom font A: font B (9 dot) memuat
+    /// 4/3 kolom font A (12 dot), mis. 48 -> 64. Font C dianggap selebar font B.
+    pub fn columns(self, font_a_columns: usize) -> usize {
+        match self {
+            Font::A => font_a_columns,
+            Font::B | Font::C => font_a_columns * 4 / 3,
+        }
+    }
+}
+
+/// ESC M n - pilih font karakter
+pub fn esc_font(buf: &mut Vec<u8>, font: Font) {
+    let n = match font {
+        Font::A => 0,
+        Font::B => 1,
+        Font::C => 2,
+    };
+    buf.extend_from_slice(&[0x1B, b'M', n]);
+}
+
+/// Orientasi karakter ESC/POS untuk op `rotate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    UpsideDown,
+    Clockwise90,
+}
+
+impl Rotation {
+    pub fn parse(mode: &str) -> Result<Self, ProxyError> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "none" | "0" => Ok(Rotation::None),
+            "180" => Ok(Rotation::UpsideDown),
+            "90cw" | "90" => Ok(Rotation::Clockwise90),
+            other => Err(ProxyError::BadPayload(format!("rotate mode '{}' tidak dikenal (none, 180, 90cw)", other))),
+        }
+    }
+}
+
+/// ESC { n + ESC V n - set orientasi karakter; mode lain selalu dimatikan supaya tidak bertumpuk.
+/// `base_upside_down` untuk printer `rotate_180`: "none" kembali ke posisi terbalik, "180" membatalkannya.
+/// Hasilnya status upside-down mode printer setelah perintah ini.
+pub fn esc_rotation(buf: &mut Vec<u8>, rotation: Rotation, base_upside_down: bool) -> bool {
+    let upside_down = (rotation == Rotation::UpsideDown) != base_upside_down && rotation != Rotation::Clockwise90;
+    esc_upside_down(buf, upside_down);
+    buf.extend_from_slice(&[0x1B, b'V', (rotation == Rotation::Clockwise90) as u8]);
+    upside_down
+}
+
 pub fn esc_feed(buf: &mut Vec<u8>, lines: u8) {
     buf.extend_from_slice(&[0x1B, 0x64, lines]); // ESC d n
 }
 
+/// ESC J n - cetak buffer lalu feed n motion unit (~1 dot), untuk spasi vertikal yang presisi
+pub fn esc_feed_dots(buf: &mut Vec<u8>, dots: u8) {
+    buf.extend_from_slice(&[0x1B, b'J', dots]);
+}
+
+/// Varian perintah cut GS V
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CutVariant {
+    /// GS V 0
+    Full,
+    /// GS V 1
+    Partial,
+    /// GS V 65 n: feed ke posisi cut + n motion unit, lalu full cut
+    FeedAndFull { feed: u8 },
+    /// GS V 66 n: feed ke posisi cut + n motion unit, lalu partial cut
+    FeedAndPartial { feed: u8 },
+}
+
+impl CutVariant {
+    /// Dari mode op JSON ("full"/"partial"/"p") dan `feed` opsional
+    pub fn from_mode(mode: Option<&str>, feed: Option<u8>) -> Self {
+        let partial = mode.is_some_and(|m| matches!(m.to_ascii_lowercase().as_str(), "partial" | "p"));
+        match (partial, feed) {
+            (false, None) => CutVariant::Full,
+            (true, None) => CutVariant::Partial,
+            (false, Some(feed)) => CutVariant::FeedAndFull { feed },
+            (true, Some(feed)) => CutVariant::FeedAndPartial { feed },
+        }
+    }
+}
+
+pub fn esc_cut_variant(buf: &mut Vec<u8>, cut: CutVariant) {
+    match cut {
+        CutVariant::Full => buf.extend_from_slice(&[0x1D, 0x56, 0x00]),
+        CutVariant::Partial => buf.extend_from_slice(&[0x1D, 0x56, 0x01]),
+        CutVariant::FeedAndFull { feed } => buf.extend_from_slice(&[0x1D, 0x56, 0x41, feed]),
+        CutVariant::FeedAndPartial { feed } => buf.extend_from_slice(&[0x1D, 0x56, 0x42, feed]),
+    }
+}
+
 pub fn esc_cut(buf: &mut Vec<u8>, partial: bool) {
-    buf.extend_from_slice(&[0x1D, 0x56, if partial { 0x01 } else { 0x00 }]); // GS V m
+    esc_cut_variant(buf, if partial { CutVariant::Partial } else { CutVariant::Full }); // GS V m
+}
+
+/// GS V 97 n - reserve cut: potong saat kertas mencapai posisi cut + n setelah data berikutnya dicetak
+pub fn esc_cut_reserve(buf: &mut Vec<u8>, n: u8) {
+    buf.extend_from_slice(&[0x1D, 0x56, 0x61, n]);
+}
+
+/* ===================== Barcode ===================== */
+
+/// Simbologi barcode 1D untuk op `barcode` (GS k function B)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarcodeSymbology {
+    /// UPC-A, 11 digit (check digit dihitung printer) atau 12 digit
+    UpcA,
+    /// EAN-8 (JAN-8), 7 digit (check digit dihitung printer) atau 8 digit
+    Ean8,
+    /// Interleaved 2 of 5, jumlah digit genap
+    Itf,
+}
+
+impl BarcodeSymbology {
+    pub fn parse(val: &str) -> Result<Self, ProxyError> {
+        match val.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "upca" => Ok(Self::UpcA),
+            "ean8" | "jan8" => Ok(Self::Ean8),
+            "itf" => Ok(Self::Itf),
+            _ => Err(ProxyError::BadPayload(format!(
+                "barcode symbology '{}' tidak dikenal (upc_a|ean8|itf)",
+                val
+            ))),
+        }
+    }
+
+    /// Nilai m untuk GS k m n (function B)
+    fn code(self) -> u8 {
+        match self {
+            Self::UpcA => 65,
+            Self::Ean8 => 68,
+            Self::Itf => 70,
+        }
+    }
+
+    /// Validasi data sesuai aturan simbologi
+    pub fn validate(self, data: &str) -> Result<(), ProxyError> {
+        if data.is_empty() || !data.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ProxyError::BadPayload(format!("barcode {:?}: data harus berisi digit 0-9, got '{}'", self, data)));
+        }
+        match self {
+            Self::UpcA if !(11..=12).contains(&data.len()) => Err(ProxyError::BadPayload(format!(
+                "barcode UPC-A butuh 11-12 digit, got {}",
+                data.len()
+            ))),
+            Self::Ean8 if !(7..=8).contains(&data.len()) => Err(ProxyError::BadPayload(format!(
+                "barcode EAN-8 butuh 7-8 digit, got {}",
+                data.len()
+            ))),
+            Self::Itf if !data.len().is_multiple_of(2) => Err(ProxyError::BadPayload(format!(
+                "barcode ITF butuh jumlah digit genap, got {}",
+                data.len()
+            ))),
+            Self::Itf if data.len() > 255 => Err(ProxyError::BadPayload("barcode ITF maksimal 254 digit".into())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Tambahkan check digit GS1 (mod 10) ke data EAN/UPC yang belum punya check digit
+    pub fn with_check_digit(self, data: &str) -> Result<String, ProxyError> {
+        let digits = match self {
+            Self::UpcA => 11,
+            Self::Ean8 => 7,
+            Self::Itf => {
+                return Err(ProxyError::BadPayload("barcode ITF tidak punya check digit, auto_check_digit hanya untuk upc_a/ean8".into()))
+            }
+        };
+        if data.len() != digits || !data.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ProxyError::BadPayload(format!(
+                "barcode {:?} dengan auto_check_digit butuh tepat {} digit tanpa check digit, got '{}'",
+                self, digits, data
+            )));
+        }
+        // Bobot 3,1,3,1,... dari digit paling kanan
+        let sum: u32 = data
+            .bytes()
+            .rev()
+            .enumerate()
+            .map(|(i, b)| u32::from(b - b'0') * if i % 2 == 0 { 3 } else { 1 })
+            .sum();
+        Ok(format!("{}{}", data, (10 - sum % 10) % 10))
+    }
+}
+
+/// Posisi teks HRI (GS H n) dari string op: "none", "above", "below" (default), "both"
+fn parse_hri(val: Option<&str>) -> Result<u8, ProxyError> {
+    match val.map(|v| v.to_ascii_lowercase()).as_deref() {
+        Some("none") => Ok(0),
+        Some("above") => Ok(1),
+        None | Some("below") => Ok(2),
+        Some("both") => Ok(3),
+        Some(other) => Err(ProxyError::BadPayload(format!("barcode hri '{}' tidak dikenal (none|above|below|both)", other))),
+    }
+}
+
+/// GS H (HRI), GS h (tinggi), GS w (lebar modul), lalu GS k m n data
+pub fn esc_barcode(
+    buf: &mut Vec<u8>,
+    symbology: BarcodeSymbology,
+    data: &str,
+    height: Option<u8>,
+    width: Option<u8>,
+    hri: Option<&str>,
+) -> Result<(), ProxyError> {
+    symbology.validate(data)?;
+    let width = width.unwrap_or(3);
+    if !(2..=6).contains(&width) {
+        return Err(ProxyError::BadPayload(format!("barcode width harus 2-6, got {}", width)));
+    }
+    let height = height.unwrap_or(162);
+    if height == 0 {
+        return Err(ProxyError::BadPayload("barcode height harus > 0".into()));
+    }
+    buf.extend_from_slice(&[0x1D, b'H', parse_hri(hri)?]);
+    buf.extend_from_slice(&[0x1D, b'h', height]);
+    buf.extend_from_slice(&[0x1D, b'w', width]);
+    buf.extend_from_slice(&[0x1D, b'k', symbology.code(), data.len() as u8]);
+    buf.extend_from_slice(data.as_bytes());
+    Ok(())
+}
+
+/* ===================== Test Pattern ===================== */
+
+/// Lebar bar test pattern dalam dot (muat di kertas 58mm maupun 80mm)
+const TEST_PATTERN_WIDTH: u32 = 384;
+
+/// Jenis layout op `test_pattern`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPatternKind {
+    /// Semua karakter printable code page aktif (0x20..0x7E, 0x80..0xFF)
+    Chars,
+    /// Teks rata kiri/tengah/kanan dan penggaris kolom
+    Alignment,
+    /// Gradien abu-abu dan bar hitam untuk setiap level density
+    Density,
+    /// Semua di atas
+    All,
+}
+
+impl TestPatternKind {
+    pub fn parse(kind: Option<&str>) -> Result<Self, ProxyError> {
+        match kind.map(|k| k.to_ascii_lowercase()).as_deref() {
+            None | Some("all") => Ok(Self::All),
+            Some("chars") => Ok(Self::Chars),
+            Some("alignment") => Ok(Self::Alignment),
+            Some("density") => Ok(Self::Density),
+            Some(other) => Err(ProxyError::BadPayload(format!(
+                "test_pattern kind '{}' tidak dikenal (chars|alignment|density|all)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Bar raster hitam penuh selebar `TEST_PATTERN_WIDTH`
+fn esc_test_bar(buf: &mut Vec<u8>, height: u32) -> Result<(), ProxyError> {
+    let bitmap = vec![0xFF; TEST_PATTERN_WIDTH.div_ceil(8) as usize * height as usize];
+    esc_raster_image(buf, TEST_PATTERN_WIDTH, height, &bitmap, 0)
+}
+
+fn esc_test_chars(buf: &mut Vec<u8>) {
+    esc_text_line(buf, "== CHARS (code page aktif) ==", true);
+    for row in (0x20u8..=0xF0).step_by(16) {
+        buf.extend_from_slice(format!("{:02X}: ", row).as_bytes());
+        // 0x7F (DEL) bukan karakter printable
+        buf.extend((row..=row + 15).filter(|b| *b != 0x7F));
+        buf.push(b'\n');
+    }
 }
 
+fn esc_test_alignment(buf: &mut Vec<u8>, rotate_180: bool) -> Result<(), ProxyError> {
+    esc_text_line(buf, "== ALIGNMENT ==", true);
+    for (align, label) in [(Align::Left, "|<- LEFT"), (Align::Center, "-> CENTER <-"), (Align::Right, "RIGHT ->|")] {
+        esc_align(buf, align.mounted(rotate_180));
+        esc_text_line(buf, label, true);
+    }
+    esc_align(buf, Align::Left.mounted(rotate_180));
+    // Penggaris kolom: 32 kolom (58mm) dan 48 kolom (80mm) Font A
+    esc_text_line(buf, "123456789012345678901234567890123456789012345678", true);
+    esc_test_bar(buf, 8)
+}
+
+fn esc_test_density(buf: &mut Vec<u8>, family: PrinterFamily) -> Result<(), ProxyError> {
+    esc_text_line(buf, "== DENSITY ==", true);
+    // Gradien putih -> hitam, di-dither supaya level abu-abu terlihat
+    let (w, h) = (TEST_PATTERN_WIDTH, 48);
+    let gray: Vec<u8> = (0..h)
+        .flat_map(|_| (0..w).map(move |x| 255 - (x * 255 / (w - 1)) as u8))
+        .collect();
+    let bitmap = gray_to_mono(&gray, w, h, Dither::FloydSteinberg, 128);
+    esc_raster_image(buf, w, h, &bitmap, 0)?;
+    for level in -4i8..=4 {
+        esc_density(buf, family, level);
+        esc_text_line(buf, &format!("density {:+}", level), true);
+        esc_test_bar(buf, 16)?;
+    }
+    esc_density(buf, family, 0);
+    Ok(())
+}
+
+/// Layout diagnostik bawaan untuk kalibrasi printer (op `test_pattern`)
+pub fn esc_test_pattern(buf: &mut Vec<u8>, kind: TestPatternKind, family: PrinterFamily, rotate_180: bool) -> Result<(), ProxyError> {
+    if matches!(kind, TestPatternKind::Chars | TestPatternKind::All) {
+        esc_test_chars(buf);
+    }
+    if matches!(kind, TestPatternKind::Alignment | TestPatternKind::All) {
+        esc_test_alignment(buf, rotate_180)?;
+    }
+    if matches!(kind, TestPatternKind::Density | TestPatternKind::All) {
+        esc_test_density(buf, family)?;
+    }
+    Ok(())
+}
+
+/* ===================== Page Mode ===================== */
+
+/// ESC L - pindah ke page mode
+pub fn esc_page_begin(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&[0x1B, b'L']);
+}
+
+/// ESC W xL xH yL yH dxL dxH dyL dyH - area cetak page mode
+pub fn esc_print_area(buf: &mut Vec<u8>, x: u16, y: u16, w: u16, h: u16) {
+    buf.extend_from_slice(&[0x1B, b'W']);
+    for v in [x, y, w, h] {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+/// ESC $ nL nH (horizontal) + GS $ nL nH (vertikal, page mode) - posisi absolut
+pub fn esc_position(buf: &mut Vec<u8>, x: u16, y: u16) {
+    buf.extend_from_slice(&[0x1B, b'$']);
+    buf.extend_from_slice(&x.to_le_bytes());
+    buf.extend_from_slice(&[0x1D, b'$']);
+    buf.extend_from_slice(&y.to_le_bytes());
+}
+
+/// FF - cetak page dan kembali ke standard mode
+pub fn esc_page_print(buf: &mut Vec<u8>) {
+    buf.push(0x0C);
+}
+
+/* ===================== Image Limits ===================== */
+
+/// Batas ukuran gambar untuk mencegah payload yang menghabiskan memori
+#[derive(Debug, Clone, Copy)]
+pub struct ImageLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_bitmap_bytes: usize,
+    /// Batas teks base64 satu `<image>` (dicek saat parsing, sebelum decode)
+    pub max_base64_bytes: usize,
+    /// Batas total teks base64 semua `<image>` dalam satu dokumen
+    pub max_document_base64_bytes: usize,
+    /// Batas jumlah `<image>` dalam satu dokumen
+    pub max_images_per_doc: usize,
+}
+
+impl ImageLimits {
+    /// Dari env `MAX_IMAGE_WIDTH`, `MAX_IMAGE_HEIGHT`, `MAX_IMAGE_BYTES`, dst. (lewat settings)
+    fn from_env() -> Self {
+        let s = crate::settings::settings();
+        Self {
+            max_width: s.max_image_width,
+            max_height: s.max_image_height,
+            max_bitmap_bytes: s.max_image_bytes,
+            max_base64_bytes: s.max_image_base64_bytes,
+            max_document_base64_bytes: s.max_document_base64_bytes,
+            max_images_per_doc: s.max_images_per_doc,
+        }
+    }
+
+    /// Validasi jumlah gambar sebelum `<image>` berikutnya mulai diparse
+    pub fn check_count(&self, parsed: usize) -> Result<(), ProxyError> {
+        if parsed >= self.max_images_per_doc {
+            return Err(ProxyError::BadPayload(format!(
+                "Terlalu banyak <image> dalam dokumen (maksimum {})",
+                self.max_images_per_doc
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validasi akumulasi base64 selama parsing, sebelum data di-decode
+    pub fn check_base64(&self, image_len: usize, document_len: usize) -> Result<(), ProxyError> {
+        if image_len > self.max_base64_bytes {
+            return Err(ProxyError::BadPayload(format!(
+                "Base64 <image> terlalu besar (> {} bytes)",
+                self.max_base64_bytes
+            )));
+        }
+        if document_len > self.max_document_base64_bytes {
+            return Err(ProxyError::BadPayload(format!(
+                "Total base64 gambar dalam dokumen terlalu besar (> {} bytes)",
+                self.max_document_base64_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validasi buffer kerja saat decode (data grayscale, buffer error dithering, PNG RGBA)
+    /// sebelum dialokasikan; ukurannya bisa 8-32x bitmap 1bpp hasil akhirnya
+    pub fn check_buffer(&self, bytes: usize, what: &str) -> Result<(), ProxyError> {
+        if bytes > self.max_bitmap_bytes {
+            return Err(ProxyError::BadPayload(format!(
+                "Ukuran {} terlalu besar ({} bytes, maksimum {} bytes)",
+                what, bytes, self.max_bitmap_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validasi dimensi gambar, return jumlah byte bitmap yang diharapkan
+    pub fn check(&self, width: u32, height: u32) -> Result<usize, ProxyError> {
+        if width > self.max_width || height > self.max_height {
+            return Err(ProxyError::BadPayload(format!(
+                "Dimensi gambar terlalu besar ({}x{}, maksimum {}x{})",
+                width, height, self.max_width, self.max_height
+            )));
+        }
+        let expected = width.div_ceil(8) as usize * height as usize;
+        if expected > self.max_bitmap_bytes {
+            return Err(ProxyError::BadPayload(format!(
+                "Ukuran bitmap terlalu besar ({} bytes, maksimum {} bytes)",
+                expected, self.max_bitmap_bytes
+            )));
+        }
+        Ok(expected)
+    }
+}
+
+/// Validasi dimensi dan ukuran data graphic sebelum didefinisikan
+pub fn check_graphic(width: u32, height: u32, data_len: usize) -> Result<(), ProxyError> {
+    if width == 0 || height == 0 || width > 8192 || height > 2304 {
+        return Err(ProxyError::BadPayload(format!(
+            "Dimensi graphic {}x{} di luar rentang 1..8192 x 1..2304",
+            width, height
+        )));
+    }
+    let expected = IMAGE_LIMITS.check(width, height)?;
+    if data_len != expected {
+        return Err(ProxyError::BadPayload(format!(
+            "Ukuran data graphic tidak cocok (got {}, expected {})",
+            data_len,
+            expected
+        )));
+    }
+    Ok(())
+}
+
+/// GS ( L / GS 8 L fn 83 - simpan graphic raster 1bpp ke download graphics area (RAM printer) dengan key kc1 kc2.
+/// GS 8 L dipakai jika parameter tidak muat di pL pH (> 65535 byte).
+pub fn esc_define_graphic(buf: &mut Vec<u8>, key: [u8; 2], width: u32, height: u32, data: &[u8]) -> Result<(), ProxyError> {
+    check_graphic(width, height, data.len())?;
+    // m fn a kc1 kc2 b xL xH yL yH c + data
+    let len = 11 + data.len();
+    match u16::try_from(len) {
+        Ok(len) => {
+            buf.extend_from_slice(&[0x1D, b'(', b'L']);
+            buf.extend_from_slice(&len.to_le_bytes());
+        }
+        Err(_) => {
+            buf.extend_from_slice(&[0x1D, b'8', b'L']);
+            buf.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    buf.extend_from_slice(&[0x30, 0x53, 0x30, key[0], key[1], 0x01]);
+    buf.extend_from_slice(&(width as u16).to_le_bytes());
+    buf.extend_from_slice(&(height as u16).to_le_bytes());
+    buf.push(0x31);
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+/// GS ( L fn 85 - cetak graphic dari download graphics area, skala 1x1
+pub fn esc_print_graphic(buf: &mut Vec<u8>, key: [u8; 2]) {
+    buf.extend_from_slice(&[0x1D, b'(', b'L', 0x06, 0x00, 0x30, 0x55, key[0], key[1], 0x01, 0x01]);
+}
+
+/// Global image limits, dibaca sekali dari environment
+pub static IMAGE_LIMITS: Lazy<ImageLimits> = Lazy::new(ImageLimits::from_env);
+
 /// GS v 0 m xL xH yL yH data
 /// data = bitmap 1bpp, row-major, MSB=left (default ESC/POS)
 pub fn esc_raster_image(
@@ -77,8 +1226,8 @@ pub fn esc_raster_image(
     data: &[u8],
     scale_m: u8,
 ) -> Result<(), ProxyError> {
-    let x_bytes = ((width + 7) / 8) as usize;
-    let expected = x_bytes * height as usize;
+    let expected = IMAGE_LIMITS.check(width, height)?;
+    let x_bytes = width.div_ceil(8) as usize;
     if data.len() != expected {
         return Err(ProxyError::BadPayload(format!(
             "Ukuran data gambar tidak cocok (got {}, expected {} by bytes/row {})",
@@ -104,6 +1253,25 @@ fn bit_reverse_byte(mut b: u8) -> u8 {
     b
 }
 
+/// Putar bitmap 1bpp 180° (ESC { tidak berlaku untuk raster image)
+fn rotate_bitmap_180(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let x_bytes = width.div_ceil(8) as usize;
+    let width = width as usize;
+    let height = height as usize;
+    let mut out = vec![0u8; data.len()];
+    for y in 0..height {
+        let src_row = &data[(height - 1 - y) * x_bytes..(height - y) * x_bytes];
+        let dst_row = &mut out[y * x_bytes..(y + 1) * x_bytes];
+        for x in 0..width {
+            let sx = width - 1 - x;
+            if src_row[sx / 8] & (0x80 >> (sx % 8)) != 0 {
+                dst_row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    out
+}
+
 /// Apply optional invert and bit-order fix
 fn transform_bitmap(
     mut data: Vec<u8>,
@@ -125,24 +1293,32 @@ fn transform_bitmap(
 
 /* ===================== ePOS-Print SOAP Parsing ===================== */
 
-#[derive(Debug, Clone)]
+fn serialize_len<S: serde::Serializer>(data: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(data.len() as u64)
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ImageSpec {
     pub width: u32,
     pub height: u32,
     pub align: Align,
     pub gap_lines: u8,     // feed setelah gambar
     pub scale_m: u8,       // 0:1x, 1:2w, 2:2h, 3:2x
-    #[allow(dead_code)]
     pub invert: bool,      // invert bit
-    #[allow(dead_code)]
     pub bit_order: BitOrder,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dither: Option<Dither>, // hanya untuk input grayscale
+    #[serde(rename = "bitmap_bytes", serialize_with = "serialize_len")]
     pub bitmap: Vec<u8>,   // packed 1bpp
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct EposDoc {
     pub images: Vec<ImageSpec>,
-    pub cut: Option<String>, // "feed" / "full"/"partial"/...
+    pub cut: Option<String>, // "feed" / "no_feed" / "reserve" / "full" / "partial" / "feed_full" / "feed_partial"
+    /// Atribut `feed` pada `<cut>`: jumlah baris feed sebelum cut
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cut_feed: Option<u8>,
 }
 
 fn parse_align(val: &str) -> Align {
@@ -153,15 +1329,74 @@ fn parse_align(val: &str) -> Align {
     }
 }
 
-fn parse_scale(val: &str) -> u8 {
-    match val.to_ascii_lowercase().as_str() {
-        "2w" => 1,
-        "2h" => 2,
-        "2x" | "2" => 3,
-        _ => 0,
+/// Faktor skala gambar per sumbu (1..=8)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ImageScale {
+    width: u8,
+    height: u8,
+}
+
+impl ImageScale {
+    const ONE: ImageScale = ImageScale { width: 1, height: 1 };
+    const MAX: u8 = 8;
+
+    /// Bit `m` GS v 0 (hanya 2x per sumbu) dan sisa faktor yang dikerjakan secara software.
+    /// Faktor genap memakai 2x hardware supaya bitmap yang dikirim lebih kecil.
+    fn split(self) -> (u8, u32, u32) {
+        let axis = |f: u8| if f.is_multiple_of(2) { (true, (f / 2) as u32) } else { (false, f as u32) };
+        let (hw_w, soft_w) = axis(self.width);
+        let (hw_h, soft_h) = axis(self.height);
+        (hw_w as u8 | (hw_h as u8) << 1, soft_w, soft_h)
     }
 }
 
+/// `scale`: "2"/"Nx" (kedua sumbu), "Nw" (lebar), "Nh" (tinggi), atau "WxH" (mis. "3x2"), N = 1..8.
+/// Nilai tidak dikenal dicatat sebagai warning dan dicetak 1x.
+fn parse_scale(val: &str) -> ImageScale {
+    let v = val.trim().to_ascii_lowercase();
+    let factor = |s: &str| s.parse::<u8>().ok().filter(|f| (1..=ImageScale::MAX).contains(f));
+    let parsed = if let Some(n) = v.strip_suffix('w') {
+        factor(n).map(|w| ImageScale { width: w, height: 1 })
+    } else if let Some(n) = v.strip_suffix('h') {
+        factor(n).map(|h| ImageScale { width: 1, height: h })
+    } else if let Some(n) = v.strip_suffix('x') {
+        factor(n).map(|f| ImageScale { width: f, height: f })
+    } else if let Some((w, h)) = v.split_once('x') {
+        factor(w).zip(factor(h)).map(|(w, h)| ImageScale { width: w, height: h })
+    } else {
+        factor(&v).map(|f| ImageScale { width: f, height: f })
+    };
+    parsed.unwrap_or_else(|| {
+        warn!("⚠️ <image scale=\"{}\"> tidak dikenal (1-8: \"Nx\", \"Nw\", \"Nh\", \"WxH\"), dicetak 1x", val);
+        ImageScale::ONE
+    })
+}
+
+/// Perbesar bitmap 1bpp MSB-first dengan menduplikasi piksel (`sw` x `sh`)
+fn upscale_bitmap(data: &[u8], width: u32, height: u32, sw: u32, sh: u32) -> Vec<u8> {
+    let src_row = width.div_ceil(8) as usize;
+    let new_width = width * sw;
+    let dst_row = new_width.div_ceil(8) as usize;
+    let mut out = Vec::with_capacity(dst_row * (height * sh) as usize);
+    let mut row = vec![0u8; dst_row];
+    for y in 0..height as usize {
+        row.fill(0);
+        let src = &data[y * src_row..(y + 1) * src_row];
+        for x in 0..width {
+            if src[(x / 8) as usize] & (0x80 >> (x % 8)) != 0 {
+                for dx in 0..sw {
+                    let nx = x * sw + dx;
+                    row[(nx / 8) as usize] |= 0x80 >> (nx % 8);
+                }
+            }
+        }
+        for _ in 0..sh {
+            out.extend_from_slice(&row);
+        }
+    }
+    out
+}
+
 fn parse_bool(val: &str) -> bool {
     matches!(
         val.to_ascii_lowercase().as_str(),
@@ -177,11 +1412,28 @@ fn parse_bit_order(val: &str) -> BitOrder {
     }
 }
 
+/// Atribut `<cut type=".." feed="..">`
+fn parse_cut_attrs(e: &BytesStart, cut: &mut Option<String>, cut_feed: &mut Option<u8>) {
+    for a in e.attributes().flatten() {
+        let val = a.unescape_value().unwrap_or_default().to_string();
+        match a.key.as_ref().to_ascii_lowercase().as_slice() {
+            b"type" => *cut = Some(val),
+            b"feed" => *cut_feed = val.parse().ok(),
+            _ => {}
+        }
+    }
+}
+
 /// Parse SOAP ePOS-Print menjadi EposDoc (multi-image + cut)
+///
+/// `override_invert`/`override_bit` sudah berisi override request yang dilapis di atas default printer
+/// (lihat `JobOptions`); jika `None`, atribut per `<image>` yang dipakai.
+/// `strict_image`: bitmap yang ukurannya tidak sama dengan width x height ditolak, bukan di-pad/dipotong.
 pub fn parse_epos_soap(
     body: &[u8],
     override_invert: Option<bool>,
     override_bit: Option<BitOrder>,
+    strict_image: bool,
 ) -> Result<EposDoc, ProxyError> {
     let mut reader = Reader::from_reader(body);
     reader.config_mut().trim_text(true);
@@ -194,26 +1446,35 @@ pub fn parse_epos_soap(
     let mut current_height: u32 = 0;
     let mut current_align = Align::Left;
     let mut current_gap: u8 = 0;
-    let mut current_scale: u8 = 0;
+    let mut current_scale = ImageScale::ONE;
     let mut current_invert = false;
     let mut current_bit = BitOrder::MsbFirst;
+    let mut current_format = PixelFormat::Mono;
+    let mut current_dither: Option<Dither> = None;
+    let mut current_threshold: u8 = 128;
     let mut current_b64 = String::new();
+    let mut document_b64_len: usize = 0;
 
     let mut cut: Option<String> = None;
+    let mut cut_feed: Option<u8> = None;
 
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
                 let name = e.name().as_ref().to_ascii_lowercase();
                 if name.ends_with(b"image") {
+                    IMAGE_LIMITS.check_count(images.len())?;
                     collecting_image_text = true;
                     current_width = 0;
                     current_height = 0;
                     current_align = Align::Left;
                     current_gap = 0;
-                    current_scale = 0;
+                    current_scale = ImageScale::ONE;
                     current_invert = false;
                     current_bit = BitOrder::MsbFirst;
+                    current_format = PixelFormat::Mono;
+                    current_dither = None;
+                    current_threshold = 128;
                     current_b64.clear();
 
                     for a in e.attributes().flatten() {
@@ -227,21 +1488,25 @@ pub fn parse_epos_soap(
                             "scale" => current_scale = parse_scale(&val),
                             "invert" => current_invert = parse_bool(&val),
                             "bit_order" => current_bit = parse_bit_order(&val),
+                            "mode" => current_format = PixelFormat::parse(&val),
+                            "dither" => current_dither = Some(Dither::parse(&val)?),
+                            "threshold" => current_threshold = val.parse().unwrap_or(128),
                             _ => {}
                         }
                     }
                 } else if name.ends_with(b"cut") {
-                    for a in e.attributes().flatten() {
-                        if a.key.as_ref().eq_ignore_ascii_case(b"type") {
-                            cut = Some(a.unescape_value().unwrap_or_default().to_string());
-                        }
-                    }
+                    parse_cut_attrs(&e, &mut cut, &mut cut_feed);
                 }
             }
-            Ok(Event::Text(t)) => {
-                if collecting_image_text {
-                    current_b64.push_str(&t.unescape().unwrap_or_default());
-                }
+            // Klien ePOS biasanya mengirim <cut type="feed"/> (self-closing)
+            Ok(Event::Empty(e)) if e.name().as_ref().to_ascii_lowercase().ends_with(b"cut") => {
+                parse_cut_attrs(&e, &mut cut, &mut cut_feed);
+            }
+            Ok(Event::Text(t)) if collecting_image_text => {
+                // Guard sebelum teks diakumulasi, supaya payload raksasa tidak sempat di-buffer
+                IMAGE_LIMITS.check_base64(current_b64.len() + t.len(), document_b64_len + t.len())?;
+                document_b64_len += t.len();
+                current_b64.push_str(&t.unescape().unwrap_or_default());
             }
             Ok(Event::End(e)) => {
                 let name = e.name().as_ref().to_ascii_lowercase();
@@ -254,38 +1519,89 @@ pub fn parse_epos_soap(
                         ));
                     }
 
+                    // Tolak dimensi berlebihan sebelum decode/alokasi apa pun
+                    IMAGE_LIMITS.check(current_width, current_height)?;
+                    let expected = current_format.raw_len(current_width, current_height);
+                    if current_format != PixelFormat::Mono {
+                        // Data grayscale di-pad ke `expected`, gray16 di-unpack ke 1 byte per pixel,
+                        // dan error diffusion memakai buffer i16 per pixel
+                        let pixels = current_width as usize * current_height as usize;
+                        IMAGE_LIMITS.check_buffer(expected.max(pixels), "data grayscale")?;
+                        if matches!(current_dither.unwrap_or_default(), Dither::FloydSteinberg | Dither::Atkinson) {
+                            IMAGE_LIMITS.check_buffer(pixels * std::mem::size_of::<i16>(), "buffer dithering")?;
+                        }
+                    }
+
                     let cleaned: String = current_b64.chars().filter(|c| !c.is_whitespace()).collect();
                     
-                    // Pre-allocate with estimated decoded size to avoid reallocations
+                    // Pre-allocate with estimated decoded size to avoid reallocations,
+                    // capped at the expected bitmap size (extra bytes are truncated anyway)
                     let estimated_decoded_size = (cleaned.len() * 3) / 4; // Base64 decode ratio
-                    let mut bitmap = Vec::with_capacity(estimated_decoded_size);
+                    let mut bitmap = Vec::with_capacity(estimated_decoded_size.min(expected));
                     BASE64_STANDARD.decode_vec(cleaned.trim(), &mut bitmap).map_err(|e| {
                         ProxyError::BadPayload(format!("Base64 <image> invalid: {e}"))
                     })?;
 
-                    let x_bytes = ((current_width + 7) / 8) as usize;
-                    let expected = x_bytes * current_height as usize;
+                    if strict_image && bitmap.len() != expected {
+                        return Err(ProxyError::BadPayload(format!(
+                            "<image> ke-{} ({}x{}, mode {:?}): bitmap {} byte, seharusnya {} byte",
+                            images.len() + 1,
+                            current_width,
+                            current_height,
+                            current_format,
+                            bitmap.len(),
+                            expected
+                        )));
+                    }
                     if bitmap.len() < expected {
                         let mut padded = Vec::with_capacity(expected);
                         padded.extend_from_slice(&bitmap);
-                        padded.resize(expected, 0);
+                        // Pad dengan putih: 0 untuk 1bpp, 0xFF untuk grayscale
+                        padded.resize(expected, if current_format == PixelFormat::Mono { 0 } else { 0xFF });
                         bitmap = padded;
                     } else if bitmap.len() > expected {
                         bitmap.truncate(expected);
                     }
 
                     let invert = override_invert.unwrap_or(current_invert);
-                    let bit = override_bit.unwrap_or(current_bit);
-                    let bitmap = transform_bitmap(bitmap, invert, bit);
+                    let (bit, dither, bitmap) = match current_format {
+                        PixelFormat::Mono => {
+                            let bit = override_bit.unwrap_or(current_bit);
+                            (bit, None, bitmap)
+                        }
+                        PixelFormat::Gray16 | PixelFormat::Gray8 => {
+                            let gray = if current_format == PixelFormat::Gray16 {
+                                unpack_gray16(&bitmap, current_width, current_height)
+                            } else {
+                                bitmap
+                            };
+                            let dither = current_dither.unwrap_or_default();
+                            let mono = gray_to_mono(&gray, current_width, current_height, dither, current_threshold);
+                            // Hasil dithering selalu MSB-first
+                            (BitOrder::MsbFirst, Some(dither), mono)
+                        }
+                    };
+                    let mut bitmap = transform_bitmap(bitmap, invert, bit);
+
+                    // GS v 0 hanya mendukung 2x; faktor lain diperbesar di sini sebelum dikirim
+                    let (scale_m, soft_w, soft_h) = current_scale.split();
+                    let (mut width, mut height) = (current_width, current_height);
+                    if soft_w > 1 || soft_h > 1 {
+                        IMAGE_LIMITS.check(width * soft_w, height * soft_h)?;
+                        bitmap = upscale_bitmap(&bitmap, width, height, soft_w, soft_h);
+                        width *= soft_w;
+                        height *= soft_h;
+                    }
 
                     images.push(ImageSpec {
-                        width: current_width,
-                        height: current_height,
+                        width,
+                        height,
                         align: current_align,
                         gap_lines: current_gap,
-                        scale_m: current_scale,
+                        scale_m,
                         invert,
                         bit_order: bit,
+                        dither,
                         bitmap,
                     });
                 }
@@ -303,37 +1619,73 @@ pub fn parse_epos_soap(
         ));
     }
 
-    Ok(EposDoc { images, cut })
+    Ok(EposDoc { images, cut, cut_feed })
+}
+
+/// Opsi build ESC/POS dari EposDoc
+#[derive(Debug, Clone, Copy)]
+pub struct EposBuildOptions {
+    /// Kirim ESC @ di awal job
+    pub init: bool,
+    /// Tambahkan feed + cut otomatis jika dokumen tidak punya <cut>
+    pub auto_cut: bool,
+    /// Printer terpasang terbalik: upside-down mode dan perataan kiri/kanan ditukar
+    pub rotate_180: bool,
+}
+
+impl Default for EposBuildOptions {
+    fn default() -> Self {
+        Self {
+            init: true,
+            auto_cut: true,
+            rotate_180: false,
+        }
+    }
 }
 
 /// Bangun ESC/POS dari EposDoc
-pub fn build_escpos_from_epos_doc(doc: &EposDoc) -> Result<Vec<u8>, ProxyError> {
+pub fn build_escpos_from_epos_doc(doc: &EposDoc, opts: EposBuildOptions) -> Result<Vec<u8>, ProxyError> {
     // Pre-calculate total capacity needed for better memory allocation
     let total_bitmap_size: usize = doc.images.iter().map(|i| i.bitmap.len()).sum();
     let estimated_commands_size = doc.images.len() * 50; // ~50 bytes per image command overhead
     let mut out = Vec::with_capacity(1024 + total_bitmap_size + estimated_commands_size);
-    esc_init(&mut out);
+    if opts.init {
+        esc_init(&mut out);
+    }
+    esc_mounted_defaults(&mut out, opts.rotate_180);
 
+    // Gambar ePOS selalu di standard mode: upside-down mode printer yang memutarnya, bukan software
     for img in &doc.images {
-        esc_align(&mut out, img.align);
+        esc_align(&mut out, img.align.mounted(opts.rotate_180));
         esc_raster_image(&mut out, img.width, img.height, &img.bitmap, img.scale_m)?;
         if img.gap_lines > 0 {
             esc_feed(&mut out, img.gap_lines);
         }
     }
 
-    esc_align(&mut out, Align::Left);
+    esc_align(&mut out, Align::Left.mounted(opts.rotate_180));
 
     if let Some(t) = &doc.cut {
-        if t.eq_ignore_ascii_case("feed") {
-            esc_feed(&mut out, 8);
-            esc_cut(&mut out, false);
-        } else if t.eq_ignore_ascii_case("partial") {
-            esc_cut(&mut out, true);
-        } else {
-            esc_cut(&mut out, false);
+        match t.to_ascii_lowercase().as_str() {
+            "feed" => {
+                esc_feed(&mut out, doc.cut_feed.unwrap_or(8));
+                esc_cut(&mut out, false);
+            }
+            "no_feed" => esc_cut(&mut out, false),
+            "reserve" => esc_cut_reserve(&mut out, doc.cut_feed.unwrap_or(0)),
+            "feed_full" => esc_cut_variant(&mut out, CutVariant::FeedAndFull { feed: doc.cut_feed.unwrap_or(0) }),
+            "feed_partial" => esc_cut_variant(&mut out, CutVariant::FeedAndPartial { feed: doc.cut_feed.unwrap_or(0) }),
+            other => {
+                if let Some(n) = doc.cut_feed.filter(|n| *n > 0) {
+                    esc_feed(&mut out, n);
+                }
+                if !matches!(other, "full" | "partial") {
+                    warn!("⚠️ Unknown <cut> type '{}', using full cut", t);
+                }
+                esc_cut(&mut out, other == "partial");
+            }
         }
-    } else {
+    } else if opts.auto_cut {
         // Auto-cut after image printing if no explicit cut command is provided
         // Add more feed lines to ensure all images are fully printed before cutting
         esc_feed(&mut out, 8);
@@ -343,28 +1695,383 @@ pub fn build_escpos_from_epos_doc(doc: &EposDoc) -> Result<Vec<u8>, ProxyError>
     Ok(out)
 }
 
-pub fn build_escpos_from_ops(ops: &[PrintOp]) -> Result<Vec<u8>, ProxyError> {
-    // Better capacity estimation based on operation types
-    let estimated_size = ops.iter().map(|op| match op {
+/// Opsi build ESC/POS dari JSON ops
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpsBuildOptions<'a> {
+    /// Printer tujuan untuk registry graphic (`define_graphic`/`print_graphic`); `None` untuk estimate
+    /// dan print ad-hoc, yang tidak menyimpan graphic
+    pub printer_id: Option<&'a str>,
+    /// Printer terpasang terbalik: upside-down mode dan perataan tertukar di awal job dan setelah setiap
+    /// init; gambar diputar software hanya saat upside-down mode printer tidak berlaku
+    pub rotate_180: bool,
+    /// Keluarga perintah vendor untuk op yang berbeda antar vendor (density)
+    pub family: PrinterFamily,
+    /// Printer CJK: teks non-ASCII dikirim lewat kanji mode dengan encoding ini
+    pub cjk: Option<CjkEncoding>,
+    /// Lebar baris dalam kolom font A: teks op `text` yang lebih lebar di-wrap (disesuaikan dengan font aktif)
+    pub chars_per_line: Option<u16>,
+}
+
+impl OpsBuildOptions<'_> {
+    /// Build ops di thread blocking (dither dan skala gambar `image_url` bisa berat)
+    pub async fn build_blocking(self, ops: Vec<PrintOp>, vars: JobVars) -> Result<Vec<u8>, ProxyError> {
+        let printer_id = self.printer_id.map(str::to_owned);
+        let OpsBuildOptions { rotate_180, family, cjk, chars_per_line, .. } = self;
+        crate::image::run_blocking(move || {
+            let opts = OpsBuildOptions { printer_id: printer_id.as_deref(), rotate_180, family, cjk, chars_per_line };
+            build_escpos_from_ops_with_vars(&ops, opts, &vars)
+        })
+        .await
+    }
+}
+
+/// Perkiraan ukuran ESC/POS satu op untuk kapasitas buffer
+fn estimated_op_size(op: &PrintOp) -> usize {
+    match op {
         PrintOp::Init => 2,
-        PrintOp::Text { data, .. } => data.len() + 1,
-        PrintOp::Feed { .. } => 3,
-        PrintOp::Cut { .. } => 3,
-    }).sum::<usize>();
+        PrintOp::Text { data, .. } => data.len() + 4,
+        PrintOp::Feed { .. } | PrintOp::FeedDots { .. } => 3,
+        PrintOp::Cut { .. } => 4,
+        PrintOp::Command { args, .. } => 8 + args.len(),
+        PrintOp::Density { .. } => 7,
+        PrintOp::ImageUrl { image, .. } => image.as_ref().map_or(0, |i| i.pixels.len() / 8 + 8),
+        PrintOp::PageBegin | PrintOp::PagePrint => 2,
+        PrintOp::SetPrintArea { .. } => 10,
+        PrintOp::SetPosition { .. } => 8,
+        PrintOp::Barcode { data, .. } => data.len() + 13,
+        PrintOp::Font { .. } => 3,
+        PrintOp::Rotate { .. } => 6,
+        PrintOp::DefineGraphic { base64, .. } => base64.len() / 4 * 3 + 16,
+        PrintOp::PrintGraphic { .. } => 11,
+        PrintOp::TestPattern { .. } => 8192,
+        PrintOp::If { then, else_, .. } => {
+            let then: usize = then.iter().map(estimated_op_size).sum();
+            let else_: usize = else_.iter().flatten().map(estimated_op_size).sum();
+            then.max(else_)
+        }
+    }
+}
+
+/// Nilai variabel job sebagai teks; `null` atau tidak ada = `None`
+fn var_text(vars: &JobVars, name: &str) -> Option<String> {
+    match vars.get(name)? {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Kondisi op `if`: `equals` (sama persis) dan/atau `not_empty`; tanpa keduanya = variabel tidak kosong
+fn eval_condition(vars: &JobVars, var: &str, equals: Option<&str>, not_empty: Option<bool>) -> bool {
+    let value = var_text(vars, var);
+    let is_empty = value.as_deref().is_none_or(str::is_empty);
+    if equals.is_none() && not_empty.is_none() {
+        return !is_empty;
+    }
+    equals.is_none_or(|e| value.as_deref() == Some(e)) && not_empty.is_none_or(|n| n != is_empty)
+}
+
+pub fn build_escpos_from_ops(ops: &[PrintOp], opts: OpsBuildOptions<'_>) -> Result<Vec<u8>, ProxyError> {
+    build_escpos_from_ops_with_vars(ops, opts, &JobVars::new())
+}
+
+/// Build ops dengan variabel job untuk op `if`
+pub fn build_escpos_from_ops_with_vars(ops: &[PrintOp], opts: OpsBuildOptions<'_>, vars: &JobVars) -> Result<Vec<u8>, ProxyError> {
+    // Better capacity estimation based on operation types
+    let estimated_size = ops.iter().map(estimated_op_size).sum::<usize>();
     let mut out = Vec::with_capacity(estimated_size.max(256));
-    
+    esc_mounted_defaults(&mut out, opts.rotate_180);
+    let mut state = EmitState {
+        page: None,
+        font: Font::A,
+        upside_down: opts.rotate_180,
+        graphics: HashMap::new(),
+    };
+    emit_ops(&mut out, ops, opts, vars, &mut state)?;
+    if state.page.is_some() {
+        return Err(ProxyError::BadPayload("page mode tidak ditutup dengan page_print".into()));
+    }
+    Ok(out)
+}
+
+/// State printer selama build ops, dibagi dengan cabang `if`
+struct EmitState {
+    /// Page mode: Some(area) selama di dalam page (area None = default printer)
+    page: Option<Option<(u16, u16)>>,
+    /// Font aktif, untuk wrap teks
+    font: Font,
+    /// Upside-down mode (ESC {) aktif; tidak berlaku untuk isi page mode
+    upside_down: bool,
+    /// Graphic yang didefinisikan job ini (id -> key), belum tentu sudah ada di registry
+    graphics: HashMap<String, [u8; 2]>,
+}
+
+/// Tulis ops ke `out`; state dibagi dengan cabang `if` supaya page bisa dibuka/ditutup di level
+/// berbeda dan wrap teks mengikuti font yang dipilih di cabang
+fn emit_ops(out: &mut Vec<u8>, ops: &[PrintOp], opts: OpsBuildOptions<'_>, vars: &JobVars, state: &mut EmitState) -> Result<(), ProxyError> {
     for op in ops {
         match op {
-            PrintOp::Init => esc_init(&mut out),
-            PrintOp::Text { data, newline } => esc_text_line(&mut out, data, newline.unwrap_or(true)),
-            PrintOp::Feed { lines } => esc_feed(&mut out, *lines),
-            PrintOp::Cut { mode } => {
-                let partial = matches!(mode.as_deref(), Some("partial" | "PARTIAL" | "p"));
-                esc_cut(&mut out, partial);
+            PrintOp::Init => {
+                esc_init(out);
+                // ESC @ juga membatalkan page mode dan kembali ke font A
+                state.page = None;
+                state.font = Font::A;
+                // ESC @ mereset upside-down mode dan perataan
+                esc_mounted_defaults(out, opts.rotate_180);
+                state.upside_down = opts.rotate_180;
+            }
+            PrintOp::Text { data, newline, rtl, lang } => {
+                let wrapped;
+                let data = match opts.chars_per_line {
+                    Some(width) => {
+                        wrapped = wrap_text(data, state.font.columns(width as usize));
+                        &wrapped
+                    }
+                    None => data,
+                };
+                let newline = newline.unwrap_or(true);
+                match (rtl, lang) {
+                    (None, None) => match opts.cjk {
+                        Some(encoding) => esc_text_cjk(out, data, newline, encoding),
+                        None => esc_text_line(out, data, newline),
+                    },
+                    (rtl, lang) => {
+                        let lang = lang.as_deref().map(TextLang::parse).transpose()?;
+                        let rtl = rtl.unwrap_or_else(|| lang.is_some_and(TextLang::is_rtl));
+                        esc_text_lang(out, data, newline, rtl, lang);
+                    }
+                }
+            }
+            PrintOp::Feed { lines } => esc_feed(out, *lines),
+            PrintOp::FeedDots { dots } => esc_feed_dots(out, *dots),
+            PrintOp::Cut { mode, feed } => esc_cut_variant(out, CutVariant::from_mode(mode.as_deref(), *feed)),
+            PrintOp::Command { name, args } => {
+                esc_named_command(out, name, args)?;
+                if name == "upside_down" {
+                    state.upside_down = args.first().is_some_and(|n| n & 1 == 1);
+                }
+            }
+            PrintOp::Density { level } => esc_density(out, opts.family, *level),
+            PrintOp::ImageUrl { url, max_width, image } => {
+                let image = image.as_ref().ok_or_else(|| {
+                    error!("❌ image_url {} was not prefetched before build", url);
+                    ProxyError::Internal
+                })?;
+                let scaled = downscale_gray(image, max_width.unwrap_or(DEFAULT_IMAGE_URL_WIDTH));
+                let bitmap = gray_to_mono(&scaled.pixels, scaled.width, scaled.height, Dither::FloydSteinberg, 128);
+                // Upside-down mode printer sudah memutar gambar di standard mode; software hanya saat mode itu tidak berlaku
+                let native = state.upside_down && state.page.is_none();
+                let bitmap = if opts.rotate_180 && !native {
+                    rotate_bitmap_180(&bitmap, scaled.width, scaled.height)
+                } else {
+                    bitmap
+                };
+                esc_raster_image(out, scaled.width, scaled.height, &bitmap, 0)?;
+            }
+            PrintOp::PageBegin => {
+                if state.page.is_some() {
+                    return Err(ProxyError::BadPayload("page_begin di dalam page mode yang belum dicetak".into()));
+                }
+                state.page = Some(None);
+                esc_page_begin(out);
+            }
+            PrintOp::SetPrintArea { x, y, w, h } => {
+                let Some(area) = state.page.as_mut() else {
+                    return Err(ProxyError::BadPayload("set_print_area hanya berlaku setelah page_begin".into()));
+                };
+                if *w == 0 || *h == 0 {
+                    return Err(ProxyError::BadPayload("set_print_area: w dan h harus > 0".into()));
+                }
+                *area = Some((*w, *h));
+                esc_print_area(out, *x, *y, *w, *h);
+            }
+            PrintOp::SetPosition { x, y } => {
+                let Some(area) = state.page else {
+                    return Err(ProxyError::BadPayload("set_position hanya berlaku setelah page_begin".into()));
+                };
+                // Posisi relatif terhadap origin area cetak
+                if let Some((w, h)) = area {
+                    if *x >= w || *y >= h {
+                        return Err(ProxyError::BadPayload(format!(
+                            "set_position ({}, {}) di luar area cetak {}x{}",
+                            x, y, w, h
+                        )));
+                    }
+                }
+                esc_position(out, *x, *y);
+            }
+            PrintOp::Barcode { symbology, data, height, width, hri, auto_check_digit } => {
+                let symbology = BarcodeSymbology::parse(symbology)?;
+                let with_check;
+                let data = match auto_check_digit {
+                    Some(true) => {
+                        with_check = symbology.with_check_digit(data)?;
+                        &with_check
+                    }
+                    _ => data,
+                };
+                esc_barcode(out, symbology, data, *height, *width, hri.as_deref())?;
+            }
+            PrintOp::Font { name } => {
+                state.font = Font::parse(name)?;
+                esc_font(out, state.font);
+            }
+            PrintOp::Rotate { mode } => state.upside_down = esc_rotation(out, Rotation::parse(mode)?, opts.rotate_180),
+            PrintOp::DefineGraphic { id, base64, width, height } => {
+                IMAGE_LIMITS.check_base64(base64.len(), base64.len())?;
+                let bitmap = BASE64_STANDARD
+                    .decode(base64.trim())
+                    .map_err(|e| ProxyError::BadPayload(format!("define_graphic '{}': base64 invalid: {}", id, e)))?;
+                let key = match opts.printer_id {
+                    Some(printer_id) => {
+                        let graphic = crate::graphics::stage(printer_id, id, *width, *height, &bitmap)?;
+                        out.extend_from_slice(graphic.define_bytes());
+                        graphic.key
+                    }
+                    None => {
+                        let key = state.graphics.get(id).copied().unwrap_or_else(|| crate::graphics::detached_key(state.graphics.len()));
+                        out.extend_from_slice(&crate::graphics::define_detached(key, id, *width, *height, &bitmap)?);
+                        key
+                    }
+                };
+                state.graphics.insert(id.clone(), key);
+            }
+            PrintOp::PrintGraphic { id } => {
+                let key = match (state.graphics.get(id), opts.printer_id) {
+                    (Some(key), _) => *key,
+                    (None, Some(printer_id)) => crate::graphics::key_of(printer_id, id)?,
+                    // Tanpa registry (estimate, ad-hoc): key sementara, yang berarti hanya ukuran perintahnya
+                    (None, None) => crate::graphics::detached_key(0),
+                };
+                esc_print_graphic(out, key)
+            }
+            PrintOp::TestPattern { kind } => {
+                if state.page.is_some() {
+                    return Err(ProxyError::BadPayload("test_pattern tidak bisa dipakai di dalam page mode".into()));
+                }
+                esc_test_pattern(out, TestPatternKind::parse(kind.as_deref())?, opts.family, opts.rotate_180)?;
+            }
+            PrintOp::If { var, equals, not_empty, then, else_ } => {
+                let matched = eval_condition(vars, var, equals.as_deref(), *not_empty);
+                debug!("🔀 if '{}' -> {}", var, if matched { "then" } else { "else" });
+                if let Some(branch) = if matched { Some(then) } else { else_.as_ref() } {
+                    emit_ops(out, branch, opts, vars, state)?;
+                }
+            }
+            PrintOp::PagePrint => {
+                if state.page.take().is_none() {
+                    return Err(ProxyError::BadPayload("page_print tanpa page_begin".into()));
+                }
+                esc_page_print(out);
             }
         }
     }
-    Ok(out)
+    Ok(())
+}
+
+/// Sisipkan header setelah ESC @ di awal job (supaya tidak ikut di-reset) dan footer
+/// sebelum feed + cut di akhir job (supaya tidak tercetak di struk berikutnya)
+pub fn wrap_job(job: &[u8], header: &[u8], footer: &[u8]) -> Vec<u8> {
+    let head = if job.starts_with(&[0x1B, 0x40]) { 2 } else { 0 };
+    let mut tail = job.len();
+    if tail >= head + 4 && job[tail - 4..tail - 2] == [0x1D, 0x56] && matches!(job[tail - 2], 0x41 | 0x42) {
+        // GS V 65/66 n sudah termasuk feed
+        tail -= 4;
+    } else if tail >= head + 3 && job[tail - 3..tail - 1] == [0x1D, 0x56] && matches!(job[tail - 1], 0x00 | 0x01 | 0x30 | 0x31) {
+        tail -= 3;
+        if tail >= head + 3 && job[tail - 3..tail - 1] == [0x1B, 0x64] {
+            tail -= 3;
+        }
+    }
+
+    let mut out = Vec::with_capacity(job.len() + header.len() + footer.len());
+    out.extend_from_slice(&job[..head]);
+    out.extend_from_slice(header);
+    out.extend_from_slice(&job[head..tail]);
+    out.extend_from_slice(footer);
+    out.extend_from_slice(&job[tail..]);
+    out
+}
+
+/* ===================== Automatic Status Back ===================== */
+
+/// GS a n - aktifkan ASB: drawer, online/offline, error, dan sensor kertas
+pub const ASB_ENABLE: [u8; 3] = [0x1D, 0x61, 0x0F];
+
+/// DLE EOT 1 - minta status printer (real-time), dijawab satu byte
+pub const STATUS_REQUEST: [u8; 3] = [0x10, 0x04, 0x01];
+
+/// Apakah data berisi jawaban DLE EOT 1: bit 0,7 = 0, bit 1,4 = 1.
+/// Tidak bentrok dengan frame ASB (byte pertama ASB bit 1 = 0, byte lainnya bit 4 = 0).
+pub fn has_status_reply(data: &[u8]) -> bool {
+    data.iter().any(|b| b & 0x93 == 0x12)
+}
+
+/// Cari frame ASB (4 byte) terakhir dalam data yang dibaca dari printer,
+/// return deskripsi error jika printer melaporkan kondisi yang menggagalkan cetak
+pub fn asb_error(data: &[u8]) -> Option<&'static str> {
+    // Byte 1 ASB: bit 0,1,7 = 0 dan bit 4 = 1
+    let frame = data
+        .windows(4)
+        .rev()
+        .find(|w| w[0] & 0x93 == 0x10 && w[1] & 0x90 == 0 && w[2] & 0x90 == 0 && w[3] & 0x90 == 0)?;
+    if frame[2] & 0x0C != 0 {
+        Some("paper out")
+    } else if frame[0] & 0x20 != 0 {
+        Some("cover open")
+    } else if frame[1] & 0x08 != 0 {
+        Some("autocutter error")
+    } else if frame[1] & 0x24 != 0 {
+        Some("unrecoverable error")
+    } else if frame[1] & 0x40 != 0 {
+        Some("recoverable error")
+    } else if frame[0] & 0x08 != 0 {
+        Some("offline")
+    } else {
+        None
+    }
+}
+
+/// Tinggi satu baris teks (default line spacing 1/6 inch di 180 dpi)
+const LINE_FEED_DOTS: u64 = 30;
+
+/// Perkiraan panjang kertas (dalam dot) dari stream ESC/POS:
+/// LF dan `ESC d n` dihitung sebagai baris, `GS v 0` sebagai tinggi raster
+pub fn estimate_feed_dots(bytes: &[u8]) -> u64 {
+    let mut dots = 0u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x0A => {
+                dots += LINE_FEED_DOTS;
+                i += 1;
+            }
+            0x1B if bytes.get(i + 1) == Some(&b'd') => {
+                dots += LINE_FEED_DOTS * bytes.get(i + 2).copied().unwrap_or(0) as u64;
+                i += 3;
+            }
+            0x1B if bytes.get(i + 1) == Some(&b'J') => {
+                dots += bytes.get(i + 2).copied().unwrap_or(0) as u64;
+                i += 3;
+            }
+            // GS V 65/66 n: feed ke posisi cut + n (motion unit ~ 1 dot)
+            0x1D if bytes.get(i + 1) == Some(&0x56) && matches!(bytes.get(i + 2), Some(0x41 | 0x42)) => {
+                dots += bytes.get(i + 3).copied().unwrap_or(0) as u64;
+                i += 4;
+            }
+            0x1D if bytes.get(i + 1) == Some(&0x76) && bytes.get(i + 2) == Some(&0x30) && i + 8 <= bytes.len() => {
+                let m = bytes[i + 3];
+                let x_bytes = bytes[i + 4] as usize | (bytes[i + 5] as usize) << 8;
+                let height = bytes[i + 6] as u64 | (bytes[i + 7] as u64) << 8;
+                // bit 1 dari m = double height
+                dots += if m & 0x02 != 0 { height * 2 } else { height };
+                // Lewati data bitmap supaya byte 0x0A di dalamnya tidak dihitung sebagai LF
+                i += 8 + x_bytes * height as usize;
+            }
+            _ => i += 1,
+        }
+    }
+    dots
 }
 
 // Re-export parsing utilities for use in handlers
@@ -0,0 +1,82 @@
+//! Fault injection untuk menguji timeout/retry client (`TEST_FAULT_INJECTION=true`).
+//! Query `_delay_ms`, `_fail`, `_fail_rate` pada print endpoint; tanpa env flag semuanya diabaikan.
+
+use crate::errors::ProxyError;
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Batas `_delay_ms` supaya request uji tidak menggantung selamanya
+const MAX_DELAY_MS: u64 = 120_000;
+
+/// Jenis error yang bisa disuntikkan lewat `_fail`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Offline,
+    Io,
+    BadPayload,
+}
+
+impl Fault {
+    fn parse(value: &str) -> Result<Self, ProxyError> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "offline" => Ok(Fault::Offline),
+            "io" => Ok(Fault::Io),
+            "badpayload" | "bad_payload" => Ok(Fault::BadPayload),
+            other => Err(ProxyError::BadPayload(format!("_fail '{}' tidak dikenal (offline, io, badpayload)", other))),
+        }
+    }
+
+    fn into_error(self, printer_id: &str) -> ProxyError {
+        match self {
+            Fault::Offline => ProxyError::PrinterOffline(printer_id.to_string()),
+            Fault::Io => ProxyError::Io(format!("fault injection: io error ke printer '{}'", printer_id)),
+            Fault::BadPayload => ProxyError::BadPayload("fault injection: payload ditolak".into()),
+        }
+    }
+}
+
+/// Angka acak 0..1 tanpa dependency tambahan (cukup untuk `_fail_rate`, bukan kriptografi)
+fn roll() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Jalankan fault dari query sebelum printer disentuh: tunda `_delay_ms`, lalu gagal dengan `_fail`
+/// (default `io` jika hanya `_fail_rate` yang diisi) dengan peluang `_fail_rate` (default 1).
+/// `Ok` berarti request lanjut diproses normal.
+pub async fn inject(query: &HashMap<String, String>, printer_id: &str) -> Result<(), ProxyError> {
+    if let Some(delay) = query.get("_delay_ms") {
+        let ms: u64 = delay
+            .trim()
+            .parse()
+            .map_err(|_| ProxyError::BadPayload(format!("_delay_ms harus angka, got '{}'", delay)))?;
+        let ms = ms.min(MAX_DELAY_MS);
+        info!("🧪 Fault injection: delaying request for '{}' by {} ms", printer_id, ms);
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+    }
+
+    let fault = query.get("_fail").map(|v| Fault::parse(v)).transpose()?;
+    let rate = match query.get("_fail_rate") {
+        Some(v) => v
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|r| (0.0..=1.0).contains(r))
+            .ok_or_else(|| ProxyError::BadPayload(format!("_fail_rate harus 0..1, got '{}'", v)))?,
+        None if fault.is_some() => 1.0,
+        None => return Ok(()),
+    };
+    if roll() < rate {
+        let fault = fault.unwrap_or(Fault::Io);
+        warn!("🧪 Fault injection: failing request for '{}' with {:?} (rate {})", printer_id, fault, rate);
+        return Err(fault.into_error(printer_id));
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use crate::{
+    errors::ProxyError,
+    escpos::{split_data_uri, PrintOp},
+    image::{decode_png, GrayImage},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use futures::{future::BoxFuture, FutureExt};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// Batas fetch gambar untuk op `image_url` (env `IMAGE_URL_*`)
+struct FetchConfig {
+    /// Host yang boleh di-fetch (`IMAGE_URL_ALLOWED_HOSTS`, dipisah koma). Kosong = fitur nonaktif.
+    allowed_hosts: Vec<String>,
+    timeout: Duration,
+    max_bytes: usize,
+    cache_ttl: Duration,
+}
+
+impl FetchConfig {
+    fn from_env() -> Self {
+        let s = crate::settings::settings();
+        Self {
+            allowed_hosts: s.image_url_allowed_hosts.clone(),
+            timeout: Duration::from_secs(s.image_url_timeout_secs),
+            max_bytes: s.image_url_max_bytes,
+            cache_ttl: Duration::from_secs(s.image_url_cache_secs),
+        }
+    }
+}
+
+static FETCH_CONFIG: Lazy<FetchConfig> = Lazy::new(FetchConfig::from_env);
+
+/// Gambar hasil fetch per URL, disimpan sebentar supaya logo yang sama tidak di-download tiap job
+static IMAGE_CACHE: Lazy<DashMap<String, (Instant, Arc<GrayImage>)>> = Lazy::new(DashMap::new);
+
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(FETCH_CONFIG.timeout)
+        // Redirect bisa dipakai untuk keluar dari allowlist
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("HTTP client init")
+});
+
+/// Pastikan URL memakai http/https dan host-nya ada di allowlist (mencegah SSRF)
+fn check_allowed(url: &str) -> Result<reqwest::Url, ProxyError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| ProxyError::BadPayload(format!("image_url '{}' invalid: {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ProxyError::BadPayload(format!("image_url harus http/https: {}", url)));
+    }
+    let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+    if !FETCH_CONFIG.allowed_hosts.contains(&host) {
+        warn!("🚫 image_url host '{}' not in IMAGE_URL_ALLOWED_HOSTS", host);
+        return Err(ProxyError::BadPayload(format!(
+            "Host '{}' tidak diizinkan untuk image_url (IMAGE_URL_ALLOWED_HOSTS)",
+            host
+        )));
+    }
+    Ok(parsed)
+}
+
+/// Download dan decode gambar PNG dari URL (dengan cache singkat)
+pub async fn fetch_image(url: &str) -> Result<Arc<GrayImage>, ProxyError> {
+    let parsed = check_allowed(url)?;
+
+    if let Some(entry) = IMAGE_CACHE.get(url) {
+        if entry.0.elapsed() < FETCH_CONFIG.cache_ttl {
+            debug!("🎯 image_url cache hit: {}", url);
+            return Ok(entry.1.clone());
+        }
+    }
+
+    info!("🌐 Fetching image from {}", url);
+    let mut response = HTTP_CLIENT
+        .get(parsed)
+        .send()
+        .await
+        .map_err(|e| ProxyError::BadPayload(format!("Fetch image_url {} gagal: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(ProxyError::BadPayload(format!(
+            "Fetch image_url {} gagal: HTTP {}",
+            url,
+            response.status()
+        )));
+    }
+
+    let max = FETCH_CONFIG.max_bytes;
+    if response.content_length().is_some_and(|len| len as usize > max) {
+        return Err(ProxyError::BadPayload(format!("image_url {} melebihi batas {} bytes", url, max)));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| ProxyError::BadPayload(format!("Fetch image_url {} gagal: {}", url, e)))?
+    {
+        if body.len() + chunk.len() > max {
+            return Err(ProxyError::BadPayload(format!("image_url {} melebihi batas {} bytes", url, max)));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let len = body.len();
+    let image = Arc::new(crate::image::run_blocking(move || decode_png(&body)).await?);
+    debug!("🖼️ Decoded {} ({} bytes) -> {:?}", url, len, image);
+
+    IMAGE_CACHE.retain(|_, (at, _)| at.elapsed() < FETCH_CONFIG.cache_ttl);
+    IMAGE_CACHE.insert(url.to_string(), (Instant::now(), image.clone()));
+    Ok(image)
+}
+
+/// Decode gambar dari data URI (tanpa network, jadi tidak butuh allowlist); format dari MIME type
+fn decode_data_uri(uri: &str) -> Result<GrayImage, ProxyError> {
+    let Some((mime, data)) = split_data_uri(uri) else {
+        return Err(ProxyError::BadPayload("data URI image_url harus berbentuk data:<mime>;base64,<data>".into()));
+    };
+    let bytes = BASE64_STANDARD
+        .decode(data.trim())
+        .map_err(|e| ProxyError::BadPayload(format!("data URI base64 invalid: {}", e)))?;
+    if bytes.len() > FETCH_CONFIG.max_bytes {
+        return Err(ProxyError::BadPayload(format!("data URI melebihi batas {} bytes", FETCH_CONFIG.max_bytes)));
+    }
+    match mime.to_ascii_lowercase().as_str() {
+        "image/png" => decode_png(&bytes),
+        other => Err(ProxyError::BadPayload(format!("data URI format '{}' tidak didukung (image/png)", other))),
+    }
+}
+
+/// Fetch semua op `image_url` sebelum ops dibangun menjadi ESC/POS (termasuk di dalam cabang `if`)
+pub fn prefetch_images(ops: &mut [PrintOp]) -> BoxFuture<'_, Result<(), ProxyError>> {
+    async move {
+        for op in ops.iter_mut() {
+            match op {
+                PrintOp::ImageUrl { url, image, .. } => {
+                    if url.trim_start().starts_with("data:") {
+                        debug!("🖼️ Decoding image_url data URI ({} chars)", url.len());
+                        let uri = url.clone();
+                        *image = Some(Arc::new(crate::image::run_blocking(move || decode_data_uri(&uri)).await?));
+                    } else {
+                        *image = Some(fetch_image(url).await?);
+                    }
+                }
+                PrintOp::If { then, else_, .. } => {
+                    prefetch_images(then).await?;
+                    if let Some(else_) = else_ {
+                        prefetch_images(else_).await?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    .boxed()
+}
@@ -0,0 +1,275 @@
+use crate::{
+    errors::ProxyError,
+    escpos::{check_graphic, esc_define_graphic, wrap_job},
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tracing::{debug, info};
+
+/// Graphic yang didefinisikan lewat op `define_graphic`, disimpan di proxy supaya job berikutnya cukup
+/// mengirim id-nya (`print_graphic`)
+#[derive(Debug)]
+pub struct Graphic {
+    /// Key download graphics di printer (kc1 kc2)
+    pub key: [u8; 2],
+    /// Naik setiap isi graphic berubah, supaya koneksi yang punya versi lama mendapat definisi baru
+    version: u64,
+    /// Perintah GS ( L / GS 8 L fn 83 lengkap
+    define: Vec<u8>,
+}
+
+impl Graphic {
+    /// Perintah define untuk disisipkan ke job
+    pub fn define_bytes(&self) -> &[u8] {
+        &self.define
+    }
+}
+
+/// Key yang sudah didefinisikan di satu koneksi printer, beserta versinya
+pub type Defined = HashMap<[u8; 2], u64>;
+
+/// Apakah `print_graphic` di payload boleh dilengkapi definisi dari registry saat dikirim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicRefs {
+    /// Job dibangun proxy dari ops: definisi yang belum ada di koneksi disisipkan
+    Resolve,
+    /// Payload apa adanya (raw, ePOS, reprint, dead letter, salinan shadow): tidak pernah disisipi
+    AsIs,
+}
+
+/// Kombinasi kc1 kc2 yang valid (32..=126)
+const KEY_SPACE: usize = 95 * 95;
+
+struct Slot {
+    graphic: Arc<Graphic>,
+    /// Waktu pakai terakhir (define/print), untuk eviction LRU
+    last_used: u64,
+}
+
+/// Graphic satu printer. Define baru masuk `staged` saat job dibangun dan baru pindah ke `committed`
+/// setelah job yang membawanya berhasil ditulis ke printer.
+#[derive(Default)]
+struct PrinterGraphics {
+    committed: HashMap<String, Slot>,
+    staged: HashMap<String, Slot>,
+}
+
+impl PrinterGraphics {
+    fn slots(&self) -> impl Iterator<Item = (&String, &Slot)> {
+        self.committed.iter().chain(self.staged.iter())
+    }
+
+    /// Graphic terkirim dengan key ini
+    fn by_key(&self, key: [u8; 2]) -> Option<&Arc<Graphic>> {
+        self.committed.values().map(|s| &s.graphic).find(|g| g.key == key)
+    }
+
+    /// Key untuk id graphic baru: pakai ulang key yang sudah bebas, buang yang paling lama tidak dipakai jika penuh
+    fn allocate_key(&mut self, id: &str) -> [u8; 2] {
+        if self.committed.len() + self.staged.len() >= *MAX_GRAPHICS {
+            let lru = self
+                .slots()
+                .filter(|(gid, _)| gid.as_str() != id)
+                .min_by_key(|(_, s)| s.last_used)
+                .map(|(gid, s)| (gid.clone(), s.graphic.key));
+            if let Some((gid, key)) = lru {
+                info!("🖼️ Graphic registry full ({}), evicting least recently used '{}' (key {:?})", *MAX_GRAPHICS, gid, key);
+                self.committed.remove(&gid);
+                self.staged.remove(&gid);
+            }
+        }
+        let used: Vec<[u8; 2]> = self.slots().map(|(_, s)| s.graphic.key).collect();
+        (0..KEY_SPACE)
+            .map(|n| [32 + (n / 95) as u8, 32 + (n % 95) as u8])
+            .find(|key| !used.contains(key))
+            .expect("GRAPHICS_MAX dibatasi KEY_SPACE")
+    }
+}
+
+/// Registry graphic per id printer: id graphic yang sama di printer lain adalah graphic lain
+static REGISTRY: Lazy<DashMap<String, PrinterGraphics>> = Lazy::new(DashMap::new);
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// Jumlah maksimum id graphic per printer (`GRAPHICS_MAX`, default 64)
+static MAX_GRAPHICS: Lazy<usize> = Lazy::new(|| crate::settings::settings().graphics_max.clamp(1, KEY_SPACE));
+
+fn tick() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+fn check_id(id: &str) -> Result<(), ProxyError> {
+    if id.is_empty() || id.len() > 64 {
+        return Err(ProxyError::BadPayload("define_graphic: id harus 1-64 karakter".into()));
+    }
+    Ok(())
+}
+
+/// Siapkan definisi graphic `id` untuk job ke printer `printer_id`. Bitmap divalidasi sebelum key
+/// dialokasikan; registry baru berubah setelah job terkirim (lihat `commit`). Isi yang sama dengan
+/// versi terkirim tidak menaikkan versi.
+pub fn stage(printer_id: &str, id: &str, width: u32, height: u32, bitmap: &[u8]) -> Result<Arc<Graphic>, ProxyError> {
+    check_id(id)?;
+    check_graphic(width, height, bitmap.len())?;
+
+    let mut printer = REGISTRY.entry(printer_id.to_string()).or_default();
+    let now = tick();
+    let existing = printer.committed.get(id).or_else(|| printer.staged.get(id)).map(|s| s.graphic.key);
+    let key = match existing {
+        Some(key) => key,
+        None => printer.allocate_key(id),
+    };
+    let mut define = Vec::new();
+    esc_define_graphic(&mut define, key, width, height, bitmap)?;
+
+    if let Some(slot) = printer.committed.get_mut(id).filter(|s| s.graphic.define == define) {
+        debug!("🖼️ Graphic '{}' on '{}' unchanged, keeping version {}", id, printer_id, slot.graphic.version);
+        slot.last_used = now;
+        let graphic = slot.graphic.clone();
+        printer.staged.remove(id);
+        return Ok(graphic);
+    }
+    let graphic = Arc::new(Graphic { key, version: NEXT_VERSION.fetch_add(1, Ordering::Relaxed), define });
+    printer.staged.insert(id.to_string(), Slot { graphic: graphic.clone(), last_used: now });
+    debug!("🖼️ Graphic '{}' on '{}' staged ({}x{}, key {:?})", id, printer_id, width, height, key);
+    Ok(graphic)
+}
+
+/// Definisi graphic tanpa menyentuh registry (estimate, print ad-hoc) dengan key sementara `key`
+pub fn define_detached(key: [u8; 2], id: &str, width: u32, height: u32, bitmap: &[u8]) -> Result<Vec<u8>, ProxyError> {
+    check_id(id)?;
+    let mut define = Vec::new();
+    esc_define_graphic(&mut define, key, width, height, bitmap)?;
+    Ok(define)
+}
+
+/// Key sementara ke-`n` untuk graphic yang tidak masuk registry
+pub fn detached_key(n: usize) -> [u8; 2] {
+    let n = n % KEY_SPACE;
+    [32 + (n / 95) as u8, 32 + (n % 95) as u8]
+}
+
+/// Key printer untuk graphic `id` yang sudah terkirim ke printer. Graphic yang didefinisikan di job
+/// yang sama dicari pemanggil lebih dulu: define dari job yang belum terkirim tidak terlihat job lain.
+pub fn key_of(printer_id: &str, id: &str) -> Result<[u8; 2], ProxyError> {
+    let mut printer = REGISTRY.get_mut(printer_id);
+    match printer.as_mut().and_then(|p| p.committed.get_mut(id)) {
+        Some(slot) => {
+            slot.last_used = tick();
+            Ok(slot.graphic.key)
+        }
+        None => Err(ProxyError::BadPayload(format!("graphic '{}' belum didefinisikan (define_graphic)", id))),
+    }
+}
+
+/// Definisi (fn 83) di payload: key dan posisi perintah lengkapnya
+type Definitions = Vec<([u8; 2], Range<usize>)>;
+
+/// Perintah graphic di payload: key yang dicetak (fn 85) dan definisi (fn 83) beserta posisinya.
+/// Data definisi dilewati supaya isi bitmap tidak terbaca sebagai perintah.
+fn scan(payload: &[u8]) -> (Vec<[u8; 2]>, Definitions) {
+    let (mut printed, mut defined) = (Vec::new(), Vec::new());
+    let mut i = 0;
+    while i + 9 <= payload.len() {
+        let cmd = &payload[i..];
+        if cmd[0] != 0x1D || cmd[2] != 0x4C {
+            i += 1;
+            continue;
+        }
+        // GS ( L pL pH m fn ... / GS 8 L p1 p2 p3 p4 m fn ...
+        let (params, len) = match cmd[1] {
+            0x28 => (5, u16::from_le_bytes([cmd[3], cmd[4]]) as usize),
+            0x38 if cmd.len() >= 12 => (7, u32::from_le_bytes([cmd[3], cmd[4], cmd[5], cmd[6]]) as usize),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let body = &cmd[params..];
+        match (body.first(), body.get(1)) {
+            (Some(0x30), Some(0x55)) if len == 6 && body.len() >= 4 => printed.push([body[2], body[3]]),
+            (Some(0x30), Some(0x53)) if body.len() >= 5 => {
+                defined.push(([body[3], body[4]], i..(i + params + len).min(payload.len())))
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += params + len;
+    }
+    (printed, defined)
+}
+
+/// Sisipkan definisi graphic yang dicetak job ops tapi belum ada (atau versinya lama) di koneksi ini,
+/// mis. koneksi baru dibuat ulang setelah graphic didefinisikan lewat koneksi lain.
+/// Payload `AsIs` tidak pernah disisipi: key `GS ( L` milik client bisa bentrok dengan key proxy.
+pub fn prepare<'a>(printer_id: &str, payload: &'a [u8], refs: GraphicRefs, connection: &Defined) -> Cow<'a, [u8]> {
+    if refs == GraphicRefs::AsIs {
+        return Cow::Borrowed(payload);
+    }
+    let Some(printer) = REGISTRY.get(printer_id) else {
+        return Cow::Borrowed(payload);
+    };
+    let (printed, defined) = scan(payload);
+
+    let mut definitions = Vec::new();
+    let mut added = Vec::new();
+    for key in printed {
+        let Some(g) = printer.by_key(key) else { continue };
+        if defined.iter().any(|(k, _)| *k == key) || added.contains(&key) || connection.get(&key) == Some(&g.version) {
+            continue;
+        }
+        debug!("🖼️ Re-sending graphic definition {:?} on this connection", key);
+        definitions.extend_from_slice(g.define_bytes());
+        added.push(key);
+    }
+
+    if definitions.is_empty() {
+        Cow::Borrowed(payload)
+    } else {
+        Cow::Owned(wrap_job(payload, &definitions, &[]))
+    }
+}
+
+/// Setelah payload berhasil ditulis: definisi yang dibawanya dicatat di koneksi, dan define yang
+/// masih staged menjadi graphic terdaftar. Definisi dengan isi yang tidak dikenal (raw job client,
+/// staged yang sudah tergeser) membuat key itu dianggap belum ada di koneksi.
+pub fn commit(printer_id: &str, payload: &[u8], connection: &mut Defined) {
+    let (_, defined) = scan(payload);
+    if defined.is_empty() {
+        return;
+    }
+    let mut printer = REGISTRY.entry(printer_id.to_string()).or_default();
+    for (key, range) in defined {
+        let bytes = &payload[range];
+        if let Some(g) = printer.committed.values().map(|s| &s.graphic).find(|g| g.key == key && g.define == bytes) {
+            connection.insert(key, g.version);
+            continue;
+        }
+        let staged = printer
+            .staged
+            .iter()
+            .find(|(_, s)| s.graphic.key == key && s.graphic.define == bytes)
+            .map(|(gid, _)| gid.clone());
+        match staged.and_then(|gid| printer.staged.remove_entry(&gid)) {
+            Some((gid, slot)) => {
+                info!("🖼️ Graphic '{}' defined on '{}' (key {:?})", gid, printer_id, key);
+                connection.insert(key, slot.graphic.version);
+                printer.committed.insert(gid, slot);
+            }
+            None => {
+                connection.remove(&key);
+            }
+        }
+    }
+}
@@ -1,41 +1,214 @@
 use crate::{
-    backend::send_to_backend,
-    config::Printer,
-    errors::{ProxyError, xml_success, xml_options_no_content},
+    backend::send_to_backend_in_session,
+    config::{Backend, Printer, ResponseFormat},
+    errors::{ProxyError, job_success, xml_options_no_content},
+    graphics::GraphicRefs,
     escpos::{
-        JsonJob, parse_epos_soap, build_escpos_from_epos_doc, build_escpos_from_ops,
-        parse_bool_public, parse_bit_order_public,
+        JsonJob, EposBuildOptions, OpsBuildOptions, PrinterFamily, parse_epos_soap, build_escpos_from_epos_doc, build_escpos_from_ops,
+        BitOrder, PrintOp, parse_bool_public, parse_bit_order_public, esc_feed, esc_cut, split_data_uri, wrap_job,
     },
     health::{ensure_printer_online, check_printer_health, PrinterStatus},
+    pool::{HealthEvent, HEALTH_CACHE},
+    queue::Priority,
+    settings::Settings,
 };
 use axum::{
     body::Bytes,
-    extract::{Path, Query, State},
-    http::{HeaderMap, Method},
-    response::IntoResponse,
+    extract::{
+        rejection::{BytesRejection, FailedToBufferBody},
+        Path, Query, State,
+    },
+    http::{HeaderMap, Method, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
 };
+use dashmap::DashMap;
+use futures::{stream, Stream, StreamExt};
+use once_cell::sync::Lazy;
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
 use http::header::CONTENT_TYPE;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, RwLock};
 use tracing::{info, warn, error, debug, instrument};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use serde_json::json;
 
 #[derive(Clone)]
 pub struct AppState {
     pub printers: Arc<HashMap<String, Printer>>,
+    /// Alias -> printer id (lihat `Printer::aliases`)
+    pub aliases: Arc<HashMap<String, String>>,
+    /// Setting dari env, di-parse sekali saat startup
+    pub settings: Arc<Settings>,
+}
+
+impl AppState {
+    /// Cari printer berdasarkan id, lalu alias
+    pub fn printer(&self, id_or_alias: &str) -> Option<&Printer> {
+        self.printers.get(id_or_alias).or_else(|| {
+            self.aliases
+                .get(id_or_alias)
+                .and_then(|id| self.printers.get(id))
+        })
+    }
+}
+
+/// Sesi cetak interaktif (`?session=<id>`): koneksi dipin ke sesi, tanpa auto-init/auto-cut
+struct PrintSession {
+    id: String,
+    end: bool,
+}
+
+/// 32 byte pertama payload dalam hex untuk log verbose per printer
+fn hex_preview(bytes: &[u8]) -> String {
+    let head: Vec<String> = bytes.iter().take(32).map(|b| format!("{:02X}", b)).collect();
+    let more = if bytes.len() > 32 { " …" } else { "" };
+    format!("{}{}", head.join(" "), more)
+}
+
+/// Header override per request; nilai yang tidak valid diabaikan dengan warning (kembali ke config printer)
+fn header_override<T>(headers: &HeaderMap, name: &str, parse: fn(&str) -> Option<T>) -> Option<T> {
+    let raw = headers.get(name)?;
+    let parsed = raw.to_str().ok().map(str::trim).and_then(parse);
+    if parsed.is_none() {
+        warn!("⚠️ Ignoring invalid {} header: {:?}", name, raw);
+    }
+    parsed
+}
+
+fn parse_strict_bool(v: &str) -> Option<bool> {
+    match v.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Lebar baris yang masuk akal untuk printer struk (8..=255 karakter)
+fn parse_chars_per_line(v: &str) -> Option<u16> {
+    v.parse().ok().filter(|n| (8..=255).contains(n))
+}
+
+/// Cek `X-Payload-SHA256` (hex) terhadap body yang diterima, sebelum parsing dan sebelum dikirim ke printer
+fn verify_payload_digest(headers: &HeaderMap, body: &[u8]) -> Result<(), ProxyError> {
+    let Some(expected) = headers.get("x-payload-sha256") else {
+        return Ok(());
+    };
+    let expected = expected
+        .to_str()
+        .ok()
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| v.len() == 64 && v.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| ProxyError::BadPayload("X-Payload-SHA256 harus 64 karakter hex".into()))?;
+    let actual: String = Sha256::digest(body).iter().map(|b| format!("{:02x}", b)).collect();
+    if actual != expected {
+        warn!("🔐 Payload SHA-256 mismatch ({} bytes): expected {}, got {}", body.len(), expected, actual);
+        return Err(ProxyError::BadPayload(format!(
+            "X-Payload-SHA256 tidak cocok (expected {}, got {})",
+            expected, actual
+        )));
+    }
+    debug!("🔐 Payload SHA-256 verified ({} bytes)", body.len());
+    Ok(())
+}
+
+/// Kirim job ke printer, lewat koneksi sesi jika request berada dalam sesi
+async fn send_job(printer: &Printer, session: Option<&PrintSession>, payload: &[u8], priority: Priority, refs: GraphicRefs) -> Result<(), ProxyError> {
+    match session {
+        // Batch coalescing dikirim sebagai satu job normal, prioritas per job tidak berlaku
+        None => match printer.options.coalesce_ms.filter(|ms| *ms > 0) {
+            Some(ms) => crate::coalesce::send_coalesced(printer, payload, Duration::from_millis(ms), refs).await,
+            None => crate::queue::send_queued(printer, payload, priority, refs).await,
+        },
+        Some(s) if s.end => {
+            // Finalisasi sesi: feed + cut yang selama sesi ditahan
+            let mut buf = Vec::with_capacity(payload.len() + 6);
+            buf.extend_from_slice(payload);
+            esc_feed(&mut buf, 8);
+            esc_cut(&mut buf, false);
+            send_to_backend_in_session(printer, &s.id, &buf, true, refs).await
+        }
+        Some(s) => send_to_backend_in_session(printer, &s.id, payload, false, refs).await,
+    }
+}
+
+/// Job terakhir yang berhasil per printer (hanya printer dengan `retain_last_job: true`)
+static LAST_JOBS: Lazy<DashMap<String, Arc<Vec<u8>>>> = Lazy::new(DashMap::new);
+
+/// Simpan bytes job terakhir untuk reprint; job di atas `MAX_RETAINED_JOB_BYTES` (default 1 MiB) tidak disimpan
+fn retain_last_job(printer: &Printer, bytes: &[u8]) {
+    if !printer.options.retain_last_job.unwrap_or(false) {
+        return;
+    }
+    let max = crate::settings::settings().max_retained_job_bytes;
+    if bytes.len() > max {
+        debug!("⏭️ Job for '{}' too large to retain for reprint ({} bytes)", printer.id, bytes.len());
+        LAST_JOBS.remove(&printer.id);
+        return;
+    }
+    LAST_JOBS.insert(printer.id.clone(), Arc::new(bytes.to_vec()));
+}
+
+/// Bytes job terakhir yang berhasil dicetak ke printer (untuk reprint)
+pub fn last_job(printer_id: &str) -> Option<Arc<Vec<u8>>> {
+    LAST_JOBS.get(printer_id).map(|j| j.clone())
 }
 
-#[instrument(skip(state, body), fields(printer_id = %printer_id, method = %method, content_length = body.len()))]
+/// Setelah config berubah: buang job tersimpan printer yang dihapus atau yang `retain_last_job`-nya dimatikan
+pub fn forget_unretained_jobs(printers: &HashMap<String, Printer>) {
+    LAST_JOBS.retain(|id, _| {
+        let keep = printers.get(id).is_some_and(|p| p.options.retain_last_job.unwrap_or(false));
+        if !keep {
+            debug!("🗑️ Dropping retained job for '{}' (printer removed or retain_last_job off)", id);
+        }
+        keep
+    });
+}
+
+/// ID job untuk korelasi log dan response (`<timestamp>-<seq>`)
+fn new_job_id() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{}-{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S"),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[instrument(skip(state, body), fields(printer_id = %printer_id, method = %method, content_length = body.as_ref().map_or(0, Bytes::len), job_id = tracing::field::Empty))]
 pub async fn handle_print(
     State(state): State<Arc<RwLock<AppState>>>,
     Path(printer_id): Path<String>,
     method: Method,
     headers: HeaderMap,
     Query(query): Query<HashMap<String, String>>,
-    body: Bytes,
+    body: Result<Bytes, BytesRejection>,
 ) -> Result<impl IntoResponse, ProxyError> {
     info!("📥 Incoming request: {} {}", method, printer_id);
+    // Body dibaca penuh dulu; body terputus (client disconnect, chunked tidak selesai) dibuang
+    // supaya struk setengah jadi tidak pernah dikirim ke printer
+    let body = match body {
+        Ok(body) => body,
+        Err(BytesRejection::FailedToBufferBody(FailedToBufferBody::UnknownBodyError(e))) => {
+            warn!("✂️ Body request ke '{}' tidak lengkap, job dibuang tanpa dikirim: {}", printer_id, e);
+            return Err(ProxyError::BadPayload(format!("Body request tidak lengkap: {}", e)));
+        }
+        Err(rejection) => {
+            warn!("❌ Body request ke '{}' ditolak: {}", printer_id, rejection.body_text());
+            return Ok(rejection.into_response());
+        }
+    };
     // Preflight
     if method == Method::OPTIONS {
         debug!("🔄 Handling OPTIONS preflight request");
@@ -47,39 +220,519 @@ pub async fn handle_print(
         return Err(ProxyError::BadPayload("Gunakan POST/PUT untuk kirim data cetak".into()));
     }
 
+    if crate::admin::is_draining() {
+        warn!("🚰 Drain mode active, rejecting new job for '{}'", printer_id);
+        return Err(ProxyError::Draining);
+    }
+    let _in_flight = crate::admin::job_started();
+
+    let job_id = new_job_id();
+    tracing::Span::current().record("job_id", job_id.as_str());
+
+    // Uji retry client: latency/error buatan sebelum printer disentuh (hanya dengan TEST_FAULT_INJECTION).
+    // Dijalankan sebelum state dikunci supaya `_delay_ms` tidak menahan reload/CRUD config.
+    if crate::settings::settings().test_fault_injection {
+        let resolved = state.read().await.printer(&printer_id).map(|p| p.id.clone());
+        if let Some(resolved) = resolved {
+            crate::faults::inject(&query, &resolved)
+                .await
+                .inspect_err(|e| crate::jobs::record(&job_id, &resolved, None, body.len(), Err(e)))?;
+        }
+    }
+
     let app_state = state.read().await;
     let printer = app_state
-        .printers
-        .get(&printer_id)
+        .printer(&printer_id)
         .ok_or_else(|| {
             error!("❌ Printer '{}' tidak ditemukan", printer_id);
             ProxyError::NotFound(printer_id.clone())
         })?;
+    // Alias di URL -> pakai id asli untuk log, dead letter, dan response
+    let printer_id = printer.id.clone();
     
     info!("✅ Printer '{}' ditemukan: {:?}", printer_id, printer.backend);
-    
-    // Health check sebelum processing request
-    info!("🔍 Checking printer '{}' health status...", printer_id);
-    ensure_printer_online(printer).await?;
-    info!("✅ Printer '{}' is online and ready", printer_id);
+    if printer.verbose() {
+        info!(
+            "🔬 [{}] job {} content-type={:?} body={} bytes query={:?}",
+            printer_id,
+            job_id,
+            headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+            body.len(),
+            query
+        );
+    }
+
+    let response_format = ResponseFormat::for_printer(printer);
+
+    verify_payload_digest(&headers, &body)
+        .inspect_err(|e| crate::jobs::record(&job_id, &printer_id, None, 0, Err(e)))?;
+
+    let (invert_override, bit_override, strict_image) = image_options(&query, &headers, printer);
+
+    // Debug: kembalikan hasil parse ePOS sebagai JSON tanpa mencetak
+    if query.get("debug").is_some_and(|v| v.eq_ignore_ascii_case("parse")) {
+        info!("🐞 Debug parse requested, not printing");
+        let doc = parse_epos_soap(&body, invert_override, bit_override, strict_image)?;
+        return Ok(axum::Json(json!({
+            "printer_id": printer_id,
+            "document": doc,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        })).into_response());
+    }
+
+    let in_session = query.get("session").is_some_and(|s| !s.is_empty());
+
+    // async_ok: printer offline tidak ditolak, job dikirim di background (request > config printer)
+    let async_ok = !in_session
+        && query.get("async_ok")
+            .map(|v| parse_bool_public(v))
+            .or_else(|| header_override(&headers, "x-async-ok", parse_strict_bool))
+            .or(printer.options.async_ok)
+            .unwrap_or(false);
+
+    // Health check sebelum processing request; dengan `DISABLE_PREFLIGHT` error muncul dari pengiriman
+    let preflight = !app_state.settings.disable_preflight;
+    let online = if preflight {
+        info!("🔍 Checking printer '{}' health status...", printer_id);
+        ensure_printer_online(printer).await
+    } else {
+        debug!("⏭️ Preflight disabled, skipping health check for printer '{}'", printer_id);
+        Ok(())
+    };
+    let mut deferred = false;
+    let printer = match online {
+        Ok(()) => {
+            if preflight {
+                info!("✅ Printer '{}' is online and ready", printer_id);
+            }
+            printer
+        }
+        Err(ProxyError::PrinterOffline(id)) => match online_fallback(&app_state, printer, in_session).await {
+            Some(fallback) => {
+                warn!("🔀 Printer '{}' offline, job '{}' rerouted to fallback '{}'", printer_id, job_id, fallback.id);
+                fallback
+            }
+            None if async_ok => {
+                warn!("📮 Printer '{}' offline, job '{}' will be delivered in background (async_ok)", printer_id, job_id);
+                deferred = true;
+                printer
+            }
+            None => {
+                let e = ProxyError::PrinterOffline(id);
+                crate::jobs::record(&job_id, &printer_id, None, 0, Err(&e));
+                return Err(e);
+            }
+        },
+        Err(e) => {
+            crate::jobs::record(&job_id, &printer_id, None, 0, Err(&e));
+            return Err(e);
+        }
+    };
+    // Mulai di sini semua mengikuti printer yang benar-benar mencetak (bisa fallback)
+    let printer_id = printer.id.clone();
+    let (invert_override, bit_override, strict_image) = image_options(&query, &headers, printer);
+
+    let session = query.get("session").filter(|s| !s.is_empty()).map(|id| PrintSession {
+        id: id.clone(),
+        end: query.get("session_end").is_some_and(|v| parse_bool_public(v)),
+    });
 
-    // Override opsional (query/header) - optimized parsing
-    let invert_override = query.get("invert")
+    if let Some(s) = &session {
+        info!("📌 Session '{}' (end: {})", s.id, s.end);
+        if s.end && body.is_empty() {
+            send_job(printer, Some(s), &[], Priority::Normal, GraphicRefs::AsIs).await?;
+            info!("✅ Session '{}' finalized on printer '{}'", s.id, printer_id);
+            return Ok(job_success(response_format, &job_id, &printer_id, 0));
+        }
+    }
+
+    // Auto-cut ePOS: override request (`no_cut`/`X-No-Cut`, `X-Auto-Cut`) > `auto_cut` printer > aktif.
+    // `no_cut` untuk struk kontinu yang digabung lintas request
+    let no_cut = query.get("no_cut")
         .map(|v| parse_bool_public(v))
         .or_else(|| {
-            headers.get("x-escpos-invert")
+            headers.get("x-no-cut")
                 .and_then(|h| h.to_str().ok())
                 .map(parse_bool_public)
         });
+    let auto_cut = no_cut
+        .map(|no_cut| !no_cut)
+        .or_else(|| header_override(&headers, "x-auto-cut", parse_strict_bool))
+        .or(printer.options.auto_cut)
+        .unwrap_or(true);
+    if !auto_cut {
+        debug!("✂️ Auto-cut disabled for this request");
+    }
+
+    // Lebar baris untuk wrap teks: `X-Chars-Per-Line` > `chars_per_line` printer > tanpa wrap
+    let chars_per_line = header_override(&headers, "x-chars-per-line", parse_chars_per_line)
+        .or(printer.options.chars_per_line);
 
-    let bit_override = query.get("bit")
+    let rotate_180 = printer.options.rotate_180.unwrap_or(false);
+    let opts = JobOptions {
+        invert: invert_override,
+        bit_order: bit_override,
+        strict_image,
+        epos: EposBuildOptions {
+            init: session.is_none(),
+            auto_cut: session.is_none() && auto_cut,
+            rotate_180,
+        },
+        ops: OpsBuildOptions {
+            printer_id: Some(&printer.id),
+            rotate_180,
+            family: PrinterFamily::from_model(printer.options.model.as_deref()),
+            cjk: printer.options.cjk_encoding,
+            chars_per_line,
+        },
+        allow_raw: PayloadMode::Raw.allowed_for(printer),
+    };
+
+    // Mode yang tidak diizinkan ditolak sebelum build (fetch image_url, decode/dither gambar)
+    if let Some(mode) = PayloadMode::detect(&headers, &body).filter(|m| !m.allowed_for(printer)) {
+        let err = mode_forbidden(mode, &printer_id);
+        crate::jobs::record(&job_id, &printer_id, Some(mode.name()), body.len(), Err(&err));
+        return Err(err);
+    }
+
+    let (mode, bytes) = build_request_payload(&headers, &body, &opts)
+        .await
+        .inspect_err(|e| crate::jobs::record(&job_id, &printer_id, None, body.len(), Err(e)))?;
+
+    // Prioritas di antrean printer: `?priority=` > field `priority` job JSON > normal
+    let priority = match query.get("priority") {
+        Some(v) => Priority::parse(v),
+        None if mode == PayloadMode::Json => json_job_priority(&body),
+        None => Ok(Priority::Normal),
+    }
+    .inspect_err(|e| crate::jobs::record(&job_id, &printer_id, Some(mode.name()), bytes.len(), Err(e)))?;
+    if priority != Priority::Normal {
+        info!("🚦 Job '{}' priority: {:?}", job_id, priority);
+    }
+
+    // Fallback sniffing bisa menghasilkan mode lain dari yang dideklarasikan
+    if !mode.allowed_for(printer) {
+        let err = mode_forbidden(mode, &printer_id);
+        crate::jobs::record(&job_id, &printer_id, Some(mode.name()), bytes.len(), Err(&err));
+        return Err(err);
+    }
+
+    // pre_hook (mis. buka laci) hanya untuk job yang lolos validasi, sebelum dikirim; sesi dilewati
+    if let Some(command) = printer.options.pre_hook.as_deref().filter(|_| session.is_none()) {
+        crate::transform::run_hook("pre_hook", command, &printer_id, &job_id, None)
+            .await
+            .inspect_err(|e| crate::jobs::record(&job_id, &printer_id, Some(mode.name()), bytes.len(), Err(e)))?;
+    }
+
+    // Header/footer dari config printer; sesi berisi potongan struk jadi dilewati
+    let skip_header = query.get("skip_header").is_some_and(|v| parse_bool_public(v));
+    let bytes = if session.is_none() && !skip_header {
+        apply_header_footer(printer, opts.ops, bytes).await?
+    } else {
+        bytes
+    };
+
+    // Hook eksternal opsional antara build dan send
+    let bytes = match printer.options.transform_command.as_deref().filter(|_| !bytes.is_empty()) {
+        Some(command) => crate::transform::run_transform(command, &printer_id, &bytes)
+            .await
+            .inspect_err(|e| crate::jobs::record(&job_id, &printer_id, Some(mode.name()), bytes.len(), Err(e)))?,
+        None => bytes,
+    };
+
+    if printer.verbose() {
+        info!(
+            "🔬 [{}] job {} mode={} payload={} bytes head={}",
+            printer_id,
+            job_id,
+            mode.name(),
+            bytes.len(),
+            hex_preview(&bytes)
+        );
+    }
+
+    if deferred {
+        let len = bytes.len();
+        let (printers, delivered_id) = (app_state.printers.clone(), job_id.clone());
+        let job = crate::deferred::DeferredJob {
+            job_id: job_id.clone(),
+            mode: mode.name(),
+            payload: bytes,
+            priority,
+            refs: mode.graphic_refs(),
+            on_delivered: Box::new(move |printer, bytes| job_delivered(&printers, printer, &delivered_id, mode, bytes, true)),
+        };
+        if let Err(e) = crate::deferred::defer(&state, printer, job, crate::admin::job_started()) {
+            crate::jobs::record(&job_id, &printer_id, Some(mode.name()), len, Err(&e));
+            return Err(e);
+        }
+        return Ok(printed_to(job_success(response_format, &job_id, &printer_id, len), &printer_id));
+    }
+
+    if let Err(e) = send_job(printer, session.as_ref(), &bytes, priority, mode.graphic_refs()).await {
+        let e = if preflight { e } else { classify_send_error(printer, e).await };
+        crate::jobs::record(&job_id, &printer_id, Some(mode.name()), bytes.len(), Err(&e));
+        // Job biasa yang gagal karena printer (setelah retry koneksi) disimpan ke dead letter agar bisa
+        // dicetak ulang; job yang ditolak (payload, fault printer) tidak akan berhasil dengan dikirim ulang
+        if session.is_none() && e.is_transient() {
+            crate::deadletter::record(&printer_id, &e.to_string(), &bytes);
+        }
+        return Err(e);
+    }
+    job_delivered(&app_state.printers, printer, &job_id, mode, &bytes, session.is_none());
+    Ok(printed_to(job_success(response_format, &job_id, &printer_id, bytes.len()), &printer_id))
+}
+
+/// Tanpa preflight, kegagalan connect/tulis dicek ulang dengan satu probe (cache sudah di-invalidate
+/// oleh send path): printer yang memang tidak terjangkau jadi `PrinterOffline` (503 + Retry-After),
+/// selain itu error asli (`Io`, dll.) dikembalikan apa adanya
+async fn classify_send_error(printer: &Printer, e: ProxyError) -> ProxyError {
+    if !matches!(e, ProxyError::Io(_) | ProxyError::PrinterBusy(_)) {
+        return e;
+    }
+    match HEALTH_CACHE.get_or_check(printer).await {
+        PrinterStatus::Offline => {
+            warn!("❌ Printer '{}' is offline (send failed without preflight): {}", printer.id, e);
+            ProxyError::PrinterOffline(printer.id.clone())
+        }
+        _ => e,
+    }
+}
+
+/// Field `priority` job JSON (`"high"`, `"normal"`, `"low"`); body sudah valid sebagai job
+fn json_job_priority(body: &[u8]) -> Result<Priority, ProxyError> {
+    #[derive(Deserialize)]
+    struct JobMeta {
+        #[serde(default)]
+        priority: Priority,
+    }
+    serde_json::from_slice::<JobMeta>(body)
+        .map(|meta| meta.priority)
+        .map_err(|e| ProxyError::BadPayload(format!("JSON invalid: priority: {}", e)))
+}
+
+/// Override gambar ePOS (query/header) - optimized parsing
+/// Prioritas: override request > default printer > atribut per-image.
+/// Ukuran bitmap ePOS harus tepat: query > config printer > lenient.
+fn image_options(
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    printer: &Printer,
+) -> (Option<bool>, Option<BitOrder>, bool) {
+    let invert = query.get("invert")
+        .map(|v| parse_bool_public(v))
+        .or_else(|| {
+            headers.get("x-escpos-invert")
+                .and_then(|h| h.to_str().ok())
+                .map(parse_bool_public)
+        })
+        .or(printer.options.default_invert);
+
+    let bit = query.get("bit")
         .map(|v| parse_bit_order_public(v))
         .or_else(|| {
             headers.get("x-escpos-bit-order")
                 .and_then(|h| h.to_str().ok())
                 .map(parse_bit_order_public)
+        })
+        .or(printer.options.default_bit_order);
+
+    let strict_image = query.get("strict_image")
+        .map(|v| parse_bool_public(v))
+        .or(printer.options.strict_image)
+        .unwrap_or(false);
+
+    (invert, bit, strict_image)
+}
+
+/// Printer cadangan yang online untuk printer yang offline. Hanya satu lompatan: fallback milik
+/// fallback tidak diikuti, jadi printer yang saling menunjuk tidak bisa berputar. Sesi tidak dialihkan.
+async fn online_fallback<'a>(state: &'a AppState, printer: &Printer, in_session: bool) -> Option<&'a Printer> {
+    let id = printer.options.fallback_printer_id.as_deref().filter(|_| !in_session)?;
+    let Some(fallback) = state.printer(id).filter(|f| f.id != printer.id) else {
+        warn!("⚠️ Fallback printer '{}' for '{}' not found", id, printer.id);
+        return None;
+    };
+    match ensure_printer_online(fallback).await {
+        Ok(()) => Some(fallback),
+        Err(e) => {
+            warn!("⚠️ Fallback printer '{}' for '{}' unavailable too: {}", fallback.id, printer.id, e);
+            None
+        }
+    }
+}
+
+/// Header `X-Printed-To`: printer yang benar-benar menerima job (berbeda dari URL jika dialihkan ke fallback)
+fn printed_to(mut response: axum::response::Response, printer_id: &str) -> axum::response::Response {
+    if let Ok(value) = http::HeaderValue::from_str(printer_id) {
+        response.headers_mut().insert("x-printed-to", value);
+    }
+    response
+}
+
+/// Pencatatan setelah job berhasil ditulis: reprint, shadow, riwayat, dan post_hook
+fn job_delivered(
+    printers: &HashMap<String, Printer>,
+    printer: &Printer,
+    job_id: &str,
+    mode: PayloadMode,
+    bytes: &[u8],
+    outside_session: bool,
+) {
+    if outside_session {
+        retain_last_job(printer, bytes);
+    }
+    crate::shadow::mirror(printers, &printer.id, bytes);
+    crate::jobs::record(job_id, &printer.id, Some(mode.name()), bytes.len(), Ok(()));
+    info!("✅ Successfully sent {:?} job '{}' to printer '{}'", mode, job_id, printer.id);
+    // post_hook jalan di background: gagal hanya dicatat, response tidak menunggu
+    if let Some(command) = printer.options.post_hook.clone().filter(|_| outside_session) {
+        let (printer_id, job_id, len) = (printer.id.clone(), job_id.to_string(), bytes.len());
+        tokio::spawn(async move {
+            if let Err(e) = crate::transform::run_hook("post_hook", &command, &printer_id, &job_id, Some(len)).await {
+                warn!("⚠️ post_hook for job '{}' on printer '{}' failed: {}", job_id, printer_id, e);
+            }
         });
+    }
+}
 
+/// Estimasi ukuran dan waktu cetak job tanpa mengirim ke printer (untuk capacity planning)
+pub async fn estimate_job(
+    State(state): State<Arc<RwLock<AppState>>>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Result<impl IntoResponse, ProxyError> {
+    let app_state = state.read().await;
+    // Printer opsional: dipakai untuk rotate/model dan dpi/kecepatan
+    let printer = match query.get("printer_id") {
+        Some(id) => Some(app_state.printer(id).ok_or_else(|| {
+            warn!("❌ Printer '{}' tidak ditemukan untuk estimate", id);
+            ProxyError::NotFound(id.clone())
+        })?),
+        None => None,
+    };
+
+    let rotate_180 = printer.and_then(|p| p.options.rotate_180).unwrap_or(false);
+    let opts = JobOptions {
+        invert: query.get("invert").map(|v| parse_bool_public(v))
+            .or(printer.and_then(|p| p.options.default_invert)),
+        bit_order: query.get("bit").map(|v| parse_bit_order_public(v))
+            .or(printer.and_then(|p| p.options.default_bit_order)),
+        strict_image: query.get("strict_image").map(|v| parse_bool_public(v))
+            .or(printer.and_then(|p| p.options.strict_image))
+            .unwrap_or(false),
+        epos: EposBuildOptions {
+            init: true,
+            auto_cut: true,
+            rotate_180,
+        },
+        ops: OpsBuildOptions {
+            // Estimate tidak mendaftarkan graphic
+            printer_id: None,
+            rotate_180,
+            family: PrinterFamily::from_model(printer.and_then(|p| p.options.model.as_deref())),
+            cjk: printer.and_then(|p| p.options.cjk_encoding),
+            chars_per_line: printer.and_then(|p| p.options.chars_per_line),
+        },
+        allow_raw: printer.is_none_or(|p| PayloadMode::Raw.allowed_for(p)),
+    };
+
+    let (mode, bytes) = build_request_payload(&headers, &body, &opts).await?;
+    let bytes = match printer {
+        Some(p) if !query.get("skip_header").is_some_and(|v| parse_bool_public(v)) => {
+            apply_header_footer(p, opts.ops, bytes).await?
+        }
+        _ => bytes,
+    };
+
+    let dpi = printer.and_then(|p| p.options.dpi).unwrap_or(203).max(1) as f64;
+    let speed = printer.and_then(|p| p.options.print_speed_mm_s).unwrap_or(150).max(1) as f64;
+    let length_mm = crate::escpos::estimate_feed_dots(&bytes) as f64 * 25.4 / dpi;
+    let mut print_ms = length_mm / speed * 1000.0;
+    // Serial: transfer data bisa lebih lama dari mekanik cetak (10 bit per byte)
+    if let Some(Backend::Usb { baud_rate, .. }) = printer.map(|p| &p.backend) {
+        let baud = baud_rate.unwrap_or(9600).max(1) as f64;
+        print_ms = print_ms.max(bytes.len() as f64 * 10.0 * 1000.0 / baud);
+    }
+
+    debug!("📐 Estimate {:?}: {} bytes, {:.1} mm, {:.0} ms", mode, bytes.len(), length_mm, print_ms);
+    Ok(axum::Json(json!({
+        "printer_id": printer.map(|p| p.id.as_str()),
+        "mode": mode.name(),
+        "bytes": bytes.len(),
+        "estimated_length_mm": (length_mm * 10.0).round() / 10.0,
+        "estimated_print_ms": print_ms.round() as u64,
+    })))
+}
+
+/// Daftar op JSON job beserta field-nya, supaya API bisa dipakai tanpa membaca source
+pub async fn list_ops() -> impl IntoResponse {
+    let commands: Vec<_> = crate::escpos::named_commands()
+        .map(|(name, args)| json!({ "name": name, "args": args }))
+        .collect();
+    axum::Json(json!({
+        "ops": crate::escpos::OP_SCHEMAS,
+        "commands": commands,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Tambahkan `header_ops`/`footer_ops` printer ke job (semua mode payload)
+async fn apply_header_footer(printer: &Printer, opts: OpsBuildOptions<'_>, job: Vec<u8>) -> Result<Vec<u8>, ProxyError> {
+    let header = build_config_ops(printer.options.header_ops.as_deref(), opts).await?;
+    let footer = build_config_ops(printer.options.footer_ops.as_deref(), opts).await?;
+    if header.is_empty() && footer.is_empty() {
+        return Ok(job);
+    }
+    debug!("🏷️ Adding header ({} bytes) / footer ({} bytes) for printer '{}'", header.len(), footer.len(), printer.id);
+    Ok(wrap_job(&job, &header, &footer))
+}
+
+async fn build_config_ops(ops: Option<&[PrintOp]>, opts: OpsBuildOptions<'_>) -> Result<Vec<u8>, ProxyError> {
+    let Some(ops) = ops.filter(|ops| !ops.is_empty()) else {
+        return Ok(Vec::new());
+    };
+    let mut ops = ops.to_vec();
+    crate::fetch::prefetch_images(&mut ops).await?;
+    build_escpos_from_ops(&ops, opts)
+}
+
+/// Bangun payload untuk job ad-hoc admin (tanpa printer di config): opsi default, semua mode diterima
+pub(crate) async fn build_adhoc_payload(headers: &HeaderMap, body: &[u8]) -> Result<(&'static str, Vec<u8>), ProxyError> {
+    let opts = JobOptions {
+        invert: None,
+        bit_order: None,
+        strict_image: false,
+        epos: EposBuildOptions {
+            init: true,
+            auto_cut: true,
+            rotate_180: false,
+        },
+        ops: OpsBuildOptions {
+            printer_id: None,
+            rotate_180: false,
+            family: PrinterFamily::from_model(None),
+            cjk: None,
+            chars_per_line: None,
+        },
+        allow_raw: true,
+    };
+    let (mode, bytes) = build_request_payload(headers, body, &opts).await?;
+    Ok((mode.name(), bytes))
+}
+
+fn mode_forbidden(mode: PayloadMode, printer_id: &str) -> ProxyError {
+    warn!("🚫 Mode '{}' not allowed for printer '{}'", mode.name(), printer_id);
+    ProxyError::Forbidden(format!("Mode '{}' tidak diizinkan untuk printer '{}'", mode.name(), printer_id))
+}
+
+/// Deteksi mode payload (Content-Type, fallback sniffing) lalu bangun bytes ESC/POS
+async fn build_request_payload(
+    headers: &HeaderMap,
+    body: &[u8],
+    opts: &JobOptions<'_>,
+) -> Result<(PayloadMode, Vec<u8>), ProxyError> {
     // Content-Type - avoid unnecessary allocations
     let ct = headers
         .get(CONTENT_TYPE)
@@ -89,83 +742,201 @@ pub async fn handle_print(
     debug!("📄 Content-Type: {}", ct);
     debug!("📊 Body size: {} bytes", body.len());
 
-    // Mode A: ePOS SOAP - optimized content-type checking
-    if ct.starts_with("text/plain")
-        || ct.starts_with("text/xml")
-        || ct.starts_with("application/xml")
-    {
-        info!("🔄 Processing ePOS-Print SOAP mode");
-        let doc = parse_epos_soap(&body, invert_override, bit_override)?;
-        info!("✅ Parsed {} image(s), cut: {:?}", doc.images.len(), doc.cut);
-        
-        let bytes = build_escpos_from_epos_doc(&doc)?;
-        info!("📦 Generated {} ESC/POS bytes", bytes.len());
-        
-        send_to_backend(printer, &bytes).await?;
-        info!("✅ Successfully sent to printer '{}'", printer_id);
-        return Ok(xml_success().into_response());
-    }
-
-    // Mode B: RAW ESC/POS
-    if ct.starts_with("application/octet-stream")
-        || headers
-            .get("x-esc-pos-mode")
-            .and_then(|v| v.to_str().ok())
-            .is_some_and(|v| v.eq_ignore_ascii_case("raw"))
-    {
-        info!("🔄 Processing RAW ESC/POS mode");
-        if body.is_empty() {
-            warn!("❌ Empty body for raw mode");
-            return Err(ProxyError::BadPayload("Body kosong untuk mode raw".into()));
+    // Declared Content-Type tetap jalur utama, sniffing body hanya sebagai fallback
+    let built = match PayloadMode::from_headers(ct, headers) {
+        Some(declared) => match build_job_bytes(declared, ct, body, opts).await {
+            Ok(bytes) => (declared, bytes),
+            Err(ProxyError::BadPayload(msg)) => match PayloadMode::sniff(body).filter(|m| *m != declared) {
+                Some(detected) => {
+                    warn!(
+                        "🔀 Payload dengan Content-Type '{}' gagal diproses sebagai {:?} ({}), terdeteksi sebagai {:?}",
+                        ct, declared, msg, detected
+                    );
+                    (detected, build_job_bytes(detected, ct, body, opts).await?)
+                }
+                None => return Err(ProxyError::BadPayload(msg)),
+            },
+            Err(e) => return Err(e),
+        },
+        None => match PayloadMode::sniff(body) {
+            Some(detected) => {
+                warn!("🔀 Content-Type '{}' tidak didukung, body terdeteksi sebagai {:?}", ct, detected);
+                (detected, build_job_bytes(detected, ct, body, opts).await?)
+            }
+            None => {
+                warn!("❌ Unsupported content type: {}", ct);
+                return Err(ProxyError::BadPayload(
+                    "Unsupported payload. Gunakan text/plain|text/xml|application/xml|application/vnd.epson.epos+xml (ePOS), application/octet-stream (raw), application/json (job), atau multipart/form-data.".into(),
+                ));
+            }
+        },
+    };
+    Ok(built)
+}
+
+/// Mode payload print endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PayloadMode {
+    /// ePOS-Print SOAP/XML
+    Epos,
+    /// RAW ESC/POS bytes
+    Raw,
+    /// JSON job (base64 atau ops)
+    Json,
+    /// multipart/form-data: part teks, file PNG, dan raw chunk sesuai urutan
+    Multipart,
+}
+
+impl PayloadMode {
+    /// Mode berdasarkan Content-Type / header `X-ESC-POS-Mode` yang dideklarasikan client
+    fn from_headers(ct: &str, headers: &HeaderMap) -> Option<Self> {
+        // Media type tanpa parameter (`; charset=...`), case-insensitive
+        let media = ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        // ePOS SDK mengirim text/plain, text/xml, application/xml, atau vendor type
+        // application/vnd.epson.epos+xml; suffix +xml lain (mis. application/soap+xml) juga diterima
+        if matches!(media.as_str(), "text/plain" | "text/xml" | "application/xml")
+            || media.ends_with("+xml")
+        {
+            Some(Self::Epos)
+        } else if media == "application/octet-stream"
+            || headers
+                .get("x-esc-pos-mode")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("raw"))
+        {
+            Some(Self::Raw)
+        } else if media == "application/json" {
+            Some(Self::Json)
+        } else if media == "multipart/form-data" {
+            Some(Self::Multipart)
+        } else {
+            None
         }
-        
-        info!("📦 Sending {} raw bytes to printer", body.len());
-        send_to_backend(printer, &body).await?;
-        info!("✅ Successfully sent raw data to printer '{}'", printer_id);
-        return Ok(xml_success().into_response());
-    }
-
-    // Mode C: JSON job
-    if ct.starts_with("application/json") {
-        info!("🔄 Processing JSON job mode");
-        let job: JsonJob =
-            serde_json::from_slice(&body).map_err(|e| {
-                error!("❌ JSON parsing error: {}", e);
-                ProxyError::BadPayload(format!("JSON invalid: {e}"))
-            })?;
-            
-        let bytes = match job {
-            JsonJob::RawBase64 { ref base64 } => {
-                info!("📦 Processing base64 data ({} chars)", base64.len());
-                BASE64_STANDARD.decode(base64).map_err(|e| {
-                    error!("❌ Base64 decode error: {}", e);
-                    ProxyError::BadPayload(format!("Base64 invalid: {e}"))
-                })?
+    }
+
+    /// Mode yang dicoba lebih dulu saat build: yang dideklarasikan client, atau hasil sniffing body
+    fn detect(headers: &HeaderMap, body: &[u8]) -> Option<Self> {
+        let ct = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+        Self::from_headers(ct, headers).or_else(|| Self::sniff(body))
+    }
+
+    /// Tebak mode dari isi body: `<` -> XML, `{`/`[` -> JSON, ESC @ -> raw
+    fn sniff(body: &[u8]) -> Option<Self> {
+        if body.starts_with(&[0x1B, 0x40]) {
+            return Some(Self::Raw);
+        }
+        let text = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body); // UTF-8 BOM
+        match text.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'<') => Some(Self::Epos),
+            Some(b'{' | b'[') => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Nama mode seperti di opsi `allowed_modes`
+    fn name(self) -> &'static str {
+        match self {
+            Self::Epos => "epos",
+            Self::Raw => "raw",
+            Self::Json => "json",
+            Self::Multipart => "multipart",
+        }
+    }
+
+    /// Definisi graphic hanya disisipkan ke job JSON ops; multipart bisa membawa potongan raw client
+    fn graphic_refs(self) -> GraphicRefs {
+        match self {
+            Self::Json => GraphicRefs::Resolve,
+            Self::Epos | Self::Raw | Self::Multipart => GraphicRefs::AsIs,
+        }
+    }
+
+    /// Apakah mode ini diizinkan oleh `allowed_modes` printer (None = semua mode)
+    fn allowed_for(self, printer: &Printer) -> bool {
+        printer
+            .options
+            .allowed_modes
+            .as_ref()
+            .is_none_or(|modes| modes.iter().any(|m| m.eq_ignore_ascii_case(self.name())))
+    }
+}
+
+/// Opsi build per request (override query/header di atas default printer, dan mode sesi)
+struct JobOptions<'a> {
+    invert: Option<bool>,
+    bit_order: Option<BitOrder>,
+    /// Tolak bitmap ePOS yang ukurannya tidak cocok dengan width x height (default: pad/potong)
+    strict_image: bool,
+    epos: EposBuildOptions,
+    ops: OpsBuildOptions<'a>,
+    /// Raw chunk di dalam multipart hanya diterima jika printer juga menerima mode raw
+    allow_raw: bool,
+}
+
+/// Bangun bytes ESC/POS dari body sesuai mode
+async fn build_job_bytes(mode: PayloadMode, ct: &str, body: &[u8], opts: &JobOptions<'_>) -> Result<Vec<u8>, ProxyError> {
+    match mode {
+        PayloadMode::Epos => {
+            info!("🔄 Processing ePOS-Print SOAP mode");
+            // Decode base64, dither, dan upscale gambar ePOS di thread blocking
+            let body = body.to_vec();
+            let (invert, bit_order, strict_image, epos) = (opts.invert, opts.bit_order, opts.strict_image, opts.epos);
+            let bytes = crate::image::run_blocking(move || {
+                let doc = parse_epos_soap(&body, invert, bit_order, strict_image)?;
+                info!("✅ Parsed {} image(s), cut: {:?}", doc.images.len(), doc.cut);
+                build_escpos_from_epos_doc(&doc, epos)
+            })
+            .await?;
+            info!("📦 Generated {} ESC/POS bytes", bytes.len());
+            Ok(bytes)
+        }
+        PayloadMode::Raw => {
+            info!("🔄 Processing RAW ESC/POS mode");
+            if body.is_empty() {
+                warn!("❌ Empty body for raw mode");
+                return Err(ProxyError::BadPayload("Body kosong untuk mode raw".into()));
             }
-            JsonJob::Ops { ref ops } => {
-                info!("🔄 Processing {} operations", ops.len());
-                for (i, op) in ops.iter().enumerate() {
-                    debug!("  Op {}: {:?}", i, op);
+
+            info!("📦 Sending {} raw bytes to printer", body.len());
+            Ok(body.to_vec())
+        }
+        PayloadMode::Json => {
+            info!("🔄 Processing JSON job mode");
+            let job = JsonJob::parse(body).inspect_err(|e| error!("❌ JSON parsing error: {}", e))?;
+
+            let bytes = match job {
+                JsonJob::RawBase64 { ref base64 } => {
+                    info!("📦 Processing base64 data ({} chars)", base64.len());
+                    // Data URI (`data:application/octet-stream;base64,...`) juga diterima
+                    let base64 = split_data_uri(base64).map_or(base64.as_str(), |(_, data)| data);
+                    BASE64_STANDARD.decode(base64).map_err(|e| {
+                        error!("❌ Base64 decode error: {}", e);
+                        ProxyError::BadPayload(format!("Base64 invalid: {e}"))
+                    })?
+                }
+                JsonJob::Ops { mut ops, vars } => {
+                    info!("🔄 Processing {} operations", ops.len());
+                    for (i, op) in ops.iter().enumerate() {
+                        debug!("  Op {}: {:?}", i, op);
+                    }
+                    crate::fetch::prefetch_images(&mut ops).await?;
+                    opts.ops.build_blocking(ops, vars).await?
                 }
-                build_escpos_from_ops(&ops)?
+            };
+
+            if bytes.is_empty() {
+                warn!("❌ Generated empty ESC/POS data");
+                return Err(ProxyError::BadPayload("Tidak ada data ESC/POS yang akan dikirim".into()));
             }
-        };
-        
-        if bytes.is_empty() {
-            warn!("❌ Generated empty ESC/POS data");
-            return Err(ProxyError::BadPayload("Tidak ada data ESC/POS yang akan dikirim".into()));
+
+            info!("📦 Generated {} ESC/POS bytes from JSON", bytes.len());
+            Ok(bytes)
+        }
+        PayloadMode::Multipart => {
+            info!("🔄 Processing multipart/form-data mode");
+            crate::multipart::build_from_multipart(ct, body, opts.ops, opts.allow_raw).await
         }
-        
-        info!("📦 Generated {} ESC/POS bytes from JSON", bytes.len());
-        send_to_backend(printer, &bytes).await?;
-        info!("✅ Successfully sent JSON job to printer '{}'", printer_id);
-        return Ok(xml_success().into_response());
     }
-
-    warn!("❌ Unsupported content type: {}", ct);
-    Err(ProxyError::BadPayload(
-        "Unsupported payload. Gunakan text/plain|text/xml|application/xml (ePOS), application/octet-stream (raw), atau application/json (job).".into(),
-    ))
 }
 
 #[instrument]
@@ -179,7 +950,6 @@ pub async fn health_check() -> &'static str {
 pub async fn printers_health_check(State(state): State<Arc<RwLock<AppState>>>) -> impl IntoResponse {
     info!("🏥 Checking health status of all printers");
     
-    let mut results = HashMap::new();
     let mut futures = Vec::new();
     
     // Create futures for all printer health checks (reduce cloning)
@@ -190,17 +960,79 @@ pub async fn printers_health_check(State(state): State<Arc<RwLock<AppState>>>) -
         
         futures.push(async move {
             let status = check_printer_health(&printer_ref).await;
-            (id_ref, status)
+            (id_ref, Some(status))
         });
     }
     
     // Execute all health checks concurrently
     let health_results = futures::future::join_all(futures).await;
     
+    axum::Json(health_summary(health_results))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkHealthRequest {
+    pub printer_ids: Vec<String>,
+}
+
+/// Check health status of a subset of printers (`POST /health/printers`)
+#[instrument(skip(state))]
+pub async fn printers_health_check_bulk(
+    State(state): State<Arc<RwLock<AppState>>>,
+    axum::Json(request): axum::Json<BulkHealthRequest>,
+) -> axum::response::Response {
+    info!("🏥 Checking health status of {} requested printer(s)", request.printer_ids.len());
+
+    // Setiap id memicu probe sendiri: daftar yang terlalu panjang ditolak sebelum ada koneksi dibuka
+    let max_ids = crate::settings::settings().health_bulk_max_ids;
+    if request.printer_ids.len() > max_ids {
+        warn!("❌ Bulk health: {} printer id melebihi batas {}", request.printer_ids.len(), max_ids);
+        return (
+            StatusCode::BAD_REQUEST,
+            axum::Json(json!({
+                "status": "error",
+                "message": format!("printer_ids maksimal {} id (HEALTH_BULK_MAX_IDS)", max_ids)
+            })),
+        )
+            .into_response();
+    }
+
+    let app_state = state.read().await;
+    let futures = request.printer_ids.into_iter().map(|id| {
+        // ID/alias tidak dikenal -> entry "not_found", request tetap sukses
+        let printer = app_state.printer(&id).cloned();
+        async move {
+            match printer {
+                Some(p) => (id, Some(check_printer_health(&p).await)),
+                None => {
+                    warn!("❓ Bulk health: printer '{}' tidak ditemukan", id);
+                    (id, None)
+                }
+            }
+        }
+    });
+    let health_results = futures::future::join_all(futures).await;
+
+    axum::Json(health_summary(health_results)).into_response()
+}
+
+/// Bangun JSON health (status keseluruhan, summary, per printer); `None` = printer tidak ditemukan
+fn health_summary(health_results: Vec<(String, Option<PrinterStatus>)>) -> serde_json::Value {
+    let total = health_results.len();
+    let mut results = HashMap::new();
     let mut online_count = 0;
     let mut offline_count = 0;
+    let mut not_found_count = 0;
     
     for (id, status) in health_results {
+        let Some(status) = status else {
+            not_found_count += 1;
+            results.insert(id, json!({
+                "status": "not_found",
+                "message": "❓ Printer tidak ditemukan"
+            }));
+            continue;
+        };
         let status_str = match status {
             PrinterStatus::Online => {
                 online_count += 1;
@@ -210,6 +1042,7 @@ pub async fn printers_health_check(State(state): State<Arc<RwLock<AppState>>>) -
                 offline_count += 1;
                 "offline"
             }
+            PrinterStatus::Busy => "busy",
             PrinterStatus::Unknown => "unknown"
         };
         
@@ -223,18 +1056,63 @@ pub async fn printers_health_check(State(state): State<Arc<RwLock<AppState>>>) -
     
     info!("🏥 Health check completed: {} online, {} offline", online_count, offline_count);
     
-    let response = json!({
+    let mut summary = json!({
+        "total": total,
+        "online": online_count,
+        "offline": offline_count
+    });
+    if not_found_count > 0 {
+        summary["not_found"] = json!(not_found_count);
+    }
+    
+    json!({
         "status": overall_status,
         "timestamp": chrono::Utc::now().to_rfc3339(),
-        "summary": {
-            "total": app_state.printers.len(),
-            "online": online_count,
-            "offline": offline_count
-        },
+        "summary": summary,
         "printers": results
+    })
+}
+
+/// Live printer status via Server-Sent Events.
+/// Snapshot status saat connect, lalu event setiap kali status printer berubah, plus heartbeat.
+#[instrument(skip(state))]
+pub async fn health_stream(
+    State(state): State<Arc<RwLock<AppState>>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    info!("📡 Health stream subscriber connected");
+    // Subscribe dulu supaya perubahan selama snapshot tidak terlewat
+    let rx = HEALTH_CACHE.subscribe();
+
+    let printers: Vec<Printer> = state.read().await.printers.values().cloned().collect();
+    let snapshot = futures::future::join_all(printers.iter().map(|p| async move {
+        HealthEvent {
+            printer_id: p.id.clone(),
+            status: check_printer_health(p).await.as_str(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }))
+    .await;
+
+    let updates = stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("⚠️ Health stream subscriber lagged, skipped {} event(s)", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
     });
-    
-    axum::Json(response)
+
+    let events = stream::iter(snapshot).chain(updates).map(|event| {
+        Ok(Event::default()
+            .event("status")
+            .json_data(&event)
+            .unwrap_or_else(|_| Event::default().comment("serialize error")))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)).text("heartbeat"))
 }
 
 /// Check health status of a specific printer
@@ -247,27 +1125,125 @@ pub async fn printer_health_check(
     
     let app_state = state.read().await;
     let printer = app_state
-        .printers
-        .get(&printer_id)
+        .printer(&printer_id)
         .ok_or_else(|| ProxyError::NotFound(printer_id.clone()))?;
+    let printer_id = printer.id.clone();
     
     let status = check_printer_health(printer).await;
     
-    let status_str = match status {
-        PrinterStatus::Online => "online",
-        PrinterStatus::Offline => "offline",
-        PrinterStatus::Unknown => "unknown",
-    };
+    let status_str = status.as_str();
     
     info!("🏥 Printer '{}' status: {}", printer_id, status);
     
-    let response = json!({
+    let mut response = json!({
         "printer_id": printer_id,
         "status": status_str,
         "message": status.to_string(),
         "backend": printer.backend,
         "timestamp": chrono::Utc::now().to_rfc3339()
     });
+    // Model/firmware via GS I hanya untuk printer yang ditandai mendukung
+    if printer.options.identify.unwrap_or(false) && status == PrinterStatus::Online {
+        response["identity"] = json!(crate::health::identify_printer(printer).await);
+    }
     
     Ok(axum::Json(response))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::any, Router};
+    use std::sync::Mutex as StdMutex;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    /// Printer TCP palsu yang mengumpulkan semua byte yang diterima
+    async fn mock_printer() -> (u16, Arc<StdMutex<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(StdMutex::new(Vec::new()));
+        let sink = received.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let sink = sink.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        sink.lock().unwrap().extend_from_slice(&buf[..n]);
+                    }
+                });
+            }
+        });
+        (port, received)
+    }
+
+    /// Proxy dengan satu printer `mock` yang menunjuk ke `printer_port`
+    async fn spawn_proxy(printer_port: u16) -> std::net::SocketAddr {
+        let config = format!(
+            "printers:\n  - name: \"Mock\"\n    id: \"mock\"\n    backend: {{ type: \"tcp9100\", host: \"127.0.0.1\", port: {} }}\n",
+            printer_port
+        );
+        let (printers, aliases) = crate::config::build_printers_map(
+            crate::config::ConfigFormat::Yaml.parse(config.as_bytes()).unwrap(),
+        );
+        let state = Arc::new(RwLock::new(AppState {
+            printers: Arc::new(printers),
+            aliases: Arc::new(aliases),
+            settings: crate::settings::init_for_tests(),
+        }));
+        let app = Router::new()
+            .route("/:printer_id/cgi-bin/epos/service.cgi", any(handle_print))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn raw_request(content_length: usize, body: &[u8]) -> Vec<u8> {
+        let mut request = format!(
+            "POST /mock/cgi-bin/epos/service.cgi HTTP/1.1\r\nHost: proxy\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            content_length
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        request
+    }
+
+    #[tokio::test]
+    async fn aborted_upload_is_not_written_to_printer() {
+        let (printer_port, received) = mock_printer().await;
+        let proxy = spawn_proxy(printer_port).await;
+
+        // Client mengirim 10 dari 100 byte lalu putus
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(&raw_request(100, b"\x1b@HALF-REC")).await.unwrap();
+        client.shutdown().await.unwrap();
+        drop(client);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(received.lock().unwrap().is_empty(), "printer menerima body terputus");
+
+        // Kontrol: request lengkap ke printer yang sama tetap sampai
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(&raw_request(10, b"\x1b@COMPLETE")).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200"), "{}", String::from_utf8_lossy(&response));
+        // Response dikirim setelah write selesai, printer palsu mungkin belum membaca
+        let contains = |needle: &[u8]| received.lock().unwrap().windows(needle.len()).any(|w| w == needle);
+        for _ in 0..50 {
+            if contains(b"\x1b@COMPLETE") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(contains(b"\x1b@COMPLETE"));
+        assert!(!contains(b"\x1b@HALF-REC"));
+    }
+}
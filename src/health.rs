@@ -1,5 +1,10 @@
-use crate::{config::{Printer, Backend}, errors::ProxyError};
-use std::time::Duration;
+use crate::{backend::connect_tcp, config::{Printer, Backend}, errors::ProxyError};
+use crate::handlers::AppState;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
 use tokio::{net::TcpStream, time::timeout};
 use tracing::{info, warn, debug, instrument};
 
@@ -7,6 +12,8 @@ use tracing::{info, warn, debug, instrument};
 pub enum PrinterStatus {
     Online,
     Offline,
+    /// Koneksi ditolak pada printer dengan `busy_retries`: printer hidup tapi sedang melayani client lain
+    Busy,
     #[allow(dead_code)]
     Unknown,
 }
@@ -16,17 +23,168 @@ impl std::fmt::Display for PrinterStatus {
         match self {
             PrinterStatus::Online => write!(f, "🟢 Online"),
             PrinterStatus::Offline => write!(f, "🔴 Offline"),
+            PrinterStatus::Busy => write!(f, "🟠 Busy"),
             PrinterStatus::Unknown => write!(f, "🟡 Unknown"),
         }
     }
 }
 
+impl PrinterStatus {
+    /// Nilai status untuk JSON response
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrinterStatus::Online => "online",
+            PrinterStatus::Offline => "offline",
+            PrinterStatus::Busy => "busy",
+            PrinterStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// Identitas printer dari GS I (transmit printer ID)
+#[derive(Debug, Clone, Serialize)]
+pub struct PrinterIdentity {
+    /// GS I 1
+    pub model_id: Option<u8>,
+    /// GS I 2 (bit flag: bit 0 karakter multi-byte, bit 1 autocutter)
+    pub type_id: Option<u8>,
+    pub autocutter: Option<bool>,
+    /// GS I 65
+    pub firmware: Option<String>,
+    /// GS I 66
+    pub manufacturer: Option<String>,
+    /// GS I 67
+    pub model_name: Option<String>,
+    /// Nilai `model` printer yang cocok, jika bisa ditebak dari manufacturer
+    pub suggested_model: Option<&'static str>,
+}
+
+/// Identitas tidak berubah selama printer di alamat yang sama, jadi cukup di-query sekali
+static IDENTITY_CACHE: Lazy<DashMap<String, PrinterIdentity>> = Lazy::new(DashMap::new);
+
+/// Lama menunggu balasan setiap perintah GS I
+const IDENTIFY_READ_WINDOW: Duration = Duration::from_millis(300);
+
+/// Balasan GS I 65..: header `_` (0x5F), teks, NUL
+fn parse_id_string(reply: &[u8]) -> Option<String> {
+    let start = reply.iter().position(|b| *b == 0x5F)? + 1;
+    let end = reply[start..].iter().position(|b| *b == 0).map_or(reply.len(), |i| start + i);
+    let text = String::from_utf8_lossy(&reply[start..end]).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Query model/type/firmware printer dengan GS I; `None` jika printer tidak menjawab sama sekali.
+/// Hanya untuk printer dengan `identify: true`, hasil di-cache per printer + backend.
+pub async fn identify_printer(printer: &Printer) -> Option<PrinterIdentity> {
+    let cache_key = format!("{}:{:?}", printer.id, printer.backend);
+    if let Some(identity) = IDENTITY_CACHE.get(&cache_key) {
+        return Some(identity.clone());
+    }
+
+    let requests: [&[u8]; 5] = [
+        &[0x1D, b'I', 1],
+        &[0x1D, b'I', 2],
+        &[0x1D, b'I', 65],
+        &[0x1D, b'I', 66],
+        &[0x1D, b'I', 67],
+    ];
+    let replies = match crate::pool::CONNECTION_MANAGER.query(&printer.backend, &requests, IDENTIFY_READ_WINDOW).await {
+        Ok(replies) => replies,
+        Err(e) => {
+            warn!("⚠️ GS I query to printer '{}' failed: {}", printer.id, e);
+            return None;
+        }
+    };
+    if replies.iter().all(Vec::is_empty) {
+        warn!("⚠️ Printer '{}' tidak menjawab GS I (identify: true tapi tidak didukung?)", printer.id);
+        return None;
+    }
+
+    let model_id = replies[0].first().copied();
+    let type_id = replies[1].first().copied();
+    let manufacturer = parse_id_string(&replies[3]);
+    let identity = PrinterIdentity {
+        model_id,
+        type_id,
+        autocutter: type_id.map(|t| t & 0x02 != 0),
+        firmware: parse_id_string(&replies[2]),
+        suggested_model: manufacturer
+            .as_deref()
+            .filter(|m| m.to_ascii_uppercase().contains("EPSON"))
+            .map(|_| "epson"),
+        manufacturer,
+        model_name: parse_id_string(&replies[4]),
+    };
+    info!(
+        "🪪 Printer '{}' identified: {} {} (firmware {})",
+        printer.id,
+        identity.manufacturer.as_deref().unwrap_or("?"),
+        identity.model_name.as_deref().unwrap_or("?"),
+        identity.firmware.as_deref().unwrap_or("?")
+    );
+    IDENTITY_CACHE.insert(cache_key, identity.clone());
+    Some(identity)
+}
+
 /// Check if a printer is reachable (with caching)
 #[instrument(skip(printer), fields(printer_id = %printer.id))]
 pub async fn check_printer_health(printer: &Printer) -> PrinterStatus {
     crate::pool::HEALTH_CACHE.get_or_check(printer).await
 }
 
+/// Startup health sweep: cek semua printer secara paralel dan log ringkasan.
+/// Hasil masuk ke health cache sehingga request pertama tidak perlu probe ulang.
+pub async fn startup_health_sweep(printers: &HashMap<String, Printer>) {
+    info!("🔍 Running startup health check for {} printer(s)...", printers.len());
+
+    let mut results = futures::future::join_all(
+        printers.values().map(|p| async move { (p, check_printer_health(p).await) }),
+    )
+    .await;
+    results.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+
+    info!("{:<24} | {:<12} | backend", "printer", "status");
+    for (printer, status) in &results {
+        info!("{:<24} | {:<12} | {:?}", printer.id, status.to_string(), printer.backend);
+    }
+
+    let offline: Vec<&str> = results
+        .iter()
+        .filter(|(_, status)| *status == PrinterStatus::Offline)
+        .map(|(p, _)| p.id.as_str())
+        .collect();
+    let online = results.iter().filter(|(_, status)| *status == PrinterStatus::Online).count();
+
+    if offline.is_empty() {
+        info!("✅ Startup health check: {} online, 0 offline", online);
+    } else {
+        warn!(
+            "⚠️ Startup health check: {} online, {} offline ({})",
+            online,
+            offline.len(),
+            offline.join(", ")
+        );
+    }
+}
+
+/// Background polling loop: cek semua printer secara berkala selama ada subscriber
+/// `/health/stream`, sehingga perubahan status terdeteksi tanpa traffic print
+pub async fn start_health_poll_task(state: Arc<RwLock<AppState>>) {
+    let secs = crate::settings::settings().health_poll_secs;
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+
+    loop {
+        interval.tick().await;
+        if crate::pool::HEALTH_CACHE.subscriber_count() == 0 {
+            continue;
+        }
+
+        debug!("🔁 Polling health of all printers for stream subscribers");
+        let printers: Vec<Printer> = state.read().await.printers.values().cloned().collect();
+        futures::future::join_all(printers.iter().map(check_printer_health)).await;
+    }
+}
+
 /// Check TCP connectivity to printer
 #[allow(dead_code)]
 #[instrument]
@@ -59,7 +217,8 @@ async fn check_tcp_health(host: &str, port: u16) -> PrinterStatus {
 /// Validate printer is online before processing request
 #[instrument(skip(printer), fields(printer_id = %printer.id))]
 pub async fn ensure_printer_online(printer: &Printer) -> Result<(), ProxyError> {
-    let status = check_printer_health(printer).await;
+    // Online yang masih fresh di cache langsung lolos tanpa probe (hot path print)
+    let status = crate::pool::HEALTH_CACHE.get_online_or_check(printer).await;
     
     match status {
         PrinterStatus::Online => {
@@ -70,6 +229,11 @@ pub async fn ensure_printer_online(printer: &Printer) -> Result<(), ProxyError>
             warn!("❌ Printer '{}' is offline, rejecting request", printer.id);
             Err(ProxyError::PrinterOffline(printer.id.clone()))
         }
+        PrinterStatus::Busy => {
+            // Send path menunggu dan mencoba connect ulang sesuai busy_retries
+            debug!("🟠 Printer '{}' is busy, proceeding with busy retry", printer.id);
+            Ok(())
+        }
         PrinterStatus::Unknown => {
             warn!("⚠️ Printer '{}' status unknown, proceeding with caution", printer.id);
             Ok(()) // Allow unknown status to pass through
@@ -81,20 +245,16 @@ pub async fn ensure_printer_online(printer: &Printer) -> Result<(), ProxyError>
 #[allow(dead_code)]
 pub async fn quick_health_check(printer: &Printer) -> PrinterStatus {
     match &printer.backend {
-        Backend::Tcp9100 { host, port } => {
-            let addr = format!("{}:{}", host, port);
-            
-            match timeout(Duration::from_millis(500), TcpStream::connect(&addr)).await {
+        Backend::Tcp9100 { host, port, bind_addr, .. } => {
+            match timeout(Duration::from_millis(500), connect_tcp(host, *port, *bind_addr)).await {
                 Ok(Ok(_)) => PrinterStatus::Online,
                 _ => PrinterStatus::Offline,
             }
         }
-        Backend::Usb { device, baud_rate } => {
-            let baud_rate = baud_rate.unwrap_or(9600);
-            
+        Backend::Usb { .. } => {
             match timeout(
                 Duration::from_millis(500), 
-                async { tokio_serial::new(device, baud_rate).open() }
+                async { crate::config::serial_builder(&printer.backend).and_then(|b| b.open().map_err(|e| e.to_string())) }
             ).await {
                 Ok(Ok(_)) => PrinterStatus::Online,
                 _ => PrinterStatus::Offline,
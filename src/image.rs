@@ -0,0 +1,246 @@
+use crate::errors::ProxyError;
+use serde::Serialize;
+
+/* ===================== Grayscale -> 1bpp Dithering ===================== */
+
+/// Algoritma halftoning untuk konversi grayscale ke 1bpp
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dither {
+    /// Threshold tetap 128
+    None,
+    /// Threshold custom (atribut `threshold`), cocok untuk logo/teks
+    Threshold,
+    /// Error diffusion Floyd-Steinberg, cocok untuk foto (default)
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion Atkinson, kontras lebih tinggi dari Floyd-Steinberg
+    Atkinson,
+    /// Bayer 4x4, pola teratur yang stabil untuk gradasi
+    Ordered,
+}
+
+impl Dither {
+    pub fn parse(val: &str) -> Result<Self, ProxyError> {
+        match val.to_ascii_lowercase().replace('_', "-").as_str() {
+            "none" => Ok(Dither::None),
+            "threshold" => Ok(Dither::Threshold),
+            "floyd-steinberg" | "floyd" | "fs" => Ok(Dither::FloydSteinberg),
+            "atkinson" => Ok(Dither::Atkinson),
+            "ordered" | "bayer" => Ok(Dither::Ordered),
+            other => Err(ProxyError::BadPayload(format!(
+                "Dither '{}' tidak dikenal, gunakan none|threshold|floyd-steinberg|atkinson|ordered",
+                other
+            ))),
+        }
+    }
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// Konversi grayscale 8-bit (0 = hitam, 255 = putih) ke bitmap 1bpp MSB-first (bit 1 = titik hitam)
+pub fn gray_to_mono(gray: &[u8], width: u32, height: u32, dither: Dither, threshold: u8) -> Vec<u8> {
+    let w = width as usize;
+    let h = height as usize;
+    let x_bytes = w.div_ceil(8);
+    let mut out = vec![0u8; x_bytes * h];
+    let mut set_black = |x: usize, y: usize| out[y * x_bytes + x / 8] |= 0x80 >> (x % 8);
+
+    match dither {
+        Dither::None | Dither::Threshold => {
+            let t = if dither == Dither::None { 128 } else { threshold };
+            for y in 0..h {
+                for x in 0..w {
+                    if gray[y * w + x] < t {
+                        set_black(x, y);
+                    }
+                }
+            }
+        }
+        Dither::Ordered => {
+            for y in 0..h {
+                for x in 0..w {
+                    let t = BAYER_4X4[y % 4][x % 4] * 16 + 8;
+                    if gray[y * w + x] < t {
+                        set_black(x, y);
+                    }
+                }
+            }
+        }
+        Dither::FloydSteinberg | Dither::Atkinson => {
+            let mut buf: Vec<i16> = gray[..w * h].iter().map(|&g| g as i16).collect();
+            // (dx, dy, bobot) dengan pembagi 16 (FS) atau 8 (Atkinson)
+            let (kernel, divisor): (&[(isize, usize, i16)], i16) = if dither == Dither::FloydSteinberg {
+                (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16)
+            } else {
+                (&[(1, 0, 1), (2, 0, 1), (-1, 1, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)], 8)
+            };
+            for y in 0..h {
+                for x in 0..w {
+                    let old = buf[y * w + x];
+                    let new = if old < 128 { 0 } else { 255 };
+                    if new == 0 {
+                        set_black(x, y);
+                    }
+                    let err = old - new;
+                    for &(dx, dy, weight) in kernel {
+                        let nx = x as isize + dx;
+                        let ny = y + dy;
+                        if nx >= 0 && (nx as usize) < w && ny < h {
+                            buf[ny * w + nx as usize] += err * weight / divisor;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Format pixel data gambar ePOS (atribut `mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 1bpp packed (default)
+    Mono,
+    /// 4bpp grayscale (`mode="gray16"`)
+    Gray16,
+    /// 8bpp grayscale (`mode="gray8"`, ekstensi proxy)
+    Gray8,
+}
+
+impl PixelFormat {
+    pub fn parse(val: &str) -> Self {
+        match val.to_ascii_lowercase().as_str() {
+            "gray16" => PixelFormat::Gray16,
+            "gray8" | "gray256" => PixelFormat::Gray8,
+            _ => PixelFormat::Mono,
+        }
+    }
+
+    /// Jumlah byte data mentah untuk dimensi ini
+    pub fn raw_len(self, width: u32, height: u32) -> usize {
+        let row = match self {
+            PixelFormat::Mono => width.div_ceil(8),
+            PixelFormat::Gray16 => width.div_ceil(2),
+            PixelFormat::Gray8 => width,
+        };
+        row as usize * height as usize
+    }
+}
+
+/// Unpack grayscale 4bpp (ePOS `mode="gray16"`, 2 pixel per byte, high nibble dulu) ke 8-bit
+pub fn unpack_gray16(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let w = width as usize;
+    let row_bytes = w.div_ceil(2);
+    let mut out = Vec::with_capacity(w * height as usize);
+    for row in data.chunks(row_bytes).take(height as usize) {
+        for x in 0..w {
+            let byte = row.get(x / 2).copied().unwrap_or(0xFF);
+            let nibble = if x % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+            out.push(nibble * 17); // 0x0..0xF -> 0..255
+        }
+    }
+    out
+}
+
+/* ===================== PNG Decode ===================== */
+
+/// Gambar grayscale 8-bit hasil decode (0 = hitam, 255 = putih)
+#[derive(Clone)]
+pub struct GrayImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl std::fmt::Debug for GrayImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GrayImage({}x{})", self.width, self.height)
+    }
+}
+
+/// Jalankan decode/dither gambar di thread blocking supaya worker runtime tidak tertahan oleh
+/// pekerjaan CPU berat; span tracing request ikut dibawa supaya log tetap berisi job id
+pub async fn run_blocking<T, F>(f: F) -> Result<T, ProxyError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ProxyError> + Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f))
+        .await
+        .map_err(|e| {
+            tracing::error!("❌ Image task failed: {}", e);
+            ProxyError::Internal
+        })?
+}
+
+/// Decode PNG ke grayscale; alpha dikomposisikan di atas latar putih (kertas)
+pub fn decode_png(bytes: &[u8]) -> Result<GrayImage, ProxyError> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| ProxyError::BadPayload(format!("PNG invalid: {e}")))?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    let limits = &*crate::escpos::IMAGE_LIMITS;
+    limits.check(width, height)?;
+    // Header PNG kecil bisa mengklaim kanvas RGBA raksasa: cek ukuran buffer decode sebelum alokasi
+    let buffer_size = reader.output_buffer_size();
+    limits.check_buffer(buffer_size, "buffer decode PNG")?;
+
+    let mut buf = vec![0u8; buffer_size];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| ProxyError::BadPayload(format!("PNG invalid: {e}")))?;
+    let data = &buf[..frame.buffer_size()];
+
+    let channels = frame.color_type.samples();
+    let pixels = data
+        .chunks_exact(channels)
+        .map(|px| {
+            let (luma, alpha) = match px {
+                [g] => (*g as u32, 255),
+                [g, a] => (*g as u32, *a as u32),
+                [r, g, b] => ((*r as u32 * 299 + *g as u32 * 587 + *b as u32 * 114) / 1000, 255),
+                [r, g, b, a] => ((*r as u32 * 299 + *g as u32 * 587 + *b as u32 * 114) / 1000, *a as u32),
+                _ => (255, 255),
+            };
+            ((luma * alpha + 255 * (255 - alpha)) / 255) as u8
+        })
+        .collect();
+
+    Ok(GrayImage { width, height, pixels })
+}
+
+/// Perkecil gambar ke lebar maksimum (box filter, rasio dijaga); gambar yang lebih kecil tidak diubah
+pub fn downscale_gray(img: &GrayImage, max_width: u32) -> GrayImage {
+    if max_width == 0 || img.width <= max_width {
+        return img.clone();
+    }
+    let new_w = max_width;
+    let new_h = ((img.height as u64 * new_w as u64) / img.width as u64).max(1) as u32;
+    let (sw, sh) = (img.width as usize, img.height as usize);
+    let mut pixels = Vec::with_capacity(new_w as usize * new_h as usize);
+
+    for y in 0..new_h as usize {
+        let y0 = y * sh / new_h as usize;
+        let y1 = ((y + 1) * sh / new_h as usize).max(y0 + 1);
+        for x in 0..new_w as usize {
+            let x0 = x * sw / new_w as usize;
+            let x1 = ((x + 1) * sw / new_w as usize).max(x0 + 1);
+            let mut sum = 0u32;
+            for row in img.pixels[y0 * sw..y1 * sw].chunks(sw) {
+                sum += row[x0..x1].iter().map(|&p| p as u32).sum::<u32>();
+            }
+            pixels.push((sum / ((y1 - y0) * (x1 - x0)) as u32) as u8);
+        }
+    }
+
+    GrayImage { width: new_w, height: new_h, pixels }
+}
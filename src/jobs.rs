@@ -0,0 +1,65 @@
+use crate::errors::ProxyError;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Ringkasan satu job print untuk riwayat singkat di `/admin/jobs`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSummary {
+    pub job_id: String,
+    pub printer_id: String,
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<&'static str>,
+    pub bytes: usize,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Jumlah job yang disimpan (env `JOB_HISTORY_SIZE`, default 200, 0 = nonaktif)
+static JOB_HISTORY_SIZE: Lazy<usize> = Lazy::new(|| crate::settings::settings().job_history_size);
+
+/// Ring buffer job terbaru, hanya di memori (hilang saat restart)
+static JOB_HISTORY: Lazy<Mutex<VecDeque<JobSummary>>> =
+    Lazy::new(|| Mutex::new(VecDeque::with_capacity(*JOB_HISTORY_SIZE)));
+
+/// Catat hasil job; job terlama dibuang jika buffer penuh
+pub fn record(job_id: &str, printer_id: &str, mode: Option<&'static str>, bytes: usize, result: Result<(), &ProxyError>) {
+    crate::stats::record_job(printer_id, result);
+    let capacity = *JOB_HISTORY_SIZE;
+    if capacity == 0 {
+        return;
+    }
+    let entry = JobSummary {
+        job_id: job_id.to_string(),
+        printer_id: printer_id.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        mode,
+        bytes,
+        success: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+    };
+
+    let mut history = JOB_HISTORY.lock().unwrap_or_else(|e| e.into_inner());
+    while history.len() >= capacity {
+        history.pop_front();
+    }
+    history.push_back(entry);
+}
+
+/// Job terbaru dulu, opsional difilter per printer
+pub fn recent(printer_id: Option<&str>, limit: usize) -> Vec<JobSummary> {
+    let history = JOB_HISTORY.lock().unwrap_or_else(|e| e.into_inner());
+    history
+        .iter()
+        .rev()
+        .filter(|j| printer_id.is_none_or(|id| j.printer_id == id))
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+pub fn capacity() -> usize {
+    *JOB_HISTORY_SIZE
+}
@@ -1,25 +1,50 @@
 mod admin;
+mod authguard;
 mod backend;
+mod coalesce;
 mod config;
+mod deadletter;
+mod deferred;
 mod errors;
 mod escpos;
+mod faults;
+mod fetch;
+mod graphics;
 mod handlers;
 mod health;
+mod image;
+mod jobs;
+mod metrics;
+mod multipart;
 mod pool;
 mod printers;
+mod queue;
+mod settings;
+mod shadow;
+mod stats;
+mod systemd;
+mod trace;
+mod transform;
 
 use axum::{
     routing::{any, get},
     Router, serve,
 };
-use admin::{admin_shutdown, admin_restart, admin_renew_ssl, admin_status};
-use config::{load_config, build_printers_map};
-use handlers::{AppState, handle_print, health_check, printers_health_check, printer_health_check};
-use printers::{list_printers, get_printer, create_printer, update_printer, delete_printer, reload_printers};
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use admin::{admin_shutdown, admin_restart, admin_renew_ssl, admin_status, admin_drain, admin_deadletter_list, admin_deadletter_retry, admin_jobs, admin_stats, admin_stats_reset, admin_runtime_config, admin_print_adhoc, admin_printer_trace};
+use config::{load_config, validate_config, build_printers_map};
+use settings::Settings;
+use handlers::{AppState, handle_print, estimate_job, list_ops, health_check, health_stream, printers_health_check, printers_health_check_bulk, printer_health_check};
+use metrics::metrics;
+use printers::{list_printers, get_printer, create_printer, update_printer, delete_printer, reload_printers, reprint_printer, replace_config};
+use std::{future::IntoFuture, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 use tokio::{net::TcpListener, signal};
-use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
+use http::{header, HeaderName, HeaderValue, Method};
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
 use tracing::{error, info, warn};
 use tracing_subscriber::{
     fmt,
@@ -27,12 +52,54 @@ use tracing_subscriber::{
     util::SubscriberInitExt,
     EnvFilter,
 };
-use tracing_appender::{non_blocking, rolling};
+use tracing_appender::{
+    non_blocking,
+    rolling::{RollingFileAppender, Rotation},
+};
+
+/// File log dari setting: `LOG_DIR` (default `logs`), `LOG_ROTATION` ("daily" default, "hourly", "never"),
+/// `LOG_MAX_FILES` (jumlah file rotasi yang disimpan, default tanpa batas)
+struct LogFileSettings {
+    dir: String,
+    rotation: &'static str,
+    max_files: Option<usize>,
+}
+
+impl LogFileSettings {
+    fn from_settings(settings: &Settings) -> Self {
+        Self {
+            dir: settings.log_dir.clone(),
+            rotation: settings.log_rotation,
+            max_files: settings.log_max_files,
+        }
+    }
+
+    fn appender(&self) -> anyhow::Result<RollingFileAppender> {
+        let rotation = match self.rotation {
+            "hourly" => Rotation::HOURLY,
+            "never" => Rotation::NEVER,
+            _ => Rotation::DAILY,
+        };
+        let mut builder = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix("printer-proxy.log");
+        if let Some(n) = self.max_files {
+            builder = builder.max_log_files(n);
+        }
+        builder
+            .build(&self.dir)
+            .map_err(|e| anyhow::anyhow!("Gagal membuat log file di '{}': {}", self.dir, e))
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Setup file logging dengan rotasi harian
-    let file_appender = rolling::daily("logs", "printer-proxy.log");
+    // Semua env dibaca dan divalidasi sekali di sini
+    let settings = settings::init()?;
+
+    // Setup file logging, default rotasi harian di ./logs/
+    let log_settings = LogFileSettings::from_settings(&settings);
+    let file_appender = log_settings.appender()?;
     let (non_blocking_file, _guard) = non_blocking(file_appender);
     
     // Setup console logging
@@ -66,17 +133,24 @@ async fn main() -> anyhow::Result<()> {
         .init();
     
     info!("🚀 Starting Printer Proxy (ESC/POS) v{}", env!("CARGO_PKG_VERSION"));
-    info!("📝 Logs akan disimpan di folder: ./logs/");
+    stats::init();
+    info!(
+        "📝 Logs akan disimpan di folder: {}/ (rotasi {}, simpan {})",
+        log_settings.dir,
+        log_settings.rotation,
+        log_settings.max_files.map_or("semua file".to_string(), |n| format!("{} file", n))
+    );
     
     // Pastikan _guard tidak di-drop (untuk file logging)
     std::mem::forget(_guard);
     std::mem::forget(_stdout_guard);
 
-    let config_path = std::env::var("PRINTERS_CONFIG").unwrap_or_else(|_| "printers.yaml".to_string());
+    let config_path = settings.printers_config.clone();
     info!("📄 Loading config dari: {}", config_path);
     
     let config = load_config(&config_path)?;
-    let printers_map = build_printers_map(config);
+    validate_config(&config)?;
+    let (printers_map, aliases) = build_printers_map(config);
 
     if printers_map.is_empty() {
         error!("❌ Config tidak berisi printer apa pun");
@@ -88,21 +162,49 @@ async fn main() -> anyhow::Result<()> {
         info!("🖨️  Printer '{}' -> {:?}", id, printer.backend);
     }
 
+    shadow::log_startup(&printers_map);
+
+    if settings.test_fault_injection {
+        warn!("🧪 TEST_FAULT_INJECTION aktif: print endpoint menerima _delay_ms/_fail/_fail_rate, jangan dipakai di production");
+    }
+    if settings.disable_preflight {
+        warn!("⏭️ DISABLE_PREFLIGHT aktif: print endpoint tidak cek printer online sebelum kirim");
+    }
+
+    // Opsional: probe semua printer saat startup (tidak menggagalkan startup)
+    if settings.startup_health_check {
+        health::startup_health_sweep(&printers_map).await;
+    }
+
+    // Opsional: buka koneksi awal ke printer online supaya job pertama tidak menunggu connect
+    pool::prewarm_pools(&printers_map).await;
+
     let state = Arc::new(RwLock::new(AppState {
         printers: Arc::new(printers_map),
+        aliases: Arc::new(aliases),
+        settings: settings.clone(),
     }));
 
-    let app = Router::new()
-        // Health endpoints
-        .route("/healthz", get(health_check))
-        .route("/health/printers", get(printers_health_check))
-        .route("/health/printer/:printer_id", get(printer_health_check))
-        
+    // Timeout per kelas route: health/admin cepat gagal, print diberi waktu lebih untuk job besar
+    let health_timeout = Duration::from_secs(settings.health_timeout_secs);
+    let print_timeout = Duration::from_secs(settings.print_timeout_secs);
+    info!("⏱️ Timeouts: health/admin {:?}, print {:?}", health_timeout, print_timeout);
+
+    // Management endpoints (admin + printer CRUD); CORS untuk admin UI berbasis browser hanya jika diatur
+    let management = Router::new()
         // Admin endpoints (secured with token)
         .route("/admin/shutdown", get(admin_shutdown))
         .route("/admin/restart", get(admin_restart))
         .route("/admin/ssl/renew", get(admin_renew_ssl))
         .route("/admin/status", get(admin_status))
+        .route("/admin/drain", axum::routing::post(admin_drain))
+        .route("/admin/config/runtime", get(admin_runtime_config))
+        .route("/admin/deadletter", get(admin_deadletter_list))
+        .route("/admin/jobs", get(admin_jobs))
+        .route("/admin/stats", get(admin_stats))
+        .route("/admin/stats/reset", axum::routing::post(admin_stats_reset))
+        .route("/admin/print/adhoc", axum::routing::post(admin_print_adhoc))
+        .route("/admin/printer/:printer_id/trace", get(admin_printer_trace))
         
         // Printer CRUD endpoints (secured with token)
         .route("/api/printers", get(list_printers))
@@ -111,31 +213,71 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/printers/:printer_id", axum::routing::put(update_printer))
         .route("/api/printers/:printer_id", axum::routing::delete(delete_printer))
         .route("/api/printers/reload", get(reload_printers))
-        
-        // Endpoint kompatibel ePOS: /:printer_id/cgi-bin/epos/service.cgi
+        .route("/api/config", axum::routing::put(replace_config))
+        .route("/api/ops", get(list_ops))
+        .layer(axum::middleware::from_fn(authguard::guard))
+        .layer(TimeoutLayer::new(health_timeout))
+        // Endpoint management yang mencetak ulang job diberi timeout print, bukan timeout admin
+        .merge(
+            Router::new()
+                .route("/admin/deadletter/:id/retry", axum::routing::post(admin_deadletter_retry))
+                .route("/api/printers/:printer_id/reprint", axum::routing::post(reprint_printer))
+                .layer(axum::middleware::from_fn(authguard::guard))
+                .layer(TimeoutLayer::new(print_timeout)),
+        );
+    // Cross-origin hanya untuk origin yang didaftarkan di `CORS_ALLOW_ORIGIN`
+    let management = match management_cors() {
+        Some(cors) => management.layer(cors),
+        None => management,
+    };
+
+    // Health endpoints (`get` juga melayani HEAD: status sama tanpa body, untuk liveness probe)
+    let health = Router::new()
+        .route("/healthz", get(health_check))
+        .route("/health/printers", get(printers_health_check).post(printers_health_check_bulk))
+        .route("/health/printer/:printer_id", get(printer_health_check))
+        .route("/health/stream", get(health_stream))
+        .route("/metrics", get(metrics))
+        .layer(TimeoutLayer::new(health_timeout));
+
+    // Endpoint kompatibel ePOS: /:printer_id/cgi-bin/epos/service.cgi
+    let print = Router::new()
         .route("/:printer_id/cgi-bin/epos/service.cgi", any(handle_print))
-        .with_state(state)
-        .layer(TraceLayer::new_for_http())
-        .layer(TimeoutLayer::new(Duration::from_secs(30)));
-
-    let addr: SocketAddr = std::env::var("LISTEN_ADDR")
-        .unwrap_or_else(|_| "0.0.0.0:8080".to_string())
-        .parse()
-        .expect("LISTEN_ADDR invalid");
+        .route("/estimate", axum::routing::post(estimate_job))
+        .layer(TimeoutLayer::new(print_timeout));
+
+    let app = Router::new()
+        .merge(health)
+        .merge(management)
+        .merge(print)
+        .with_state(state.clone())
+        .layer(TraceLayer::new_for_http());
+
+    let addr = settings.listen_addr;
     
     info!("🌐 Server akan listen di: http://{}", addr);
     info!("🔗 Health check: http://{}/healthz", addr);
-    info!("🏥 Printers health: http://{}/health/printers", addr);
+    info!("🏥 Printers health: http://{}/health/printers (POST {{\"printer_ids\": [...]}} untuk subset)", addr);
     info!("🏥 Individual health: http://{}/health/printer/{{printer_id}}", addr);
+    info!("📡 Health stream (SSE): http://{}/health/stream", addr);
+    info!("📈 Metrics: http://{}/metrics", addr);
     info!("🖨️  Print endpoint: http://{}/{{printer_id}}/cgi-bin/epos/service.cgi", addr);
+    info!("📖 JSON ops schema: http://{}/api/ops", addr);
+    info!("📐 Estimate endpoint: POST http://{}/estimate?printer_id={{printer_id}}", addr);
     
     // Log admin endpoint info (but not show actual usage for security)
-    if std::env::var("ADMIN_TOKEN").is_ok() {
+    if settings.admin_token.is_some() {
         info!("🔒 Admin endpoints available (secured with ADMIN_TOKEN)");
         info!("🛑 Admin shutdown: GET /admin/shutdown?token=TOKEN");
         info!("🔄 Admin restart: GET /admin/restart?token=TOKEN");
         info!("🔐 Admin SSL renew: GET /admin/ssl/renew?token=TOKEN&domain=DOMAIN&port=PORT");
         info!("📊 Admin status: GET /admin/status?token=TOKEN");
+        info!("🚰 Admin drain: POST /admin/drain?token=TOKEN&enable=true|false");
+        info!("💀 Dead letters: GET /admin/deadletter?token=TOKEN, POST /admin/deadletter/{{id}}/retry?token=TOKEN");
+        info!("🧾 Recent jobs: GET /admin/jobs?token=TOKEN&printer_id=ID&limit=N");
+        info!("🧮 Stats: GET /admin/stats?token=TOKEN, POST /admin/stats/reset?token=TOKEN");
+        info!("⚙️ Runtime config: GET /admin/config/runtime?token=TOKEN");
+        info!("🧪 Ad-hoc print: POST /admin/print/adhoc?token=TOKEN&host=HOST&port=PORT");
         
         info!("🖨️  Printer CRUD endpoints available:");
         info!("📋 List printers: GET /api/printers?token=TOKEN");
@@ -144,6 +286,8 @@ async fn main() -> anyhow::Result<()> {
         info!("✏️  Update printer: PUT /api/printers/{{id}}?token=TOKEN");
         info!("🗑️  Delete printer: DELETE /api/printers/{{id}}?token=TOKEN");
         info!("🔄 Reload config: GET /api/printers/reload?token=TOKEN");
+        info!("📦 Replace config: PUT /api/config?token=TOKEN");
+        info!("🔁 Reprint last job: POST /api/printers/{{id}}/reprint?token=TOKEN");
     } else {
         warn!("⚠️  Admin and printer management endpoints disabled (ADMIN_TOKEN not set)");
     }
@@ -157,6 +301,8 @@ async fn main() -> anyhow::Result<()> {
         .expect("Failed to bind to address");
     
     info!("✅ Server siap menerima koneksi di {}", addr);
+    systemd::notify_ready();
+    tokio::spawn(systemd::start_watchdog_task());
     
     // Start background cleanup task
     tokio::spawn(async {
@@ -164,15 +310,91 @@ async fn main() -> anyhow::Result<()> {
     });
     info!("🧹 Background cleanup task started");
     
-    if let Err(e) = serve(listener, app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await {
-        error!("❌ Server error: {}", e);
+    // Start health polling for /health/stream subscribers
+    tokio::spawn(health::start_health_poll_task(state));
+    info!("🔁 Background health polling task started");
+    
+    // Setelah signal, axum menunggu request berjalan selesai; SHUTDOWN_GRACE_SECS membatasi tunggu itu
+    let grace = Duration::from_secs(settings.shutdown_grace_secs);
+    let (stopping_tx, stopping_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            let _ = stopping_tx.send(());
+        });
+
+    tokio::select! {
+        result = server.into_future() => {
+            if let Err(e) = result {
+                error!("❌ Server error: {}", e);
+            }
+        }
+        _ = force_exit_after_grace(stopping_rx, grace) => {}
+    }
+    // Job async_ok hanya ada di memori: simpan yang belum terkirim ke dead letter sebelum keluar
+    let pending = deferred::dead_letter_pending();
+    if pending > 0 {
+        warn!("📮 {} job async_ok belum terkirim, disimpan ke dead letter", pending);
     }
+    info!("👋 Server stopped");
 
     Ok(())
 }
 
+/// Tunggu signal shutdown, tutup koneksi idle di pool, lalu beri job yang masih berjalan waktu `grace`.
+/// Selesai (dan memaksa exit) hanya jika grace habis sebelum graceful shutdown axum selesai.
+async fn force_exit_after_grace(stopping: tokio::sync::oneshot::Receiver<()>, grace: Duration) {
+    if stopping.await.is_err() {
+        // Server berhenti tanpa signal (error), tidak ada yang ditunggu
+        return std::future::pending().await;
+    }
+    info!("⏳ Menunggu maksimal {:?} untuk {} job yang masih berjalan", grace, admin::in_flight_jobs());
+    let closed = pool::CONNECTION_MANAGER.drain_all_pools().await;
+    info!("🧹 {} koneksi idle ditutup", closed);
+
+    tokio::time::sleep(grace).await;
+    warn!(
+        "⏱️ Grace period shutdown ({:?}) habis, force exit dengan {} job masih berjalan",
+        grace,
+        admin::in_flight_jobs()
+    );
+}
+
+/// CORS untuk management API. Tanpa `CORS_ALLOW_ORIGIN` tidak ada CORS (hanya same-origin);
+/// cross-origin harus didaftarkan per origin (dipisah koma), wildcard `*` tidak diterima.
+fn management_cors() -> Option<CorsLayer> {
+    let configured = settings::settings().cors_allow_origin.as_deref()?;
+    let origins: Vec<HeaderValue> = configured
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .filter(|o| {
+            if *o == "*" {
+                warn!("⚠️ CORS_ALLOW_ORIGIN: wildcard '*' tidak diizinkan untuk admin API, diabaikan");
+                return false;
+            }
+            true
+        })
+        .filter_map(|o| HeaderValue::from_str(o).ok())
+        .collect();
+    if origins.is_empty() {
+        return None;
+    }
+    info!("🌍 Admin API CORS allowed for {} origin(s)", origins.len());
+
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static("x-admin-token"),
+            HeaderName::from_static("x-api-key"),
+        ])
+        .max_age(Duration::from_secs(600));
+    Some(cors)
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
@@ -196,5 +418,5 @@ async fn shutdown_signal() {
             warn!("🔄 SIGTERM signal diterima, shutting down gracefully...");
         },
     }
-    info!("👋 Server stopped");
+    systemd::notify_stopping();
 }
\ No newline at end of file
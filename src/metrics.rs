@@ -0,0 +1,47 @@
+use crate::{
+    config::Printer,
+    handlers::AppState,
+    health::{check_printer_health, PrinterStatus},
+};
+use axum::{extract::State, response::IntoResponse};
+use http::header::CONTENT_TYPE;
+use std::{fmt::Write, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::{debug, instrument};
+
+/// Escape label value sesuai Prometheus text exposition format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Prometheus metrics endpoint (text exposition format)
+#[instrument(skip(state))]
+pub async fn metrics(State(state): State<Arc<RwLock<AppState>>>) -> impl IntoResponse {
+    debug!("📈 Metrics requested");
+
+    let mut printers: Vec<Printer> = state.read().await.printers.values().cloned().collect();
+    printers.sort_by(|a, b| a.id.cmp(&b.id));
+
+    // Status diambil lewat health cache, jadi scrape tidak selalu memicu probe baru
+    let statuses = futures::future::join_all(
+        printers.iter().map(|p| async move { (p.id.as_str(), check_printer_health(p).await) }),
+    )
+    .await;
+
+    let mut out = String::new();
+    out.push_str("# HELP printer_proxy_printer_up Whether the printer is reachable (1 = online, 0 = offline).\n");
+    out.push_str("# TYPE printer_proxy_printer_up gauge\n");
+    for (id, status) in statuses {
+        let value = match status {
+            PrinterStatus::Online | PrinterStatus::Busy => 1,
+            PrinterStatus::Offline => 0,
+            PrinterStatus::Unknown => continue,
+        };
+        let _ = writeln!(out, "printer_proxy_printer_up{{printer_id=\"{}\"}} {}", escape_label(id), value);
+    }
+
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
@@ -0,0 +1,105 @@
+use crate::{
+    errors::ProxyError,
+    escpos::{JobVars, OpsBuildOptions, PrintOp},
+    image::{decode_png, run_blocking},
+};
+use bytes::Bytes;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// Jenis satu part `multipart/form-data`
+enum PartKind {
+    /// Field teks biasa -> op `text`
+    Text,
+    /// File PNG -> gambar raster (sama seperti op `image_url`)
+    Image,
+    /// `application/octet-stream` atau field bernama `raw` -> bytes ESC/POS apa adanya
+    Raw,
+}
+
+fn part_kind(name: Option<&str>, file_name: Option<&str>, content_type: Option<&str>) -> Result<PartKind, String> {
+    let ct = content_type.unwrap_or("").to_ascii_lowercase();
+    if ct == "application/octet-stream" || name.is_some_and(|n| n.eq_ignore_ascii_case("raw")) {
+        Ok(PartKind::Raw)
+    } else if ct == "image/png" || file_name.is_some_and(|f| f.to_ascii_lowercase().ends_with(".png")) {
+        Ok(PartKind::Image)
+    } else if file_name.is_none() && (ct.is_empty() || ct.starts_with("text/plain")) {
+        Ok(PartKind::Text)
+    } else {
+        Err(format!("content-type '{}' tidak didukung (text, image/png, application/octet-stream)", ct))
+    }
+}
+
+/// Bangun satu stream ESC/POS dari part multipart sesuai urutannya.
+///
+/// Part teks dan gambar dikumpulkan menjadi ops lalu dibangun di thread blocking (`build_blocking`);
+/// part raw disisipkan apa adanya di posisinya. `allow_raw` false jika printer tidak menerima mode raw.
+pub async fn build_from_multipart(
+    content_type: &str,
+    body: &[u8],
+    opts: OpsBuildOptions<'_>,
+    allow_raw: bool,
+) -> Result<Vec<u8>, ProxyError> {
+    let boundary = multer::parse_boundary(content_type)
+        .map_err(|e| ProxyError::BadPayload(format!("multipart boundary invalid: {}", e)))?;
+    let body = Bytes::copy_from_slice(body);
+    let stream = futures::stream::once(async move { Ok::<_, std::io::Error>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut out = Vec::new();
+    let mut ops: Vec<PrintOp> = Vec::new();
+    let mut index = 0usize;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ProxyError::BadPayload(format!("multipart invalid: {}", e)))?
+    {
+        let name = field.name().map(str::to_string);
+        let file_name = field.file_name().map(str::to_string);
+        let content_type = field.content_type().map(|m| m.essence_str().to_string());
+        let label = format!("part[{}] ({})", index, name.as_deref().unwrap_or("-"));
+        index += 1;
+
+        let kind = part_kind(name.as_deref(), file_name.as_deref(), content_type.as_deref())
+            .map_err(|e| ProxyError::BadPayload(format!("{}: {}", label, e)))?;
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| ProxyError::BadPayload(format!("{}: {}", label, e)))?;
+        debug!("🧩 {} {} bytes", label, data.len());
+
+        match kind {
+            PartKind::Text => {
+                let text = String::from_utf8(data.to_vec())
+                    .map_err(|_| ProxyError::BadPayload(format!("{}: teks harus UTF-8", label)))?;
+                ops.push(PrintOp::Text { data: text, newline: None, rtl: None, lang: None });
+            }
+            PartKind::Image => {
+                let image = run_blocking(move || decode_png(&data))
+                    .await
+                    .map_err(|e| ProxyError::BadPayload(format!("{}: {}", label, e)))?;
+                ops.push(PrintOp::ImageUrl { url: label, max_width: None, image: Some(Arc::new(image)) });
+            }
+            PartKind::Raw => {
+                if !allow_raw {
+                    warn!("🚫 {} berisi raw ESC/POS tapi mode raw tidak diizinkan", label);
+                    return Err(ProxyError::Forbidden(format!("{}: raw part tidak diizinkan untuk printer ini", label)));
+                }
+                if !ops.is_empty() {
+                    out.extend(opts.build_blocking(std::mem::take(&mut ops), JobVars::new()).await?);
+                }
+                out.extend_from_slice(&data);
+            }
+        }
+    }
+
+    if !ops.is_empty() {
+        out.extend(opts.build_blocking(ops, JobVars::new()).await?);
+    }
+    if out.is_empty() {
+        return Err(ProxyError::BadPayload("multipart tanpa part yang bisa dicetak".into()));
+    }
+    info!("🧩 Built {} ESC/POS bytes from {} multipart part(s)", out.len(), index);
+    Ok(out)
+}
@@ -1,48 +1,329 @@
 use crate::{
-    config::{Backend, Printer},
+    backend::{connect_tcp, connect_tls},
+    config::{serial_builder, Backend, Printer},
     errors::ProxyError,
+    escpos::{asb_error, has_status_reply, wrap_job, ASB_ENABLE, STATUS_REQUEST},
+    graphics::GraphicRefs,
     health::PrinterStatus,
 };
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use once_cell::sync::Lazy;
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
 use std::{
-    sync::Arc,
+    collections::HashMap,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use tokio::{
-    io::AsyncWriteExt,
+    io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
-    sync::{Mutex, Semaphore},
+    sync::{broadcast, Mutex, Semaphore},
     time::timeout,
 };
+use tokio_rustls::client::TlsStream;
 use tokio_serial::SerialPort;
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument, Span};
+
+/// Peringatkan jika beberapa id printer memakai pool yang sama (backend identik). Batas koneksi pool,
+/// retry sibuk, dan graphic yang sudah didefinisikan berlaku per backend, jadi job printer-printer
+/// tersebut saling antre di pool yang sama.
+pub fn warn_shared_pools<'a>(printers: impl IntoIterator<Item = &'a Printer>) {
+    let mut by_key: HashMap<String, Vec<&str>> = HashMap::new();
+    for printer in printers {
+        by_key.entry(ConnectionManager::pool_key(&printer.backend)).or_default().push(printer.id.as_str());
+    }
+    for (key, mut ids) in by_key {
+        if ids.len() > 1 {
+            ids.sort_unstable();
+            warn!("🔀 Printer {} memakai backend yang sama ({}) dan berbagi satu pool koneksi", ids.join(", "), key);
+        }
+    }
+}
 
 /// Connection types for different backends
 #[derive(Debug)]
 pub enum Connection {
     Tcp(TcpStream),
-    Usb(Box<dyn SerialPort>),
+    Tls(Box<TlsStream<TcpStream>>),
+    Usb(SharedSerial),
+}
+
+/// Port serial yang bisa dipindah ke thread blocking: `write`/`flush` serialport adalah syscall
+/// blocking (tcdrain) dan tidak boleh jalan di thread runtime
+type SharedSerial = Arc<std::sync::Mutex<Box<dyn SerialPort>>>;
+
+fn lock_serial(port: &SharedSerial) -> std::sync::MutexGuard<'_, Box<dyn SerialPort>> {
+    port.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Jalankan operasi blocking pada port serial lewat `spawn_blocking`
+async fn with_serial<T, F>(port: &SharedSerial, f: F) -> Result<T, std::io::Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Box<dyn SerialPort>) -> Result<T, std::io::Error> + Send + 'static,
+{
+    let port = port.clone();
+    tokio::task::spawn_blocking(move || f(&mut lock_serial(&port)))
+        .await
+        .map_err(|e| std::io::Error::other(format!("Serial task gagal: {}", e)))?
 }
 
 impl Connection {
     async fn write_all(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
         match self {
             Connection::Tcp(stream) => stream.write_all(buf).await,
-            Connection::Usb(port) => {
-                use std::io::Write;
-                port.write_all(buf)
-            }
+            Connection::Tls(stream) => stream.write_all(buf).await,
+            Connection::Usb(port) => write_serial_all(port, buf).await,
         }
     }
 
     async fn flush(&mut self) -> Result<(), std::io::Error> {
         match self {
             Connection::Tcp(stream) => stream.flush().await,
-            Connection::Usb(port) => {
-                use std::io::Write;
-                port.flush()
+            Connection::Tls(stream) => stream.flush().await,
+            Connection::Usb(port) => flush_serial(port).await,
+        }
+    }
+
+    /// Kumpulkan data yang dikirim printer (mis. ASB) selama `window`
+    async fn read_available(&mut self, window: Duration) -> Result<Vec<u8>, std::io::Error> {
+        self.read_until(window, |_| false).await
+    }
+
+    /// Baca data dari printer sampai `done` terpenuhi atau `window` habis
+    async fn read_until(&mut self, window: Duration, done: impl Fn(&[u8]) -> bool) -> Result<Vec<u8>, std::io::Error> {
+        let deadline = Instant::now() + window;
+        let mut data = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let n = match self {
+                Connection::Tcp(stream) => match timeout(remaining, stream.read(&mut buf)).await {
+                    Ok(result) => result?,
+                    Err(_) => break,
+                },
+                Connection::Tls(stream) => match timeout(remaining, stream.read(&mut buf)).await {
+                    Ok(result) => result?,
+                    Err(_) => break,
+                },
+                Connection::Usb(port) => {
+                    let read = {
+                        let mut port = lock_serial(port);
+                        match port.bytes_to_read()? {
+                            0 => None,
+                            _ => Some(std::io::Read::read(&mut *port, &mut buf)?),
+                        }
+                    };
+                    match read {
+                        Some(n) => n,
+                        None => {
+                            tokio::time::sleep(remaining.min(Duration::from_millis(10))).await;
+                            continue;
+                        }
+                    }
+                }
+            };
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+            if done(&data) {
+                break;
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Lama menunggu status ASB setelah job (env `ASB_READ_MS`, default 500)
+fn asb_read_window() -> Duration {
+    Duration::from_millis(crate::settings::settings().asb_read_ms)
+}
+
+/// Time budget for a serial transfer: ~10 bits per byte (8N1) at the port's baud rate, 2x margin + 5s
+fn serial_write_budget(port: &dyn SerialPort, len: usize) -> Duration {
+    let baud = port.baud_rate().unwrap_or(9600).max(1) as u64;
+    Duration::from_secs(5) + Duration::from_millis(len as u64 * 10 * 1000 * 2 / baud)
+}
+
+/// Perkiraan kasar lama kirim `len` byte lewat serial: ~10 bit per byte (8N1), tanpa margin
+fn serial_transfer_estimate(baud: u32, len: usize) -> Duration {
+    Duration::from_millis(len as u64 * 10 * 1000 / baud.max(1) as u64)
+}
+
+/// Progres tulis serial yang sedang berjalan, per device (`/admin/stats` → `serial_writes`)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SerialProgress {
+    pub device: String,
+    pub written: usize,
+    pub total: usize,
+    pub percent: f64,
+    pub elapsed_ms: u64,
+    /// Sisa waktu perkiraan dari baud rate (bukan dari laju aktual, karena flow control bisa menahan)
+    pub eta_ms: u64,
+}
+
+static SERIAL_WRITES: Lazy<DashMap<String, SerialProgress>> = Lazy::new(DashMap::new);
+
+/// Snapshot tulis serial yang sedang berjalan, urut per device
+pub fn serial_progress() -> Vec<SerialProgress> {
+    let mut writes: Vec<SerialProgress> = SERIAL_WRITES.iter().map(|e| e.value().clone()).collect();
+    writes.sort_by(|a, b| a.device.cmp(&b.device));
+    writes
+}
+
+/// Catat progres tulis serial; entri dihapus saat drop supaya error/timeout tidak meninggalkan sisa
+struct SerialWriteTracker {
+    device: String,
+    baud: u32,
+    total: usize,
+    started: Instant,
+    last_log: Instant,
+    logged_bytes: usize,
+    log_every_bytes: usize,
+    log_every: Duration,
+}
+
+impl SerialWriteTracker {
+    fn new(port: &dyn SerialPort, total: usize) -> Self {
+        let settings = crate::settings::settings();
+        let now = Instant::now();
+        let tracker = Self {
+            device: port.name().unwrap_or_else(|| "serial".into()),
+            baud: port.baud_rate().unwrap_or(9600),
+            total,
+            started: now,
+            last_log: now,
+            logged_bytes: 0,
+            log_every_bytes: settings.serial_progress_kb * 1024,
+            log_every: Duration::from_millis(settings.serial_progress_ms),
+        };
+        if tracker.is_long() {
+            info!(
+                "🔌 USB write to {} started: {} bytes, ~{:?} at {} baud",
+                tracker.device,
+                total,
+                serial_transfer_estimate(tracker.baud, total),
+                tracker.baud
+            );
+        }
+        tracker.publish(0);
+        tracker
+    }
+
+    /// Hanya transfer di atas satu interval log yang perlu dilaporkan
+    fn is_long(&self) -> bool {
+        self.total > self.log_every_bytes
+    }
+
+    fn snapshot(&self, written: usize) -> SerialProgress {
+        SerialProgress {
+            device: self.device.clone(),
+            written,
+            total: self.total,
+            percent: (written as f64 * 1000.0 / self.total.max(1) as f64).round() / 10.0,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            eta_ms: serial_transfer_estimate(self.baud, self.total - written).as_millis() as u64,
+        }
+    }
+
+    fn publish(&self, written: usize) {
+        SERIAL_WRITES.insert(self.device.clone(), self.snapshot(written));
+    }
+
+    fn advance(&mut self, written: usize) {
+        self.publish(written);
+        if !self.is_long() || written == self.total {
+            return;
+        }
+        if written - self.logged_bytes >= self.log_every_bytes || self.last_log.elapsed() >= self.log_every {
+            let progress = self.snapshot(written);
+            info!(
+                "⏳ USB write to {}: {}/{} bytes ({}%), ~{} ms left",
+                self.device, written, self.total, progress.percent, progress.eta_ms
+            );
+            self.logged_bytes = written;
+            self.last_log = Instant::now();
+        }
+    }
+}
+
+impl Drop for SerialWriteTracker {
+    fn drop(&mut self) {
+        SERIAL_WRITES.remove(&self.device);
+    }
+}
+
+/// Write the whole buffer to a serial port in chunks, logging progress for long transfers.
+/// Serial drivers may accept only part of the buffer, or report WouldBlock/TimedOut
+/// while the TX buffer is full, so keep writing the remainder until the deadline.
+async fn write_serial_all(port: &SharedSerial, buf: &[u8]) -> Result<(), std::io::Error> {
+    use std::io::{Error, ErrorKind, Write};
+
+    let (deadline, mut tracker) = {
+        let port = lock_serial(port);
+        (Instant::now() + serial_write_budget(port.as_ref(), buf.len()), SerialWriteTracker::new(port.as_ref(), buf.len()))
+    };
+    // Potongan kecil supaya progres bisa dilaporkan di tengah transfer
+    let chunk = tracker.log_every_bytes.min(4096);
+    let mut written = 0;
+    while written < buf.len() {
+        let data = buf[written..(written + chunk).min(buf.len())].to_vec();
+        match with_serial(port, move |port| port.write(&data)).await {
+            Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "Serial port menerima 0 byte")),
+            Ok(n) => {
+                written += n;
+                tracker.advance(written);
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if Instant::now() >= deadline {
+                    return Err(Error::new(
+                        ErrorKind::TimedOut,
+                        format!("Serial write timeout, {} byte belum terkirim", buf.len() - written),
+                    ));
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
             }
+            Err(e) => return Err(e),
+        }
+    }
+    if tracker.is_long() {
+        info!(
+            "✅ USB write to {} done: {} bytes in {:?}",
+            tracker.device,
+            buf.len(),
+            tracker.started.elapsed()
+        );
+    }
+    Ok(())
+}
+
+/// Drain the serial TX buffer (serialport's flush is tcdrain on POSIX), retrying transient errors
+async fn flush_serial(port: &SharedSerial) -> Result<(), std::io::Error> {
+    use std::io::{ErrorKind, Write};
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match with_serial(port, |port| port.flush()).await {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::TimedOut)
+                    && Instant::now() < deadline =>
+            {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            Err(e) => return Err(e),
         }
     }
 }
@@ -53,18 +334,28 @@ struct PooledConnection {
     connection: Connection,
     created_at: Instant,
     last_used: Instant,
+    /// Span covering the connection's lifetime; writes on this connection run inside it
+    span: Span,
+    /// Download graphics yang sudah dikirim lewat koneksi ini
+    graphics: crate::graphics::Defined,
 }
 
 impl PooledConnection {
-    fn new(connection: Connection) -> Self {
+    fn new(connection: Connection, pool_key: &str) -> Self {
         let now = Instant::now();
         Self {
             connection,
             created_at: now,
             last_used: now,
+            span: info_span!("pooled_connection", pool_key = %pool_key),
+            graphics: Default::default(),
         }
     }
 
+    fn age_secs(&self) -> u64 {
+        self.created_at.elapsed().as_secs()
+    }
+
     fn is_expired(&self, max_age: Duration) -> bool {
         self.created_at.elapsed() > max_age
     }
@@ -78,147 +369,518 @@ impl PooledConnection {
     }
 }
 
+/// Open a fresh connection to the backend.
+/// `busy_aware`: printer punya `busy_retries`, jadi koneksi ditolak berarti sibuk, bukan mati
+async fn open_connection(backend: &Backend, busy_aware: bool) -> Result<Connection, ProxyError> {
+    debug!("🔌 Creating new connection for {:?}", backend);
+    let connection = match backend {
+        Backend::Tcp9100 { host, port, bind_addr, tls, tls_ca, tls_skip_verify } => {
+            let addr = format!("{}:{}", host, port);
+            let stream = connect_tcp(host, *port, *bind_addr)
+                .await
+                .map_err(|e| {
+                    error!("❌ TCP connect to {} failed: {}", addr, e);
+                    // Printer 9100 satu-socket menolak koneksi kedua saat melayani client lain
+                    if e.kind() == std::io::ErrorKind::ConnectionRefused && busy_aware {
+                        ProxyError::PrinterBusy(format!("TCP connect {} ditolak", addr))
+                    } else {
+                        ProxyError::Io(format!("TCP connect {} gagal: {}", addr, e))
+                    }
+                })?;
+            if tls.unwrap_or(false) {
+                let stream = connect_tls(stream, host, tls_ca.as_deref(), tls_skip_verify.unwrap_or(false))
+                    .await
+                    .map_err(|e| {
+                        error!("❌ TLS handshake with {} failed: {}", addr, e);
+                        ProxyError::Io(format!("TLS handshake {} gagal: {}", addr, e))
+                    })?;
+                Connection::Tls(Box::new(stream))
+            } else {
+                Connection::Tcp(stream)
+            }
+        }
+        Backend::Usb { device, .. } => {
+            let port = serial_builder(backend)
+                .and_then(|b| b.open().map_err(|e| e.to_string()))
+                .map_err(|e| {
+                    error!("❌ USB serial connect to {} failed: {}", device, e);
+                    ProxyError::Io(format!("USB serial connect {} gagal: {}", device, e))
+                })?;
+            Connection::Usb(Arc::new(std::sync::Mutex::new(port)))
+        }
+    };
+
+    Ok(connection)
+}
+
 /// Connection pool for a specific printer
 #[derive(Debug)]
 struct PrinterPool {
+    /// Pool key (`tcp:host:port`, `usb:device:baud`), used as `pool_key` in events
+    key: String,
     connections: Mutex<Vec<PooledConnection>>,
     #[allow(dead_code)]
     semaphore: Arc<Semaphore>,
     max_connections: usize,
     max_age: Duration,
     max_idle: Duration,
+    /// Process-wide count of pooled (idle) connections, shared by all pools
+    pooled_total: Arc<AtomicUsize>,
 }
 
 impl PrinterPool {
-    fn new(max_connections: usize) -> Self {
+    fn new(key: String, max_connections: usize, pooled_total: Arc<AtomicUsize>) -> Self {
         Self {
+            key,
             connections: Mutex::new(Vec::with_capacity(max_connections)),
             semaphore: Arc::new(Semaphore::new(max_connections)),
             max_connections,
             max_age: Duration::from_secs(300), // 5 minutes
             max_idle: Duration::from_secs(60), // 1 minute
+            pooled_total,
         }
     }
 
-    async fn get_connection(&self, backend: &Backend) -> Result<Connection, ProxyError> {
+    async fn get_connection(&self, backend: &Backend, busy_aware: bool) -> Result<PooledConnection, ProxyError> {
         // Try to get an existing connection first
         {
             let mut connections = self.connections.lock().await;
             while let Some(mut conn) = connections.pop() {
-                if !conn.is_expired(self.max_age) && !conn.is_idle_too_long(self.max_idle) {
+                self.pooled_total.fetch_sub(1, Ordering::Relaxed);
+                let reason = if conn.is_expired(self.max_age) {
+                    "expired"
+                } else if conn.is_idle_too_long(self.max_idle) {
+                    "idle"
+                } else {
                     conn.mark_used();
-                    debug!("🔄 Reusing pooled connection for {:?}", backend);
-                    return Ok(conn.connection);
-                }
-                debug!("🗑️ Discarding expired/idle connection for {:?}", backend);
+                    crate::stats::record_pool_connection(true);
+                    debug!(pool_key = %self.key, age_secs = conn.age_secs(), reused = true, "🔄 Reusing pooled connection");
+                    return Ok(conn);
+                };
+                debug!(pool_key = %self.key, age_secs = conn.age_secs(), reason, "🗑️ Discarding pooled connection");
             }
         }
 
         // No valid connection available, create new one
-        debug!("🔌 Creating new connection for {:?}", backend);
-        let connection = match backend {
-            Backend::Tcp9100 { host, port } => {
-                let addr = format!("{}:{}", host, port);
-                let stream = TcpStream::connect(&addr)
-                    .await
-                    .map_err(|e| {
-                        error!("❌ TCP connect to {} failed: {}", addr, e);
-                        ProxyError::Io(format!("TCP connect {} gagal: {}", addr, e))
-                    })?;
-                Connection::Tcp(stream)
-            }
-            Backend::Usb { device, baud_rate } => {
-                let baud_rate = baud_rate.unwrap_or(9600); // Default baud rate for ESC/POS
-                let port = tokio_serial::new(device, baud_rate)
-                    .open()
-                    .map_err(|e| {
-                        error!("❌ USB serial connect to {} failed: {}", device, e);
-                        ProxyError::Io(format!("USB serial connect {} gagal: {}", device, e))
-                    })?;
-                Connection::Usb(port)
-            }
-        };
-
-        Ok(connection)
+        let conn = PooledConnection::new(open_connection(backend, busy_aware).await?, &self.key);
+        crate::stats::record_pool_connection(false);
+        debug!(pool_key = %self.key, age_secs = 0u64, reused = false, "🔌 Created new connection");
+        Ok(conn)
     }
 
-    async fn return_connection(&self, connection: Connection) {
+    /// Park a connection for reuse. The caller must already hold a slot in `pooled_total`;
+    /// returns false (slot not consumed) when this pool is full.
+    async fn return_connection(&self, mut conn: PooledConnection) -> bool {
         let mut connections = self.connections.lock().await;
         if connections.len() < self.max_connections {
-            connections.push(PooledConnection::new(connection));
-            debug!("📥 Returned connection to pool (total: {})", connections.len());
+            conn.mark_used();
+            debug!(pool_key = %self.key, age_secs = conn.age_secs(), pooled = connections.len() + 1, "📥 Returned connection to pool");
+            connections.push(conn);
+            true
         } else {
-            debug!("🗑️ Pool full, dropping connection");
+            debug!(pool_key = %self.key, age_secs = conn.age_secs(), reason = "pool_full", "🗑️ Discarding connection");
+            false
+        }
+    }
+
+    /// `last_used` of the least-recently-used idle connection in this pool
+    async fn oldest_idle(&self) -> Option<Instant> {
+        self.connections.lock().await.iter().map(|c| c.last_used).min()
+    }
+
+    /// Drop the least-recently-used idle connection, returns true if one was removed
+    async fn evict_oldest(&self) -> bool {
+        let mut connections = self.connections.lock().await;
+        let oldest = connections
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| c.last_used)
+            .map(|(i, _)| i);
+        match oldest {
+            Some(i) => {
+                let conn = connections.swap_remove(i);
+                debug!(pool_key = %self.key, age_secs = conn.age_secs(), reason = "evicted", "🗑️ Discarding pooled connection");
+                self.pooled_total.fetch_sub(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Buang semua koneksi idle, returns jumlah yang dibuang
+    async fn drain(&self) -> usize {
+        let mut connections = self.connections.lock().await;
+        for conn in connections.iter() {
+            debug!(pool_key = %self.key, age_secs = conn.age_secs(), reason = "drain", "🗑️ Discarding pooled connection");
+        }
+        let removed = connections.len();
+        connections.clear();
+        self.pooled_total.fetch_sub(removed, Ordering::Relaxed);
+        removed
+    }
+
     async fn cleanup_expired(&self) {
         let mut connections = self.connections.lock().await;
         let initial_count = connections.len();
         connections.retain(|conn| {
-            !conn.is_expired(self.max_age) && !conn.is_idle_too_long(self.max_idle)
+            let keep = !conn.is_expired(self.max_age) && !conn.is_idle_too_long(self.max_idle);
+            if !keep {
+                debug!(pool_key = %self.key, age_secs = conn.age_secs(), reason = "cleanup", "🗑️ Discarding pooled connection");
+            }
+            keep
         });
         let removed = initial_count - connections.len();
+        self.pooled_total.fetch_sub(removed, Ordering::Relaxed);
         if removed > 0 {
             debug!("🧹 Cleaned up {} expired connections", removed);
         }
     }
 }
 
+impl Drop for PrinterPool {
+    fn drop(&mut self) {
+        // Koneksi yang dikembalikan ke pool yang sudah dilepas (drain) tetap harus keluar dari budget
+        let remaining = self.connections.get_mut().len();
+        self.pooled_total.fetch_sub(remaining, Ordering::Relaxed);
+    }
+}
+
+/// Koneksi milik satu sesi interaktif
+type SessionSlot = Arc<Mutex<Option<PooledConnection>>>;
+
 /// Global connection pool manager
 #[derive(Debug)]
 pub struct ConnectionManager {
     pools: DashMap<String, Arc<PrinterPool>>,
+    /// Connections reserved by interactive sessions, keyed by `printer_id:session_id`.
+    /// Slot kosong berarti sesi belum (atau tidak lagi) memegang koneksi
+    sessions: DashMap<String, SessionSlot>,
+    session_idle: Duration,
+    /// Idle connections parked across all pools
+    pooled_total: Arc<AtomicUsize>,
+    /// Global pooled connection budget (`MAX_TOTAL_POOLED_CONNECTIONS`), None = unlimited
+    max_total_pooled: Option<usize>,
 }
 
 impl ConnectionManager {
     fn new() -> Self {
+        let max_total_pooled = crate::settings::settings().max_total_pooled_connections;
+
         Self {
             pools: DashMap::new(),
+            sessions: DashMap::new(),
+            session_idle: Duration::from_secs(300), // 5 minutes
+            pooled_total: Arc::new(AtomicUsize::new(0)),
+            max_total_pooled,
         }
     }
 
-    fn get_pool(&self, backend: &Backend) -> Arc<PrinterPool> {
-        let pool_key = match backend {
-            Backend::Tcp9100 { host, port } => format!("tcp:{}:{}", host, port),
-            Backend::Usb { device, baud_rate } => {
-                let baud = baud_rate.unwrap_or(9600);
-                format!("usb:{}:{}", device, baud)
+    /// Current pooled connection count and the global budget
+    pub fn pooled_stats(&self) -> (usize, Option<usize>) {
+        (self.pooled_total.load(Ordering::Relaxed), self.max_total_pooled)
+    }
+
+    /// Reserve a slot in the global budget
+    fn try_reserve_slot(&self) -> bool {
+        let max = self.max_total_pooled.unwrap_or(usize::MAX);
+        self.pooled_total
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| (n < max).then_some(n + 1))
+            .is_ok()
+    }
+
+    /// Evict the least-recently-used idle connection across all pools
+    async fn evict_lru_idle(&self) -> bool {
+        let pools: Vec<Arc<PrinterPool>> = self.pools.iter().map(|e| e.value().clone()).collect();
+        let mut oldest: Option<(Instant, &Arc<PrinterPool>)> = None;
+        for pool in &pools {
+            if let Some(last_used) = pool.oldest_idle().await {
+                if oldest.is_none_or(|(t, _)| last_used < t) {
+                    oldest = Some((last_used, pool));
+                }
+            }
+        }
+        match oldest {
+            Some((_, pool)) => pool.evict_oldest().await,
+            None => false,
+        }
+    }
+
+    /// Return a connection to its pool, respecting the global budget.
+    /// Over budget, the LRU idle connection is evicted first; if nothing can be evicted
+    /// the connection is simply closed (it was still usable as a transient connection).
+    async fn return_to_pool(&self, pool: &PrinterPool, connection: PooledConnection) {
+        let reserved = self.try_reserve_slot() || (self.evict_lru_idle().await && self.try_reserve_slot());
+        if !reserved {
+            debug!(pool_key = %pool.key, age_secs = connection.age_secs(), reason = "budget", "🗑️ Global pool budget reached, dropping connection");
+            return;
+        }
+        if !pool.return_connection(connection).await {
+            self.pooled_total.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Open up to `count` connections and park them in the printer's pool (capped by pool size).
+    /// Stops at the global budget instead of evicting other pools' idle connections.
+    pub async fn prewarm(&self, printer: &Printer, count: usize) -> Result<usize, ProxyError> {
+        let pool = self.get_pool(&printer.backend);
+        let target = count.min(pool.max_connections);
+        let mut opened = 0;
+
+        while opened < target {
+            if !self.try_reserve_slot() {
+                debug!("🗑️ Global pool budget reached while prewarming '{}'", printer.id);
+                break;
             }
+            let connection = match open_connection(&printer.backend, printer.busy_retry().is_some()).await {
+                Ok(c) => PooledConnection::new(c, &pool.key),
+                Err(e) => {
+                    self.pooled_total.fetch_sub(1, Ordering::Relaxed);
+                    if opened == 0 {
+                        return Err(e);
+                    }
+                    break;
+                }
+            };
+            if !pool.return_connection(connection).await {
+                self.pooled_total.fetch_sub(1, Ordering::Relaxed);
+                break;
+            }
+            opened += 1;
+        }
+
+        Ok(opened)
+    }
+
+    /// Write `payload` on a throwaway (non-pooled) connection, to verify the device really accepts data
+    pub async fn test_write(&self, backend: &Backend, payload: &[u8]) -> Result<(), ProxyError> {
+        let target_desc = Self::target_desc(backend);
+        let attempt = async {
+            let mut connection = open_connection(backend, false).await?;
+            Self::write_payload(&mut connection, payload, &target_desc)
+                .await
+                .map_err(|e| ProxyError::Io(format!("Write {} gagal: {}", target_desc, e)))
+        };
+        timeout(Duration::from_secs(5), attempt)
+            .await
+            .map_err(|_| ProxyError::Io(format!("Write {} timeout", target_desc)))?
+    }
+
+    /// Kirim beberapa perintah query pada koneksi terpisah (bukan dari pool) dan kumpulkan balasan
+    /// masing-masing selama `window`; dipakai untuk perintah yang dijawab printer (mis. GS I)
+    pub async fn query(&self, backend: &Backend, requests: &[&[u8]], window: Duration) -> Result<Vec<Vec<u8>>, ProxyError> {
+        let target_desc = Self::target_desc(backend);
+        let attempt = async {
+            let mut connection = open_connection(backend, false).await?;
+            let mut replies = Vec::with_capacity(requests.len());
+            for request in requests {
+                Self::write_payload(&mut connection, request, &target_desc)
+                    .await
+                    .map_err(|e| ProxyError::Io(format!("Write {} gagal: {}", target_desc, e)))?;
+                let reply = connection
+                    .read_available(window)
+                    .await
+                    .map_err(|e| ProxyError::Io(format!("Read {} gagal: {}", target_desc, e)))?;
+                debug!("📟 Query {:02X?} -> {:02X?}", request, reply);
+                replies.push(reply);
+            }
+            Ok(replies)
         };
+        timeout(Duration::from_secs(5), attempt)
+            .await
+            .map_err(|_| ProxyError::Io(format!("Query {} timeout", target_desc)))?
+    }
+
+    /// Key pool untuk backend. Pool dibagi per alamat backend, bukan per id printer: beberapa id yang
+    /// menunjuk host:port (atau device) yang sama memakai satu pool dan satu batas koneksi.
+    pub fn pool_key(backend: &Backend) -> String {
+        match backend {
+            Backend::Tcp9100 { host, port, bind_addr, tls, .. } => {
+                let scheme = if tls.unwrap_or(false) { "tls" } else { "tcp" };
+                match bind_addr {
+                    Some(ip) => format!("{}:{}:{}@{}", scheme, host, port, ip),
+                    None => format!("{}:{}:{}", scheme, host, port),
+                }
+            }
+            Backend::Usb { device, baud_rate, data_bits, parity, stop_bits, flow_control } => {
+                let baud = baud_rate.unwrap_or(9600);
+                // Setting line non-default masuk key supaya perubahan config tidak memakai port lama
+                if data_bits.is_some() || parity.is_some() || stop_bits.is_some() || flow_control.is_some() {
+                    format!(
+                        "usb:{}:{}:{}{}{}:{}",
+                        device,
+                        baud,
+                        data_bits.unwrap_or(8),
+                        parity.as_deref().unwrap_or("none"),
+                        stop_bits.unwrap_or(1),
+                        flow_control.as_deref().unwrap_or("none")
+                    )
+                } else {
+                    format!("usb:{}:{}", device, baud)
+                }
+            }
+        }
+    }
+
+    fn get_pool(&self, backend: &Backend) -> Arc<PrinterPool> {
+        let pool_key = Self::pool_key(backend);
         
         self.pools
-            .entry(pool_key)
-            .or_insert_with(|| Arc::new(PrinterPool::new(5))) // Max 5 connections per printer
+            .entry(pool_key.clone())
+            .or_insert_with(|| Arc::new(PrinterPool::new(pool_key, 5, self.pooled_total.clone()))) // Max 5 connections per printer
             .clone()
     }
 
-    pub async fn send_to_printer(&self, printer: &Printer, payload: &[u8]) -> Result<(), ProxyError> {
-        let pool = self.get_pool(&printer.backend);
-        let mut connection = pool.get_connection(&printer.backend).await?;
-
-        let target_desc = match &printer.backend {
-            Backend::Tcp9100 { host, port } => format!("{}:{}", host, port),
-            Backend::Usb { device, baud_rate } => {
+    fn target_desc(backend: &Backend) -> String {
+        match backend {
+            Backend::Tcp9100 { host, port, .. } => format!("{}:{}", host, port),
+            Backend::Usb { device, baud_rate, .. } => {
                 let baud = baud_rate.unwrap_or(9600);
                 format!("{}@{}", device, baud)
             }
-        };
+        }
+    }
 
+    async fn write_payload(connection: &mut Connection, payload: &[u8], target_desc: &str) -> Result<(), std::io::Error> {
         info!("📦 Sending {} bytes to {}", payload.len(), target_desc);
         debug!("📦 Payload preview: {:02X?}", &payload[..payload.len().min(32)]);
 
-        let result = async {
-            connection.write_all(payload).await?;
-            connection.flush().await?;
-            Ok::<(), std::io::Error>(())
-        }.await;
+        connection.write_all(payload).await?;
+        connection.flush().await
+    }
+
+    /// Ambil koneksi dari pool dalam batas `acquire_timeout` printer, supaya request tidak menggantung
+    /// sampai timeout global saat printer tidak pernah melepas/menerima koneksi
+    async fn acquire(&self, pool: &PrinterPool, printer: &Printer) -> Result<PooledConnection, ProxyError> {
+        let limit = printer.acquire_timeout();
+        match timeout(limit, self.acquire_with_retry(pool, printer)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(pool_key = %pool.key, "⏰ Acquire connection for '{}' timed out after {:?}", printer.id, limit);
+                Err(ProxyError::PoolTimeout(format!("{} ({:?})", pool.key, limit)))
+            }
+        }
+    }
+
+    /// Jika printer menolak koneksi (sibuk) dan `busy_retries` di-set, tunggu lalu coba lagi
+    async fn acquire_with_retry(&self, pool: &PrinterPool, printer: &Printer) -> Result<PooledConnection, ProxyError> {
+        let Some((retries, delay)) = printer.busy_retry() else {
+            return pool.get_connection(&printer.backend, false).await;
+        };
+        let mut attempt = 0;
+        loop {
+            match pool.get_connection(&printer.backend, true).await {
+                Err(ProxyError::PrinterBusy(msg)) if attempt < retries => {
+                    attempt += 1;
+                    info!("🟠 Printer '{}' busy ({}), retry {}/{} in {:?}", printer.id, msg, attempt, retries, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn send_to_printer(&self, printer: &Printer, payload: &[u8], refs: GraphicRefs) -> Result<(), ProxyError> {
+        let retries = if printer.options.confirm_delivery.unwrap_or(false) {
+            printer.options.confirm_retries.unwrap_or(0)
+        } else {
+            0
+        };
+        // Default hanya tulis/connect yang gagal dikirim ulang. Job yang sudah tertulis tapi tidak
+        // terkonfirmasi mungkin sudah tercetak (jawaban terlambat), jadi hanya diulang jika diminta
+        let retry_unconfirmed = printer.options.confirm_retry_unconfirmed.unwrap_or(false);
+        let mut attempt = 0;
+        loop {
+            match self.send_to_printer_once(printer, payload, refs).await {
+                Err(ProxyError::Io(reason)) if attempt < retries => {
+                    attempt += 1;
+                    warn!("🔁 {} - resending job to '{}' ({}/{})", reason, printer.id, attempt, retries);
+                }
+                Err(ProxyError::Unconfirmed(reason)) if retry_unconfirmed && attempt < retries => {
+                    attempt += 1;
+                    warn!("🔁 {} - resending unconfirmed job to '{}' ({}/{}), may print twice", reason, printer.id, attempt, retries);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_to_printer_once(&self, printer: &Printer, payload: &[u8], refs: GraphicRefs) -> Result<(), ProxyError> {
+        let pool = self.get_pool(&printer.backend);
+        let mut connection = self.acquire(&pool, printer).await?;
+
+        // ASB: printer mengirim status (paper out, cover open) tanpa diminta setelah GS a diaktifkan
+        let monitor_asb = printer.options.monitor_asb.unwrap_or(false);
+        let with_asb;
+        let payload = if monitor_asb {
+            with_asb = wrap_job(payload, &ASB_ENABLE, &[]);
+            &with_asb[..]
+        } else {
+            payload
+        };
+        let payload = crate::graphics::prepare(&printer.id, payload, refs, &connection.graphics);
+        // confirm_delivery: DLE EOT 1 di ujung job, printer menjawab setelah menerima semua byte sebelumnya
+        let confirm = printer.options.confirm_delivery.unwrap_or(false);
+        let payload = &*if confirm {
+            let mut with_request = Vec::with_capacity(payload.len() + STATUS_REQUEST.len());
+            with_request.extend_from_slice(&payload);
+            with_request.extend_from_slice(&STATUS_REQUEST);
+            std::borrow::Cow::Owned(with_request)
+        } else {
+            payload
+        };
+        crate::trace::publish(&printer.id, None, payload);
+
+        let target_desc = Self::target_desc(&printer.backend);
+        let span = connection.span.clone();
+        let result = Self::write_payload(&mut connection.connection, payload, &target_desc)
+            .instrument(span)
+            .await;
 
         match result {
             Ok(()) => {
+                // Payload sudah diterima printer: define graphic di dalamnya baru didaftarkan sekarang
+                crate::graphics::commit(&printer.id, payload, &mut connection.graphics);
+                let mut status = Vec::new();
+                if confirm {
+                    let window = Duration::from_millis(printer.options.confirm_timeout_ms.unwrap_or(2000));
+                    match connection.connection.read_until(window, has_status_reply).await {
+                        Ok(reply) if has_status_reply(&reply) => {
+                            debug!("📨 {} confirmed job: {:02X?}", target_desc, reply);
+                            status = reply;
+                        }
+                        Ok(reply) => {
+                            // Jawaban yang terlambat bisa muncul di job berikutnya: koneksi dibuang
+                            warn!("❓ {} did not confirm job within {:?} (got {:02X?})", target_desc, window, reply);
+                            return Err(ProxyError::Unconfirmed(format!("{} tidak mengonfirmasi job dalam {:?}", target_desc, window)));
+                        }
+                        Err(e) => {
+                            warn!("⚠️ Reading confirmation from {} failed: {}", target_desc, e);
+                            return Err(ProxyError::Unconfirmed(format!("Konfirmasi dari {} gagal dibaca: {}", target_desc, e)));
+                        }
+                    }
+                }
+                if monitor_asb {
+                    match connection.connection.read_available(asb_read_window()).await {
+                        Ok(asb) => {
+                            status.extend_from_slice(&asb);
+                            debug!("📟 ASB from {}: {:02X?}", target_desc, status);
+                            if let Some(fault) = asb_error(&status) {
+                                warn!("🚨 Printer {} reported {} during job", target_desc, fault);
+                                // Printer yang error masih bisa mengirim ASB lanjutan (mis. saat kertas diisi);
+                                // koneksi dibuang supaya status lama tidak terbaca sebagai status job berikutnya
+                                return Err(ProxyError::PrinterFault(fault.to_string()));
+                            }
+                        }
+                        Err(e) => {
+                            // Koneksi rusak saat membaca status: jangan dikembalikan ke pool
+                            warn!("⚠️ Reading ASB from {} failed: {}", target_desc, e);
+                            return Ok(());
+                        }
+                    }
+                }
                 info!("✅ Successfully sent {} bytes to {}", payload.len(), target_desc);
                 // Return connection to pool for reuse
-                pool.return_connection(connection).await;
+                self.return_to_pool(&pool, connection).await;
                 Ok(())
             }
             Err(e) => {
@@ -229,10 +891,97 @@ impl ConnectionManager {
         }
     }
 
+    /// Send payload on a connection reserved for an interactive session.
+    /// The connection stays pinned to the session until `end` is set, then goes back to the pool.
+    pub async fn send_to_printer_in_session(
+        &self,
+        printer: &Printer,
+        session_id: &str,
+        payload: &[u8],
+        end: bool,
+        refs: GraphicRefs,
+    ) -> Result<(), ProxyError> {
+        let session_key = format!("{}:{}", printer.id, session_id);
+        let pool = self.get_pool(&printer.backend);
+
+        // Request paralel dalam sesi yang sama antre di lock slot, jadi sesi selalu memegang tepat
+        // satu koneksi dan koneksi itu tidak pernah ditulis bersamaan
+        let mut slot = loop {
+            let slot = self.sessions.entry(session_key.clone()).or_default().clone();
+            let guard = slot.clone().lock_owned().await;
+            // Slot bisa dilepas (session_end/cleanup) selagi menunggu lock: ambil slot yang berlaku
+            if self.sessions.get(&session_key).is_some_and(|s| Arc::ptr_eq(&s, &slot)) {
+                break guard;
+            }
+        };
+        let mut connection = match slot.take() {
+            Some(conn) => {
+                debug!("📌 Reusing session connection for {}", session_key);
+                conn
+            }
+            None => {
+                debug!("📌 Reserving new session connection for {}", session_key);
+                self.acquire(&pool, printer).await?
+            }
+        };
+
+        let payload = &*crate::graphics::prepare(&printer.id, payload, refs, &connection.graphics);
+        crate::trace::publish(&printer.id, Some(session_id), payload);
+        let target_desc = Self::target_desc(&printer.backend);
+        let span = connection.span.clone();
+        match Self::write_payload(&mut connection.connection, payload, &target_desc)
+            .instrument(span)
+            .await
+        {
+            Ok(()) => {
+                crate::graphics::commit(&printer.id, payload, &mut connection.graphics);
+                if end {
+                    info!("📌 Session {} ended, releasing connection", session_key);
+                    self.sessions.remove(&session_key);
+                    self.return_to_pool(&pool, connection).await;
+                } else {
+                    connection.mark_used();
+                    *slot = Some(connection);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("❌ Session write to {} failed: {}", target_desc, e);
+                Err(ProxyError::Io(format!("Write {} gagal: {}", target_desc, e)))
+            }
+        }
+    }
+
+    /// Tutup semua koneksi idle dan lepas semua pool supaya koneksi berikutnya connect (dan resolve DNS) ulang.
+    /// Koneksi yang sedang dipakai kembali ke pool lama lalu ikut dibuang.
+    pub async fn drain_all_pools(&self) -> usize {
+        let pools: Vec<Arc<PrinterPool>> = self.pools.iter().map(|e| e.value().clone()).collect();
+        self.pools.clear();
+        let mut removed = 0;
+        for pool in pools {
+            removed += pool.drain().await;
+        }
+        crate::backend::clear_dns_cache();
+        info!("🚿 Drained {} pooled connection(s) and DNS cache", removed);
+        removed
+    }
+
     pub async fn cleanup_all_pools(&self) {
         for entry in self.pools.iter() {
             entry.value().cleanup_expired().await;
         }
+
+        // Drop sessions that were abandoned without session_end
+        let initial_count = self.sessions.len();
+        // Slot yang sedang dipakai (terkunci) dilewati; slot kosong sisa write gagal ikut dibuang
+        self.sessions.retain(|_, slot| match slot.try_lock() {
+            Ok(conn) => conn.as_ref().is_some_and(|c| !c.is_idle_too_long(self.session_idle)),
+            Err(_) => true,
+        });
+        let removed = initial_count - self.sessions.len();
+        if removed > 0 {
+            debug!("🧹 Released {} idle session connections", removed);
+        }
     }
 }
 
@@ -259,67 +1008,163 @@ impl HealthCacheEntry {
     }
 }
 
+/// Health status change event, published when a printer's checked status differs from the last one
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HealthEvent {
+    pub printer_id: String,
+    pub status: &'static str,
+    pub timestamp: String,
+}
+
 /// Health check cache manager
 #[derive(Debug)]
 pub struct HealthCache {
     cache: DashMap<String, HealthCacheEntry>,
     ttl: Duration,
+    /// Last known status per printer id (not expired by cleanup, used for change detection)
+    last_status: DashMap<String, PrinterStatus>,
+    events: broadcast::Sender<HealthEvent>,
 }
 
 impl HealthCache {
     fn new(ttl: Duration) -> Self {
+        let (events, _) = broadcast::channel(64);
         Self {
             cache: DashMap::new(),
             ttl,
+            last_status: DashMap::new(),
+            events,
         }
     }
 
-    pub async fn get_or_check(&self, printer: &Printer) -> PrinterStatus {
-        let cache_key = format!("{}:{}", printer.id, match &printer.backend {
-            Backend::Tcp9100 { host, port } => format!("tcp:{}:{}", host, port),
-            Backend::Usb { device, baud_rate } => {
+    /// Subscribe to health status change events
+    pub fn subscribe(&self) -> broadcast::Receiver<HealthEvent> {
+        self.events.subscribe()
+    }
+
+    /// Cache TTL (status offline paling lama dipakai ulang selama ini)
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Number of active status subscribers (SSE clients)
+    pub fn subscriber_count(&self) -> usize {
+        self.events.receiver_count()
+    }
+
+    fn publish_if_changed(&self, printer_id: &str, status: &PrinterStatus) {
+        let previous = self.last_status.insert(printer_id.to_string(), status.clone());
+        if previous.as_ref() != Some(status) {
+            debug!("📣 Printer '{}' status changed: {:?} -> {:?}", printer_id, previous, status);
+            // Error only means there are no subscribers right now
+            let _ = self.events.send(HealthEvent {
+                printer_id: printer_id.to_string(),
+                status: status.as_str(),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            });
+        }
+    }
+
+    fn cache_key(printer: &Printer) -> String {
+        format!("{}:{}", printer.id, match &printer.backend {
+            Backend::Tcp9100 { host, port, .. } => format!("tcp:{}:{}", host, port),
+            Backend::Usb { device, baud_rate, .. } => {
                 let baud = baud_rate.unwrap_or(9600);
                 format!("usb:{}:{}", device, baud)
             }
-        });
+        })
+    }
+
+    pub async fn get_or_check(&'static self, printer: &Printer) -> PrinterStatus {
+        let cache_key = Self::cache_key(printer);
 
         // Try cache first
         if let Some(entry) = self.cache.get(&cache_key) {
             if !entry.is_expired(self.ttl) {
                 debug!("💾 Health cache hit for {}", cache_key);
+                crate::stats::record_health_cache(true);
                 return entry.status.clone();
             }
             debug!("⏰ Health cache expired for {}", cache_key);
         }
 
         // Cache miss or expired, perform actual health check
-        debug!("🔍 Performing health check for {}", cache_key);
-        let status = self.check_printer_health_direct(printer).await;
-        
-        // Update cache
-        self.cache.insert(cache_key, HealthCacheEntry::new(status.clone()));
-        
-        status
+        crate::stats::record_health_cache(false);
+        self.probe_and_store(printer, cache_key).await
+    }
+
+    /// Untuk preflight print: hanya status online yang masih fresh dipakai dari cache (tanpa probe).
+    /// Offline/busy/unknown atau expired selalu di-probe ulang supaya printer yang baru menyala langsung bisa dipakai.
+    pub async fn get_online_or_check(&'static self, printer: &Printer) -> PrinterStatus {
+        let cache_key = Self::cache_key(printer);
+
+        if let Some(entry) = self.cache.get(&cache_key) {
+            if entry.status == PrinterStatus::Online && !entry.is_expired(self.ttl) {
+                debug!("💾 Health cache hit (online) for {}, skipping preflight probe", cache_key);
+                crate::stats::record_health_cache(true);
+                return PrinterStatus::Online;
+            }
+        }
+
+        crate::stats::record_health_cache(false);
+        self.probe_and_store(printer, cache_key).await
+    }
+
+    /// Probe printer dan simpan hasilnya. Request bersamaan untuk printer yang sama menunggu probe
+    /// yang sedang berjalan dan memakai hasilnya, jadi cache yang expired di bawah beban tetap satu probe.
+    async fn probe_and_store(&'static self, printer: &Printer, cache_key: String) -> PrinterStatus {
+        let probe = match HEALTH_PROBES.entry(cache_key.clone()) {
+            Entry::Occupied(entry) => {
+                debug!("🔍 Health check for {} already running, sharing its result", cache_key);
+                entry.get().clone()
+            }
+            Entry::Vacant(entry) => {
+                let printer = printer.clone();
+                let probe = async move {
+                    debug!("🔍 Performing health check for {}", cache_key);
+                    let status = self.check_printer_health_direct(&printer).await;
+
+                    // Update cache
+                    self.cache.insert(cache_key.clone(), HealthCacheEntry::new(status.clone()));
+                    self.publish_if_changed(&printer.id, &status);
+                    HEALTH_PROBES.remove(&cache_key);
+
+                    status
+                }
+                .boxed()
+                .shared();
+                entry.insert(probe.clone());
+                probe
+            }
+        };
+        probe.await
     }
 
     #[instrument(skip(self, printer), fields(printer_id = %printer.id))]
     async fn check_printer_health_direct(&self, printer: &Printer) -> PrinterStatus {
         match &printer.backend {
-            Backend::Tcp9100 { host, port } => {
+            Backend::Tcp9100 { host, port, bind_addr, tls, tls_ca, tls_skip_verify } => {
                 let addr = format!("{}:{}", host, port);
                 debug!("🔍 Direct TCP health check for {}", addr);
                 
-                // Quick connection test with short timeout
-                let check_result = timeout(
-                    Duration::from_millis(1500), // Reduced from 2 seconds
-                    TcpStream::connect(&addr)
-                ).await;
+                // Quick connection test with short timeout (incl. TLS handshake for TLS printers)
+                let check_result = timeout(Duration::from_millis(1500), async {
+                    let stream = connect_tcp(host, *port, *bind_addr).await?;
+                    if tls.unwrap_or(false) {
+                        connect_tls(stream, host, tls_ca.as_deref(), tls_skip_verify.unwrap_or(false)).await?;
+                    }
+                    Ok::<_, std::io::Error>(())
+                }).await;
                 
                 match check_result {
                     Ok(Ok(_stream)) => {
                         debug!("✅ TCP health check passed for {}", addr);
                         PrinterStatus::Online
                     }
+                    Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused && printer.busy_retry().is_some() => {
+                        debug!("🟠 TCP health check refused for {} (printer busy?)", addr);
+                        PrinterStatus::Busy
+                    }
                     Ok(Err(e)) => {
                         debug!("❌ TCP health check failed for {}: {}", addr, e);
                         PrinterStatus::Offline
@@ -330,7 +1175,7 @@ impl HealthCache {
                     }
                 }
             }
-            Backend::Usb { device, baud_rate } => {
+            Backend::Usb { device, baud_rate, .. } => {
                 let baud_rate = baud_rate.unwrap_or(9600);
                 debug!("🔍 Direct USB health check for {}@{}", device, baud_rate);
                 
@@ -338,7 +1183,7 @@ impl HealthCache {
                 let check_result = timeout(
                     Duration::from_millis(1500),
                     async {
-                        tokio_serial::new(device, baud_rate).open()
+                        serial_builder(&printer.backend).and_then(|b| b.open().map_err(|e| e.to_string()))
                     }
                 ).await;
                 
@@ -360,15 +1205,9 @@ impl HealthCache {
         }
     }
 
-    #[allow(dead_code)]
+    /// Buang status cache printer (dipanggil saat kirim gagal, supaya status online lama tidak dipercaya)
     pub fn invalidate(&self, printer: &Printer) {
-        let cache_key = format!("{}:{}", printer.id, match &printer.backend {
-            Backend::Tcp9100 { host, port } => format!("tcp:{}:{}", host, port),
-            Backend::Usb { device, baud_rate } => {
-                let baud = baud_rate.unwrap_or(9600);
-                format!("usb:{}:{}", device, baud)
-            }
-        });
+        let cache_key = Self::cache_key(printer);
         self.cache.remove(&cache_key);
         debug!("🗑️ Invalidated health cache for {}", cache_key);
     }
@@ -386,6 +1225,29 @@ impl HealthCache {
 /// Global health cache instance with 30 second TTL
 pub static HEALTH_CACHE: Lazy<HealthCache> = Lazy::new(|| HealthCache::new(Duration::from_secs(30)));
 
+/// Health probe yang sedang berjalan per cache key health (lihat `HealthCache::probe_and_store`)
+static HEALTH_PROBES: Lazy<DashMap<String, Shared<BoxFuture<'static, PrinterStatus>>>> = Lazy::new(DashMap::new);
+
+/// Warm up pools at startup: open `pool_prewarm` (or `POOL_PREWARM`) connections per online printer
+pub async fn prewarm_pools(printers: &HashMap<String, Printer>) {
+    let global = crate::settings::settings().pool_prewarm;
+
+    for printer in printers.values() {
+        let count = printer.options.pool_prewarm.or(global).unwrap_or(0);
+        if count == 0 {
+            continue;
+        }
+        if HEALTH_CACHE.get_or_check(printer).await != PrinterStatus::Online {
+            info!("⏭️ Skipping pool prewarm for offline printer '{}'", printer.id);
+            continue;
+        }
+        match CONNECTION_MANAGER.prewarm(printer, count).await {
+            Ok(opened) => info!("🔥 Prewarmed {} connection(s) for printer '{}'", opened, printer.id),
+            Err(e) => warn!("⚠️ Pool prewarm for printer '{}' failed: {}", printer.id, e),
+        }
+    }
+}
+
 /// Background task to cleanup expired connections and cache entries
 pub async fn start_cleanup_task() {
     let mut interval = tokio::time::interval(Duration::from_secs(60)); // Cleanup every minute
@@ -394,10 +1256,136 @@ pub async fn start_cleanup_task() {
         interval.tick().await;
         debug!("🧹 Running background cleanup task");
         
-        // Cleanup connection pools
-        CONNECTION_MANAGER.cleanup_all_pools().await;
-        
-        // Cleanup health cache
-        HEALTH_CACHE.cleanup_expired().await;
+        // Panic di satu iterasi tidak boleh menghentikan task: pool dan cache harus tetap dibersihkan
+        let iteration = AssertUnwindSafe(async {
+            // Cleanup connection pools
+            CONNECTION_MANAGER.cleanup_all_pools().await;
+            
+            // Cleanup health cache
+            HEALTH_CACHE.cleanup_expired().await;
+        });
+        if let Err(panic) = iteration.catch_unwind().await {
+            warn!("⚠️ Background cleanup iteration panicked, retrying next tick: {}", panic_message(&panic));
+        }
+    }
+}
+
+/// Pesan dari payload panic (`&str` atau `String`)
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Read, Write};
+    use std::sync::Mutex as StdMutex;
+    use tokio_serial::{ClearBuffer, DataBits, FlowControl, Parity, StopBits};
+
+    /// Port serial palsu: menerima paling banyak `per_write` byte per `write`, lalu WouldBlock
+    /// sekali sebelum tulisan berikutnya (seperti TX buffer driver yang penuh). Setelah `stall_after`
+    /// byte, port tidak menerima apa pun lagi.
+    struct FakeSerial {
+        per_write: usize,
+        stall_after: usize,
+        blocked: bool,
+        received: Arc<StdMutex<Vec<u8>>>,
+    }
+
+    impl FakeSerial {
+        fn shared(per_write: usize, stall_after: usize) -> (SharedSerial, Arc<StdMutex<Vec<u8>>>) {
+            let received = Arc::new(StdMutex::new(Vec::new()));
+            let port = FakeSerial { per_write, stall_after, blocked: false, received: received.clone() };
+            (Arc::new(StdMutex::new(Box::new(port))), received)
+        }
+    }
+
+    impl Write for FakeSerial {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut received = self.received.lock().unwrap();
+            if self.blocked || received.len() >= self.stall_after {
+                self.blocked = false;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(self.per_write).min(self.stall_after - received.len());
+            received.extend_from_slice(&buf[..n]);
+            self.blocked = true;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for FakeSerial {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    impl SerialPort for FakeSerial {
+        fn name(&self) -> Option<String> { Some("/dev/fake".into()) }
+        fn baud_rate(&self) -> tokio_serial::Result<u32> { Ok(115_200) }
+        fn data_bits(&self) -> tokio_serial::Result<DataBits> { Ok(DataBits::Eight) }
+        fn flow_control(&self) -> tokio_serial::Result<FlowControl> { Ok(FlowControl::None) }
+        fn parity(&self) -> tokio_serial::Result<Parity> { Ok(Parity::None) }
+        fn stop_bits(&self) -> tokio_serial::Result<StopBits> { Ok(StopBits::One) }
+        fn timeout(&self) -> Duration { Duration::ZERO }
+        fn set_baud_rate(&mut self, _: u32) -> tokio_serial::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: DataBits) -> tokio_serial::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: FlowControl) -> tokio_serial::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: Parity) -> tokio_serial::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: StopBits) -> tokio_serial::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, _: Duration) -> tokio_serial::Result<()> { Ok(()) }
+        fn write_request_to_send(&mut self, _: bool) -> tokio_serial::Result<()> { Ok(()) }
+        fn write_data_terminal_ready(&mut self, _: bool) -> tokio_serial::Result<()> { Ok(()) }
+        fn read_clear_to_send(&mut self) -> tokio_serial::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> tokio_serial::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> tokio_serial::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> tokio_serial::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> tokio_serial::Result<u32> { Ok(0) }
+        fn bytes_to_write(&self) -> tokio_serial::Result<u32> { Ok(0) }
+        fn clear(&self, _: ClearBuffer) -> tokio_serial::Result<()> { Ok(()) }
+        fn try_clone(&self) -> tokio_serial::Result<Box<dyn SerialPort>> {
+            Err(tokio_serial::Error::new(tokio_serial::ErrorKind::Unknown, "FakeSerial tidak bisa di-clone"))
+        }
+        fn set_break(&self) -> tokio_serial::Result<()> { Ok(()) }
+        fn clear_break(&self) -> tokio_serial::Result<()> { Ok(()) }
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn write_serial_all_resumes_partial_writes_after_would_block() {
+        crate::settings::init_for_tests();
+        let (port, received) = FakeSerial::shared(700, usize::MAX);
+        let buf = payload(10_000);
+
+        write_serial_all(&port, &buf).await.expect("semua byte harus terkirim");
+
+        assert_eq!(*received.lock().unwrap(), buf);
+    }
+
+    #[tokio::test]
+    async fn write_serial_all_times_out_when_port_stops_accepting() {
+        crate::settings::init_for_tests();
+        let (port, received) = FakeSerial::shared(512, 2_000);
+        let buf = payload(4_000);
+
+        let started = Instant::now();
+        let err = write_serial_all(&port, &buf).await.expect_err("port macet harus timeout");
+
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(err.to_string().contains("2000 byte belum terkirim"), "{}", err);
+        assert_eq!(received.lock().unwrap().len(), 2_000);
+        // Budget = 5 detik + 2x waktu transfer di 115200 baud
+        assert!(started.elapsed() >= Duration::from_secs(5));
     }
 }
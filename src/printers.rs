@@ -1,7 +1,9 @@
 use crate::{
-    config::{Backend, Config, Printer},
+    config::{build_printers_map, is_env_managed, ConfigFormat, load_config, validate_aliases, Backend, Config, Printer, PrinterOptions},
     errors::ProxyError,
     handlers::AppState,
+    pool::CONNECTION_MANAGER,
+    settings::Settings,
 };
 use axum::{
     extract::{Path, Query, State},
@@ -11,7 +13,7 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     path::Path as FsPath,
     sync::Arc,
@@ -23,29 +25,55 @@ use tracing::{error, info, warn, instrument};
 pub struct PrinterCreateRequest {
     pub name: String,
     pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
     pub backend: Backend,
+    #[serde(flatten)]
+    pub options: PrinterOptions,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PrinterUpdateRequest {
     pub name: Option<String>,
+    pub aliases: Option<Vec<String>>,
+    pub tags: Option<BTreeMap<String, String>>,
     pub backend: Option<Backend>,
+    #[serde(flatten)]
+    pub options: PrinterOptions,
 }
 
 #[derive(Debug, Serialize)]
 pub struct PrinterResponse {
     pub name: String,
     pub id: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
     pub backend: Backend,
+    #[serde(flatten)]
+    pub options: PrinterOptions,
 }
 
 #[derive(Debug, Serialize)]
 pub struct PrintersListResponse {
     pub printers: Vec<PrinterResponse>,
     pub total: usize,
+    pub has_more: bool,
     pub timestamp: String,
 }
 
+/// Perbedaan config lama vs baru untuk `PUT /api/config`
+#[derive(Debug, Serialize)]
+pub struct ConfigDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
@@ -75,8 +103,8 @@ impl<T> ApiResponse<T> {
 }
 
 /// Validate admin token (reuse from admin module)
-fn validate_admin_token(provided_token: Option<&str>) -> bool {
-    let admin_token = std::env::var("ADMIN_TOKEN").unwrap_or_default();
+fn validate_admin_token(settings: &Settings, provided_token: Option<&str>) -> bool {
+    let admin_token = settings.admin_token.as_deref().unwrap_or_default();
     
     if admin_token.is_empty() {
         warn!("⚠️ ADMIN_TOKEN not set - printer management disabled");
@@ -96,22 +124,29 @@ fn validate_admin_token(provided_token: Option<&str>) -> bool {
 
 /// Get printers configuration file path
 fn get_config_path() -> String {
-    std::env::var("PRINTERS_CONFIG").unwrap_or_else(|_| "printers.yaml".to_string())
+    crate::settings::settings().printers_config.clone()
 }
 
 /// Load printers configuration from file
 fn load_printers_config() -> Result<Config, ProxyError> {
     let config_path = get_config_path();
     
+    if is_env_managed(&config_path) {
+        return load_config(&config_path)
+            .map_err(|e| ProxyError::BadPayload(format!("Invalid environment configuration: {}", e)));
+    }
+    
     if !FsPath::new(&config_path).exists() {
         return Err(ProxyError::BadPayload(format!("Configuration file not found: {}", config_path)));
     }
     
-    let content = fs::read_to_string(&config_path)
+    let content = fs::read(&config_path)
         .map_err(|e| ProxyError::Io(format!("Failed to read config file: {}", e)))?;
     
-    serde_yaml::from_str(&content)
-        .map_err(|e| ProxyError::BadPayload(format!("Invalid YAML configuration: {}", e)))
+    let format = ConfigFormat::from_path(&config_path);
+    format
+        .parse(&content)
+        .map_err(|e| ProxyError::BadPayload(format!("Invalid {} configuration: {}", format.name(), e)))
 }
 
 /// Save printers configuration to file atomically
@@ -119,12 +154,13 @@ fn save_printers_config(config: &Config) -> Result<(), ProxyError> {
     let config_path = get_config_path();
     let temp_path = format!("{}.tmp", config_path);
     
-    // Serialize to YAML
-    let yaml_content = serde_yaml::to_string(config)
+    // Serialize sesuai ekstensi file config (YAML/JSON/TOML)
+    let content = ConfigFormat::from_path(&config_path)
+        .serialize(config)
         .map_err(|e| ProxyError::BadPayload(format!("Failed to serialize config: {}", e)))?;
     
     // Write to temporary file first
-    fs::write(&temp_path, yaml_content)
+    fs::write(&temp_path, content)
         .map_err(|e| ProxyError::Io(format!("Failed to write temp config: {}", e)))?;
     
     // Atomic rename
@@ -139,20 +175,90 @@ fn save_printers_config(config: &Config) -> Result<(), ProxyError> {
     Ok(())
 }
 
+/// Response 409 untuk operasi tulis saat config dikelola lewat environment
+fn env_managed_conflict() -> axum::response::Response {
+    warn!("❌ Config is environment-managed, rejecting CRUD mutation");
+    (
+        StatusCode::CONFLICT,
+        Json(ApiResponse::<()>::error("Config is environment-managed, CRUD disabled"))
+    ).into_response()
+}
+
+/// `transform_command`/`pre_hook`/`post_hook` menjalankan program eksternal, jadi hanya boleh diatur lewat file config
+fn external_command_rejected(field: &str) -> axum::response::Response {
+    warn!("❌ Rejecting {} set via API", field);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::<()>::error(format!("{} can only be set in the config file", field)))
+    ).into_response()
+}
+
+/// Nama opsi program eksternal pertama yang di-set pada request
+fn external_command_set(options: &PrinterOptions) -> Option<&'static str> {
+    options.external_commands().into_iter().find(|(_, v)| v.is_some()).map(|(name, _)| name)
+}
+
+/// Validasi penuh seperti saat startup sebelum config ditulis, supaya config yang lolos CRUD
+/// tidak membuat start berikutnya gagal (TLS, bind_addr, fallback, opsi printer, dll.)
+/// Hasilnya response `400` jika config ditolak.
+fn rejected_before_save(config: &Config) -> Option<axum::response::Response> {
+    crate::config::validate_config(config).err().map(|e| {
+        let e = ProxyError::BadPayload(e.to_string());
+        warn!("❌ Config rejected before save: {}", e);
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<()>::error(e.to_string()))
+        ).into_response()
+    })
+}
+
 /// Reload printer configuration in memory
 async fn reload_printer_config(state: &Arc<RwLock<AppState>>) -> Result<(), ProxyError> {
     let config = load_printers_config()?;
-    let printers_map = config.printers.into_iter()
-        .map(|p| (p.id.clone(), p))
-        .collect::<HashMap<String, Printer>>();
+    validate_aliases(&config).map_err(|e| ProxyError::BadPayload(e.to_string()))?;
+    let (printers_map, aliases) = build_printers_map(config);
     
-    let mut app_state = state.write().await;
-    app_state.printers = Arc::new(printers_map);
+    crate::handlers::forget_unretained_jobs(&printers_map);
+
+    let mut appstate = state.write().await;
+    appstate.printers = Arc::new(printers_map);
+    appstate.aliases = Arc::new(aliases);
     
-    info!("🔄 Printer configuration reloaded with {} printers", app_state.printers.len());
+    info!("🔄 Printer configuration reloaded with {} printers", appstate.printers.len());
     Ok(())
 }
 
+/// Terapkan config yang baru saja disimpan ke memori. Jika reload gagal (mis. error baca sesaat),
+/// file dan state di memori berbeda: coba baca ulang sekali, lalu laporkan dengan jelas bahwa
+/// config sudah tersimpan tapi belum dipakai, bukan sekadar 500.
+async fn apply_saved_config(state: &Arc<RwLock<AppState>>) -> Result<(), axum::response::Response> {
+    let Err(first) = reload_printer_config(state).await else {
+        return Ok(());
+    };
+    error!("⚠️ Config saved to {} but reload failed: {} - retrying once", get_config_path(), first);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    match reload_printer_config(state).await {
+        Ok(()) => {
+            info!("✅ Config reload succeeded on retry");
+            Ok(())
+        }
+        Err(e) => {
+            error!(
+                "❌ INCONSISTENT: config saved to {} but not applied (running config unchanged): {}",
+                get_config_path(), e
+            );
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<()>::error(format!(
+                    "Configuration saved but not applied ({}). Fix the file if needed and reload manually via GET /api/printers/reload",
+                    e
+                )))
+            ).into_response())
+        }
+    }
+}
+
 /// List all printers
 #[instrument(skip(state))]
 pub async fn list_printers(
@@ -161,7 +267,7 @@ pub async fn list_printers(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("📋 List printers request received");
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for list printers");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -169,18 +275,45 @@ pub async fn list_printers(
         ).into_response());
     }
     
-    let app_state = state.read().await;
-    let printers: Vec<PrinterResponse> = app_state.printers
+    // Filter & pagination opsional: ?q= (substring id/name), ?tag.<key>=<value>, ?offset=, ?limit=
+    let q = query.get("q").map(|q| q.to_lowercase()).filter(|q| !q.is_empty());
+    let tag_filter: Vec<(&str, &str)> = query
+        .iter()
+        .filter_map(|(k, v)| Some((k.strip_prefix("tag.")?, v.as_str())))
+        .collect();
+    let offset = query.get("offset").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+    let limit = query.get("limit").and_then(|v| v.parse::<usize>().ok());
+    
+    let appstate = state.read().await;
+    let mut matched: Vec<&Printer> = appstate.printers
         .values()
+        .filter(|p| match &q {
+            Some(q) => p.id.to_lowercase().contains(q) || p.name.to_lowercase().contains(q),
+            None => true,
+        })
+        .filter(|p| p.has_tags(&tag_filter))
+        .collect();
+    // Urutan stabil supaya pagination konsisten antar request
+    matched.sort_by(|a, b| a.id.cmp(&b.id));
+    
+    let total = matched.len();
+    let printers: Vec<PrinterResponse> = matched
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
         .map(|p| PrinterResponse {
             name: p.name.clone(),
             id: p.id.clone(),
+            aliases: p.aliases.clone(),
+            tags: p.tags.clone(),
             backend: p.backend.clone(),
+            options: p.options.clone(),
         })
         .collect();
     
     let response = PrintersListResponse {
-        total: printers.len(),
+        has_more: offset + printers.len() < total,
+        total,
         printers,
         timestamp: chrono::Utc::now().to_rfc3339(),
     };
@@ -200,7 +333,7 @@ pub async fn get_printer(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔍 Get printer request for ID: {}", printer_id);
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for get printer");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -208,13 +341,16 @@ pub async fn get_printer(
         ).into_response());
     }
     
-    let app_state = state.read().await;
-    match app_state.printers.get(&printer_id) {
+    let appstate = state.read().await;
+    match appstate.printers.get(&printer_id) {
         Some(printer) => {
             let response = PrinterResponse {
                 name: printer.name.clone(),
                 id: printer.id.clone(),
+                aliases: printer.aliases.clone(),
+                tags: printer.tags.clone(),
                 backend: printer.backend.clone(),
+                options: printer.options.clone(),
             };
             Ok((
                 StatusCode::OK,
@@ -240,7 +376,7 @@ pub async fn create_printer(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("➕ Create printer request for ID: {}", request.id);
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for create printer");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -248,6 +384,10 @@ pub async fn create_printer(
         ).into_response());
     }
     
+    if is_env_managed(&get_config_path()) {
+        return Ok(env_managed_conflict());
+    }
+    
     // Validate request
     if request.id.is_empty() || request.name.is_empty() {
         return Ok((
@@ -255,11 +395,14 @@ pub async fn create_printer(
             Json(ApiResponse::<PrinterResponse>::error("ID and name are required"))
         ).into_response());
     }
+    if let Some(field) = external_command_set(&request.options) {
+        return Ok(external_command_rejected(field));
+    }
     
     // Check if printer already exists
     {
-        let app_state = state.read().await;
-        if app_state.printers.contains_key(&request.id) {
+        let appstate = state.read().await;
+        if appstate.printers.contains_key(&request.id) {
             warn!("❌ Printer already exists: {}", request.id);
             return Ok((
                 StatusCode::CONFLICT,
@@ -268,6 +411,19 @@ pub async fn create_printer(
         }
     }
     
+    // Opsional: pastikan device benar-benar menerima data (bukan sekadar menerima koneksi TCP)
+    // sebelum config disimpan, jadi tidak ada yang perlu di-rollback saat gagal
+    if query.get("validate").is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes")) {
+        info!("🧪 Test write ESC @ to new printer '{}'", request.id);
+        if let Err(e) = CONNECTION_MANAGER.test_write(&request.backend, &[0x1B, 0x40]).await {
+            warn!("❌ Test write to new printer '{}' failed: {}", request.id, e);
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ApiResponse::<PrinterResponse>::error(format!("Test write failed: {}", e)))
+            ).into_response());
+        }
+    }
+    
     // Load current config
     let mut config = load_printers_config()
         .map_err(|e| {
@@ -279,10 +435,23 @@ pub async fn create_printer(
     let new_printer = Printer {
         name: request.name.clone(),
         id: request.id.clone(),
+        aliases: request.aliases.clone(),
+        tags: request.tags.clone(),
         backend: request.backend.clone(),
+        options: request.options.clone(),
     };
     
     config.printers.push(new_printer.clone());
+    if let Err(e) = validate_aliases(&config) {
+        warn!("❌ Invalid aliases for new printer '{}': {}", request.id, e);
+        return Ok((
+            StatusCode::CONFLICT,
+            Json(ApiResponse::<PrinterResponse>::error(e.to_string()))
+        ).into_response());
+    }
+    if let Some(response) = rejected_before_save(&config) {
+        return Ok(response);
+    }
     
     // Save config
     save_printers_config(&config)
@@ -292,16 +461,17 @@ pub async fn create_printer(
         })?;
     
     // Reload in memory
-    reload_printer_config(&state).await
-        .map_err(|e| {
-            error!("❌ Failed to reload config: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    if let Err(response) = apply_saved_config(&state).await {
+        return Ok(response);
+    }
     
     let response = PrinterResponse {
         name: new_printer.name,
         id: new_printer.id,
+        aliases: new_printer.aliases,
+        tags: new_printer.tags,
         backend: new_printer.backend,
+        options: new_printer.options,
     };
     
     info!("✅ Printer created successfully: {}", request.id);
@@ -321,7 +491,7 @@ pub async fn update_printer(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("✏️ Update printer request for ID: {}", printer_id);
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for update printer");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -329,6 +499,14 @@ pub async fn update_printer(
         ).into_response());
     }
     
+    if let Some(field) = external_command_set(&request.options) {
+        return Ok(external_command_rejected(field));
+    }
+    
+    if is_env_managed(&get_config_path()) {
+        return Ok(env_managed_conflict());
+    }
+    
     // Load current config
     let mut config = load_printers_config()
         .map_err(|e| {
@@ -345,11 +523,28 @@ pub async fn update_printer(
             if let Some(name) = request.name {
                 printer.name = name;
             }
+            if let Some(aliases) = request.aliases {
+                printer.aliases = aliases;
+            }
+            if let Some(tags) = request.tags {
+                printer.tags = tags;
+            }
             if let Some(backend) = request.backend {
                 printer.backend = backend;
             }
+            printer.options.merge(request.options);
             
             let updated_printer = printer.clone();
+            if let Err(e) = validate_aliases(&config) {
+                warn!("❌ Invalid aliases for printer '{}': {}", printer_id, e);
+                return Ok((
+                    StatusCode::CONFLICT,
+                    Json(ApiResponse::<PrinterResponse>::error(e.to_string()))
+                ).into_response());
+            }
+            if let Some(response) = rejected_before_save(&config) {
+                return Ok(response);
+            }
             
             // Save config
             save_printers_config(&config)
@@ -359,16 +554,17 @@ pub async fn update_printer(
                 })?;
             
             // Reload in memory
-            reload_printer_config(&state).await
-                .map_err(|e| {
-                    error!("❌ Failed to reload config: {}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?;
+            if let Err(response) = apply_saved_config(&state).await {
+                return Ok(response);
+            }
             
             let response = PrinterResponse {
                 name: updated_printer.name,
                 id: updated_printer.id,
+                aliases: updated_printer.aliases,
+                tags: updated_printer.tags,
                 backend: updated_printer.backend,
+                options: updated_printer.options,
             };
             
             info!("✅ Printer updated successfully: {}", printer_id);
@@ -396,7 +592,7 @@ pub async fn delete_printer(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🗑️ Delete printer request for ID: {}", printer_id);
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for delete printer");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -404,6 +600,10 @@ pub async fn delete_printer(
         ).into_response());
     }
     
+    if is_env_managed(&get_config_path()) {
+        return Ok(env_managed_conflict());
+    }
+    
     // Load current config
     let mut config = load_printers_config()
         .map_err(|e| {
@@ -422,6 +622,9 @@ pub async fn delete_printer(
             Json(ApiResponse::<()>::error(format!("Printer '{}' not found", printer_id)))
         ).into_response());
     }
+    if let Some(response) = rejected_before_save(&config) {
+        return Ok(response);
+    }
     
     // Save config
     save_printers_config(&config)
@@ -431,11 +634,9 @@ pub async fn delete_printer(
         })?;
     
     // Reload in memory
-    reload_printer_config(&state).await
-        .map_err(|e| {
-            error!("❌ Failed to reload config: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    if let Err(response) = apply_saved_config(&state).await {
+        return Ok(response);
+    }
     
     info!("✅ Printer deleted successfully: {}", printer_id);
     Ok((
@@ -444,6 +645,62 @@ pub async fn delete_printer(
     ).into_response())
 }
 
+/// Kirim ulang job terakhir yang berhasil ke printer (butuh `retain_last_job: true`)
+#[instrument(skip(state))]
+pub async fn reprint_printer(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Path(printer_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("🔁 Reprint request for printer: {}", printer_id);
+    
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
+        warn!("❌ Invalid or missing admin token for reprint");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(ApiResponse::<()>::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+    
+    let printer = state.read().await.printers.get(&printer_id).cloned();
+    let Some(printer) = printer else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::<()>::error(format!("Printer '{}' not found", printer_id)))
+        ).into_response());
+    };
+    if !printer.options.retain_last_job.unwrap_or(false) {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<()>::error(format!("Printer '{}' does not have retain_last_job enabled", printer_id)))
+        ).into_response());
+    }
+    
+    let Some(job) = crate::handlers::last_job(&printer_id) else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::<()>::error(format!("No retained job for printer '{}' since startup", printer_id)))
+        ).into_response());
+    };
+    
+    match crate::queue::send_queued(&printer, &job, crate::queue::Priority::Normal, crate::graphics::GraphicRefs::AsIs).await {
+        Ok(()) => {
+            info!("✅ Reprinted last job ({} bytes) on '{}'", job.len(), printer_id);
+            Ok((
+                StatusCode::OK,
+                Json(ApiResponse::success(format!("Last job ({} bytes) reprinted on '{}'", job.len(), printer_id), ()))
+            ).into_response())
+        }
+        Err(e) => {
+            warn!("❌ Reprint on '{}' failed: {}", printer_id, e);
+            Ok((
+                StatusCode::BAD_GATEWAY,
+                Json(ApiResponse::<()>::error(format!("Reprint failed: {}", e)))
+            ).into_response())
+        }
+    }
+}
+
 /// Reload printer configuration from file
 #[instrument(skip(state))]
 pub async fn reload_printers(
@@ -452,7 +709,7 @@ pub async fn reload_printers(
 ) -> Result<impl IntoResponse, StatusCode> {
     info!("🔄 Reload printers configuration request");
     
-    if !validate_admin_token(query.get("token").map(|s| s.as_str())) {
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
         warn!("❌ Invalid or missing admin token for reload printers");
         return Ok((
             StatusCode::UNAUTHORIZED,
@@ -467,15 +724,207 @@ pub async fn reload_printers(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
     
-    let app_state = state.read().await;
-    let printer_count = app_state.printers.len();
+    // Opsional: koneksi pool bisa masih menunjuk IP lama (mis. printer dapat DHCP lease baru)
+    let drained = if query.get("drain_pools").is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes")) {
+        Some(CONNECTION_MANAGER.drain_all_pools().await)
+    } else {
+        None
+    };
+    
+    let appstate = state.read().await;
+    let printer_count = appstate.printers.len();
     
     info!("✅ Printers configuration reloaded with {} printers", printer_count);
+    let message = match drained {
+        Some(n) => format!("Configuration reloaded successfully with {} printers, {} pooled connection(s) drained", printer_count, n),
+        None => format!("Configuration reloaded successfully with {} printers", printer_count),
+    };
+    Ok((
+        StatusCode::OK,
+        Json(ApiResponse::success(message, ()))
+    ).into_response())
+}
+
+/// Bandingkan printer per id (serialisasi JSON supaya semua field ikut dibandingkan)
+fn diff_configs(old: &Config, new: &Config) -> ConfigDiff {
+    let old_map: HashMap<&str, serde_json::Value> = old.printers.iter()
+        .map(|p| (p.id.as_str(), serde_json::to_value(p).unwrap_or_default()))
+        .collect();
+    let new_ids: std::collections::HashSet<&str> = new.printers.iter().map(|p| p.id.as_str()).collect();
+
+    let mut diff = ConfigDiff { added: Vec::new(), removed: Vec::new(), changed: Vec::new(), unchanged: 0 };
+    for p in &new.printers {
+        match old_map.get(p.id.as_str()) {
+            None => diff.added.push(p.id.clone()),
+            Some(old_value) if *old_value != serde_json::to_value(p).unwrap_or_default() => diff.changed.push(p.id.clone()),
+            Some(_) => diff.unchanged += 1,
+        }
+    }
+    diff.removed = old.printers.iter()
+        .filter(|p| !new_ids.contains(p.id.as_str()))
+        .map(|p| p.id.clone())
+        .collect();
+    diff
+}
+
+/// Validasi struktur config upload: id/name wajib dan id harus unik
+fn validate_uploaded_config(config: &Config) -> Result<(), String> {
+    // Startup menolak config tanpa printer, jadi jangan sampai tersimpan lewat API
+    if config.printers.is_empty() {
+        return Err("Config must contain at least one printer".into());
+    }
+    let mut seen = std::collections::HashSet::new();
+    for (i, p) in config.printers.iter().enumerate() {
+        if p.id.is_empty() || p.name.is_empty() {
+            return Err(format!("printers[{}]: id and name are required", i));
+        }
+        if !seen.insert(p.id.as_str()) {
+            return Err(format!("Duplicate printer id '{}'", p.id));
+        }
+    }
+    crate::config::validate_config(config).map_err(|e| e.to_string())
+}
+
+/// Ganti seluruh config printer sekaligus (YAML atau JSON), untuk deploy deklaratif
+#[instrument(skip(state, body))]
+pub async fn replace_config(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(query): Query<HashMap<String, String>>,
+    body: axum::body::Bytes,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("📦 Replace config request ({} bytes)", body.len());
+
+    if !validate_admin_token(&state.read().await.settings, query.get("token").map(|s| s.as_str())) {
+        warn!("❌ Invalid or missing admin token for replace config");
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(ApiResponse::<ConfigDiff>::error("Invalid or missing admin token"))
+        ).into_response());
+    }
+
+    if is_env_managed(&get_config_path()) {
+        return Ok(env_managed_conflict());
+    }
+
+    // JSON adalah subset YAML, jadi satu parser cukup untuk keduanya
+    let new_config: Config = match serde_yaml::from_slice(&body) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("❌ Uploaded config is not valid YAML/JSON: {}", e);
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::<ConfigDiff>::error(format!("Invalid config: {}", e)))
+            ).into_response());
+        }
+    };
+
+    if let Err(e) = validate_uploaded_config(&new_config) {
+        warn!("❌ Uploaded config rejected: {}", e);
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<ConfigDiff>::error(e))
+        ).into_response());
+    }
+
+    let old_config = load_printers_config()
+        .map_err(|e| {
+            error!("❌ Failed to load config: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Program eksternal yang sudah ada di file boleh dipertahankan, tapi tidak boleh ditambah/diubah lewat API
+    let old_options: HashMap<&str, &PrinterOptions> = old_config.printers.iter()
+        .map(|p| (p.id.as_str(), &p.options))
+        .collect();
+    for p in &new_config.printers {
+        let old = old_options.get(p.id.as_str()).map(|o| o.external_commands());
+        for (i, (field, command)) in p.options.external_commands().into_iter().enumerate() {
+            if command.is_some() && old.as_ref().map(|o| o[i].1) != Some(command) {
+                return Ok(external_command_rejected(field));
+            }
+        }
+    }
+
+    // Opsional: semua backend harus menerima data sebelum config diganti
+    if query.get("validate").is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "yes")) {
+        for p in &new_config.printers {
+            info!("🧪 Test write ESC @ to printer '{}'", p.id);
+            if let Err(e) = CONNECTION_MANAGER.test_write(&p.backend, &[0x1B, 0x40]).await {
+                warn!("❌ Test write to printer '{}' failed: {}", p.id, e);
+                return Ok((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(ApiResponse::<ConfigDiff>::error(format!("Test write to printer '{}' failed: {}", p.id, e)))
+                ).into_response());
+            }
+        }
+    }
+
+    let diff = diff_configs(&old_config, &new_config);
+
+    save_printers_config(&new_config)
+        .map_err(|e| {
+            error!("❌ Failed to save config: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if let Err(response) = apply_saved_config(&state).await {
+        return Ok(response);
+    }
+
+    info!(
+        "✅ Config replaced: {} added, {} removed, {} changed, {} unchanged",
+        diff.added.len(), diff.removed.len(), diff.changed.len(), diff.unchanged
+    );
     Ok((
         StatusCode::OK,
-        Json(ApiResponse::success(
-            format!("Configuration reloaded successfully with {} printers", printer_count),
-            ()
-        ))
+        Json(ApiResponse::success(format!("Configuration replaced with {} printers", new_config.printers.len()), diff))
     ).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD_CONFIG: &str = r#"
+printers:
+  - name: "Kasir"
+    id: "kasir"
+    backend: { type: "tcp9100", host: "127.0.0.1", port: 9100 }
+"#;
+
+    /// Alias bentrok dengan id printer lain: lolos parse, gagal `validate_aliases`
+    const INVALID_CONFIG: &str = r#"
+printers:
+  - name: "Dapur"
+    id: "dapur"
+    backend: { type: "tcp9100", host: "127.0.0.1", port: 9101 }
+  - name: "Bar"
+    id: "bar"
+    aliases: ["dapur"]
+    backend: { type: "tcp9100", host: "127.0.0.1", port: 9102 }
+"#;
+
+    #[tokio::test]
+    async fn apply_saved_config_keeps_running_config_when_reload_fails() {
+        let settings = crate::settings::init_for_tests();
+        let (printers, aliases) = build_printers_map(ConfigFormat::Yaml.parse(OLD_CONFIG.as_bytes()).unwrap());
+        let state = Arc::new(RwLock::new(AppState {
+            printers: Arc::new(printers),
+            aliases: Arc::new(aliases),
+            settings,
+        }));
+
+        fs::write(get_config_path(), INVALID_CONFIG).unwrap();
+        let response = apply_saved_config(&state).await.expect_err("reload harus gagal");
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("saved but not applied"), "{}", body);
+        assert!(body.contains("Alias 'dapur'"), "{}", body);
+
+        let state = state.read().await;
+        assert_eq!(state.printers.keys().collect::<Vec<_>>(), vec!["kasir"]);
+        assert!(state.aliases.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{backend::send_to_backend, config::Printer, errors::ProxyError, graphics::GraphicRefs, pool::ConnectionManager};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, Notify},
+    time::timeout,
+};
+use tracing::{debug, info, warn};
+
+/// Prioritas job di antrean printer; urutan deklarasi = urutan `Ord` (low < normal < high)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Result<Self, ProxyError> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(ProxyError::BadPayload(format!("priority '{}' tidak dikenal (high, normal, low)", other))),
+        }
+    }
+}
+
+/// Job yang menunggu giliran ditulis ke printer
+struct QueuedJob {
+    priority: Priority,
+    /// Nomor urut masuk antrean, supaya job dengan prioritas sama tetap FIFO
+    seq: u64,
+    /// Config printer saat job dibuat, supaya reload config berlaku untuk job berikutnya
+    printer: Printer,
+    payload: Vec<u8>,
+    refs: GraphicRefs,
+    reply: oneshot::Sender<Result<(), ProxyError>>,
+}
+
+// BinaryHeap = max-heap: prioritas tertinggi dulu, lalu seq terkecil (paling lama menunggu)
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for QueuedJob {}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: BinaryHeap<QueuedJob>,
+    /// Worker sudah berhenti (idle); job baru harus membuat antrean baru
+    closed: bool,
+}
+
+/// Antrean satu printer fisik; satu worker per antrean menulis job satu per satu
+#[derive(Default)]
+struct PrinterQueue {
+    state: Mutex<QueueState>,
+    ready: Notify,
+}
+
+impl PrinterQueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Antrean per pool key (`tcp:host:port`, `usb:device:baud`), bukan per id printer: beberapa id yang
+/// menunjuk printer fisik yang sama berbagi satu antrean, jadi job-nya tidak pernah ditulis bersamaan
+static QUEUES: Lazy<DashMap<String, Arc<PrinterQueue>>> = Lazy::new(DashMap::new);
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Kapasitas antrean per printer (`PRINTER_QUEUE_SIZE`, default 32)
+static QUEUE_SIZE: Lazy<usize> = Lazy::new(|| crate::settings::settings().printer_queue_size);
+
+/// Worker berhenti setelah antreannya kosong selama ini (printer dihapus/tidak dipakai)
+const WORKER_IDLE: Duration = Duration::from_secs(300);
+
+/// Kirim payload lewat antrean printer dan tunggu hasil tulisnya.
+/// Job ke printer yang sama ditulis berurutan, prioritas tertinggi dulu (FIFO dalam prioritas yang sama);
+/// printer lambat hanya menahan antreannya sendiri.
+/// Antrean penuh langsung ditolak (`QueueFull`, 503) alih-alih menumpuk request.
+pub async fn send_queued(printer: &Printer, payload: &[u8], priority: Priority, refs: GraphicRefs) -> Result<(), ProxyError> {
+    let (reply, result) = oneshot::channel();
+    let mut job = Some(QueuedJob {
+        priority,
+        seq: NEXT_SEQ.fetch_add(1, AtomicOrdering::Relaxed),
+        printer: printer.clone(),
+        payload: payload.to_vec(),
+        refs,
+        reply,
+    });
+
+    let key = ConnectionManager::pool_key(&printer.backend);
+    // Worker yang baru saja berhenti karena idle menutup antreannya: buat ulang sekali
+    for _ in 0..2 {
+        let queue = QUEUES
+            .entry(key.clone())
+            .or_insert_with(|| spawn_worker(&key))
+            .clone();
+        let waiting = {
+            let mut state = queue.lock();
+            if state.closed {
+                None
+            } else if state.jobs.len() >= *QUEUE_SIZE {
+                warn!("📪 Queue {} for '{}' is full ({} jobs), rejecting job", key, printer.id, *QUEUE_SIZE);
+                return Err(ProxyError::QueueFull(printer.id.clone()));
+            } else {
+                state.jobs.extend(job.take());
+                Some(state.jobs.len())
+            }
+        };
+        match waiting {
+            Some(waiting) => {
+                queue.ready.notify_one();
+                debug!("📬 Job queued for '{}' on {} (priority {:?}, {} waiting)", printer.id, key, priority, waiting);
+                // Reply hilang hanya jika worker berhenti tak terduga (panic)
+                return result.await.unwrap_or(Err(ProxyError::Internal));
+            }
+            None => {
+                QUEUES.remove_if(&key, |_, q| Arc::ptr_eq(q, &queue));
+            }
+        }
+    }
+    Err(ProxyError::Internal)
+}
+
+fn spawn_worker(key: &str) -> Arc<PrinterQueue> {
+    let queue = Arc::new(PrinterQueue::default());
+    let worker_queue = queue.clone();
+    let key = key.to_string();
+    tokio::spawn(async move {
+        info!("📬 Queue worker for {} started", key);
+        loop {
+            let next = worker_queue.lock().jobs.pop();
+            match next {
+                Some(job) => run_job(job).await,
+                // Notify menyimpan permit, jadi job yang masuk sebelum await ini tidak terlewat
+                None => {
+                    if timeout(WORKER_IDLE, worker_queue.ready.notified()).await.is_err() {
+                        // Tolak job baru; cek ulang karena job bisa masuk tepat saat timeout
+                        let mut state = worker_queue.lock();
+                        if state.jobs.is_empty() {
+                            state.closed = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        info!("📭 Queue worker for {} stopped (idle)", key);
+    });
+    queue
+}
+
+async fn run_job(job: QueuedJob) {
+    // Client sudah menyerah (timeout/putus) sebelum gilirannya: jangan cetak, supaya retry client tidak dobel
+    if job.reply.is_closed() {
+        warn!("🗑️ Skipping queued job for '{}': requester is gone", job.printer.id);
+        return;
+    }
+    let result = send_to_backend(&job.printer, &job.payload, job.refs).await;
+    let _ = job.reply.send(result);
+}
+
+/// Jumlah job yang menunggu per pool key (`/admin/stats`)
+pub fn depths() -> Vec<(String, usize)> {
+    let mut depths: Vec<(String, usize)> = QUEUES
+        .iter()
+        .map(|e| (e.key().clone(), e.value().lock().jobs.len()))
+        .collect();
+    depths.sort();
+    depths
+}
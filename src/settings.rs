@@ -0,0 +1,298 @@
+//! Semua setting dari environment variable, di-parse dan divalidasi sekali saat startup (`init`).
+//! Handler membaca `AppState::settings`, modul tanpa akses state membaca `settings()`; tidak ada
+//! `std::env::var` per request. `GET /admin/config/runtime` menampilkan nilai efektifnya (secret disamarkan).
+
+use crate::config::ResponseFormat;
+use once_cell::sync::OnceCell;
+use serde::{Serialize, Serializer};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Settings {
+    /* ---------- Server ---------- */
+    /// `LISTEN_ADDR` (default `0.0.0.0:8080`)
+    pub listen_addr: SocketAddr,
+    /// `PRINTERS_CONFIG` (default `printers.yaml`)
+    pub printers_config: String,
+    /// Env config inline yang di-set (`PRINTERS_CONFIG_JSON`/`PRINTERS_CONFIG_YAML`); isinya tidak ditampilkan
+    pub printers_config_inline: Option<&'static str>,
+    /// `ADMIN_TOKEN`, disamarkan saat diserialisasi
+    #[serde(serialize_with = "redact")]
+    pub admin_token: Option<String>,
+    /// `ADMIN_AUTH_MAX_FAILURES` (default 5, 0 = nonaktif)
+    pub admin_auth_max_failures: u32,
+    /// `ADMIN_AUTH_LOCKOUT_SECS` (default 300)
+    pub admin_auth_lockout_secs: u64,
+    /// `CORS_ALLOW_ORIGIN` (dipisah koma; kosong = tanpa CORS, hanya same-origin)
+    pub cors_allow_origin: Option<String>,
+    /// `RESPONSE_FORMAT` (default `epos-xml`)
+    pub response_format: ResponseFormat,
+    /// `STARTUP_HEALTH_CHECK` (default false)
+    pub startup_health_check: bool,
+    /// `DISABLE_PREFLIGHT` (default false): print endpoint tidak cek printer online sebelum kirim
+    pub disable_preflight: bool,
+    /// `HEALTH_BULK_MAX_IDS` (default 100): panjang maksimum `printer_ids` di `POST /health/printers`
+    pub health_bulk_max_ids: usize,
+    /// `TEST_FAULT_INJECTION` (default false): query `_delay_ms`/`_fail`/`_fail_rate` di print endpoint
+    pub test_fault_injection: bool,
+    /// `NOTIFY_SOCKET` ada (proses dijalankan systemd)
+    pub systemd_notify: bool,
+
+    /* ---------- Timeout ---------- */
+    /// `HEALTH_TIMEOUT_SECS` (default 10)
+    pub health_timeout_secs: u64,
+    /// `PRINT_TIMEOUT_SECS` (default 60)
+    pub print_timeout_secs: u64,
+    /// `SHUTDOWN_GRACE_SECS` (default 20)
+    pub shutdown_grace_secs: u64,
+    /// `TRANSFORM_TIMEOUT_SECS` (default 10)
+    pub transform_timeout_secs: u64,
+    /// `HOOK_TIMEOUT_SECS` (default 10)
+    pub hook_timeout_secs: u64,
+    /// `HEALTH_POLL_SECS` (default 30)
+    pub health_poll_secs: u64,
+    /// `PRINTER_OFFLINE_RETRY_AFTER` (default TTL health cache)
+    pub printer_offline_retry_after: Option<u64>,
+    /// `ASB_READ_MS` (default 500)
+    pub asb_read_ms: u64,
+    /// `ASYNC_OK_RETRY_SECS` (default 5, minimal 1)
+    pub async_ok_retry_secs: u64,
+    /// `ASYNC_OK_MAX_WAIT_SECS` (default 600)
+    pub async_ok_max_wait_secs: u64,
+    /// `ASYNC_OK_MAX_PENDING` (default 64 per printer, minimal 1)
+    pub async_ok_max_pending: usize,
+    /// `COALESCE_MAX_BATCH_JOBS` (default 32 job per batch `coalesce_ms`, minimal 1)
+    pub max_batch_jobs: usize,
+    /// `COALESCE_MAX_BATCH_BYTES` (default 1 MiB per batch `coalesce_ms`)
+    pub max_batch_bytes: usize,
+
+    /* ---------- Koneksi & antrean ---------- */
+    /// `MAX_TOTAL_POOLED_CONNECTIONS` (default tanpa batas)
+    pub max_total_pooled_connections: Option<usize>,
+    /// `POOL_PREWARM` (default 0, bisa di-override per printer)
+    pub pool_prewarm: Option<usize>,
+    /// `PRINTER_QUEUE_SIZE` (default 32, minimal 1)
+    pub printer_queue_size: usize,
+    /// `SERIAL_PROGRESS_KB` (default 16, minimal 1)
+    pub serial_progress_kb: usize,
+    /// `SERIAL_PROGRESS_MS` (default 2000)
+    pub serial_progress_ms: u64,
+    /// `DNS_CACHE` (default true)
+    pub dns_cache: bool,
+    /// `DNS_CACHE_TTL_SECS` (default 60)
+    pub dns_cache_ttl_secs: u64,
+    /// `TLS_MIN_VERSION` (`1.2` default atau `1.3`)
+    pub tls_min_version: String,
+    /// `TLS_CIPHER_SUITES` (default rustls)
+    pub tls_cipher_suites: Option<String>,
+
+    /* ---------- Job ---------- */
+    /// `JOB_HISTORY_SIZE` (default 200, 0 = nonaktif)
+    pub job_history_size: usize,
+    /// `MAX_RETAINED_JOB_BYTES` (default 1 MiB)
+    pub max_retained_job_bytes: usize,
+    /// `DEAD_LETTER_DIR` (default `dead_letter`)
+    pub dead_letter_dir: String,
+    /// `DEAD_LETTER_COMPRESS` (default false)
+    pub dead_letter_compress: bool,
+    /// `SHADOW_PRINTER_ID`
+    pub shadow_printer_id: Option<String>,
+    /// `SHADOW_FILE`
+    pub shadow_file: Option<String>,
+    /// `TRACE_MAX_BYTES` (default 64 KB)
+    pub trace_max_bytes: usize,
+    /// `GRAPHICS_MAX` (default 64)
+    pub graphics_max: usize,
+
+    /* ---------- Gambar ---------- */
+    /// `MAX_IMAGE_WIDTH` (default 2048)
+    pub max_image_width: u32,
+    /// `MAX_IMAGE_HEIGHT` (default 16384)
+    pub max_image_height: u32,
+    /// `MAX_IMAGE_BYTES` (default 4 MiB)
+    pub max_image_bytes: usize,
+    /// `MAX_IMAGE_BASE64_BYTES` (default 8 MiB)
+    pub max_image_base64_bytes: usize,
+    /// `MAX_DOCUMENT_BASE64_BYTES` (default 16 MiB)
+    pub max_document_base64_bytes: usize,
+    /// `MAX_IMAGES_PER_DOC` (default 32, minimal 1)
+    pub max_images_per_doc: usize,
+    /// `IMAGE_URL_ALLOWED_HOSTS` (dipisah koma, kosong = `image_url` nonaktif)
+    pub image_url_allowed_hosts: Vec<String>,
+    /// `IMAGE_URL_TIMEOUT_SECS` (default 5)
+    pub image_url_timeout_secs: u64,
+    /// `IMAGE_URL_MAX_BYTES` (default 2 MiB)
+    pub image_url_max_bytes: usize,
+    /// `IMAGE_URL_CACHE_SECS` (default 300)
+    pub image_url_cache_secs: u64,
+
+    /* ---------- Logging ---------- */
+    /// `LOG_DIR` (default `logs`)
+    pub log_dir: String,
+    /// `LOG_ROTATION`: "daily" (default), "hourly", "never"
+    pub log_rotation: &'static str,
+    /// `LOG_MAX_FILES` (jumlah file rotasi yang disimpan, default tanpa batas)
+    pub log_max_files: Option<usize>,
+    /// `RUST_LOG`
+    pub rust_log: Option<String>,
+}
+
+/// Env yang di-set dan tidak kosong
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Env angka; nilai yang tidak valid diperlakukan seperti tidak di-set
+fn num<T: FromStr>(name: &str) -> Option<T> {
+    var(name).and_then(|v| v.parse().ok())
+}
+
+/// Env boolean: `1/true/yes/on` atau `0/false/no/off`, selain itu default
+fn flag(name: &str, default: bool) -> bool {
+    match var(name).map(|v| v.to_ascii_lowercase()).as_deref() {
+        Some("1" | "true" | "yes" | "on") => true,
+        Some("0" | "false" | "no" | "off") => false,
+        _ => default,
+    }
+}
+
+fn redact<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|_| "***").serialize(serializer)
+}
+
+impl Settings {
+    /// Parse semua env; nilai yang tidak bisa dipakai sama sekali (alamat listen, rotasi log,
+    /// versi TLS) menggagalkan startup, angka lain yang tidak valid kembali ke default
+    pub fn from_env() -> anyhow::Result<Self> {
+        let listen_addr = var("LISTEN_ADDR").unwrap_or_else(|| "0.0.0.0:8080".to_string());
+        let listen_addr = listen_addr
+            .parse()
+            .map_err(|e| anyhow::anyhow!("LISTEN_ADDR '{}' invalid: {}", listen_addr, e))?;
+        let log_rotation = match var("LOG_ROTATION").map(|v| v.to_ascii_lowercase()).as_deref() {
+            None | Some("daily") => "daily",
+            Some("hourly") => "hourly",
+            Some("never") => "never",
+            Some(other) => anyhow::bail!("LOG_ROTATION '{}' tidak dikenal (daily, hourly, never)", other),
+        };
+        let log_max_files = match var("LOG_MAX_FILES") {
+            Some(v) => Some(
+                v.parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| anyhow::anyhow!("LOG_MAX_FILES harus angka > 0, got '{}'", v))?,
+            ),
+            None => None,
+        };
+        // Divalidasi bersama config printer (`validate_config`), karena hanya berarti jika ada printer TLS
+        let tls_min_version = var("TLS_MIN_VERSION").unwrap_or_else(|| "1.2".to_string());
+
+        Ok(Self {
+            listen_addr,
+            printers_config: var("PRINTERS_CONFIG").unwrap_or_else(|| "printers.yaml".to_string()),
+            printers_config_inline: ["PRINTERS_CONFIG_JSON", "PRINTERS_CONFIG_YAML"]
+                .into_iter()
+                .find(|key| var(key).is_some()),
+            admin_token: var("ADMIN_TOKEN"),
+            admin_auth_max_failures: num("ADMIN_AUTH_MAX_FAILURES").unwrap_or(5),
+            admin_auth_lockout_secs: num("ADMIN_AUTH_LOCKOUT_SECS").unwrap_or(300),
+            cors_allow_origin: var("CORS_ALLOW_ORIGIN"),
+            response_format: match var("RESPONSE_FORMAT").as_deref() {
+                Some("json") => ResponseFormat::Json,
+                Some("empty-200") => ResponseFormat::Empty200,
+                _ => ResponseFormat::EposXml,
+            },
+            startup_health_check: flag("STARTUP_HEALTH_CHECK", false),
+            disable_preflight: flag("DISABLE_PREFLIGHT", false),
+            health_bulk_max_ids: num("HEALTH_BULK_MAX_IDS").unwrap_or(100),
+            test_fault_injection: flag("TEST_FAULT_INJECTION", false),
+            systemd_notify: std::env::var_os("NOTIFY_SOCKET").is_some(),
+
+            health_timeout_secs: num("HEALTH_TIMEOUT_SECS").unwrap_or(10),
+            print_timeout_secs: num("PRINT_TIMEOUT_SECS").unwrap_or(60),
+            shutdown_grace_secs: num("SHUTDOWN_GRACE_SECS").unwrap_or(20),
+            transform_timeout_secs: num("TRANSFORM_TIMEOUT_SECS").unwrap_or(10),
+            hook_timeout_secs: num("HOOK_TIMEOUT_SECS").unwrap_or(10),
+            health_poll_secs: num("HEALTH_POLL_SECS").unwrap_or(30),
+            printer_offline_retry_after: num("PRINTER_OFFLINE_RETRY_AFTER"),
+            asb_read_ms: num("ASB_READ_MS").unwrap_or(500),
+            async_ok_retry_secs: num("ASYNC_OK_RETRY_SECS").unwrap_or(5).max(1),
+            async_ok_max_wait_secs: num("ASYNC_OK_MAX_WAIT_SECS").unwrap_or(600),
+            async_ok_max_pending: num("ASYNC_OK_MAX_PENDING").unwrap_or(64usize).max(1),
+            max_batch_jobs: num("COALESCE_MAX_BATCH_JOBS").unwrap_or(32usize).max(1),
+            max_batch_bytes: num("COALESCE_MAX_BATCH_BYTES").unwrap_or(1024 * 1024),
+
+            max_total_pooled_connections: num("MAX_TOTAL_POOLED_CONNECTIONS"),
+            pool_prewarm: num("POOL_PREWARM"),
+            printer_queue_size: num("PRINTER_QUEUE_SIZE").unwrap_or(32usize).max(1),
+            serial_progress_kb: num("SERIAL_PROGRESS_KB").unwrap_or(16usize).max(1),
+            serial_progress_ms: num("SERIAL_PROGRESS_MS").unwrap_or(2000),
+            dns_cache: flag("DNS_CACHE", true),
+            dns_cache_ttl_secs: num("DNS_CACHE_TTL_SECS").unwrap_or(60),
+            tls_min_version,
+            tls_cipher_suites: var("TLS_CIPHER_SUITES"),
+
+            job_history_size: num("JOB_HISTORY_SIZE").unwrap_or(200),
+            max_retained_job_bytes: num("MAX_RETAINED_JOB_BYTES").unwrap_or(1024 * 1024),
+            dead_letter_dir: var("DEAD_LETTER_DIR").unwrap_or_else(|| "dead_letter".to_string()),
+            dead_letter_compress: flag("DEAD_LETTER_COMPRESS", false),
+            shadow_printer_id: var("SHADOW_PRINTER_ID"),
+            shadow_file: var("SHADOW_FILE"),
+            trace_max_bytes: num("TRACE_MAX_BYTES").unwrap_or(64 * 1024),
+            graphics_max: num("GRAPHICS_MAX").unwrap_or(64),
+
+            max_image_width: num("MAX_IMAGE_WIDTH").unwrap_or(2048),
+            max_image_height: num("MAX_IMAGE_HEIGHT").unwrap_or(16384),
+            max_image_bytes: num("MAX_IMAGE_BYTES").unwrap_or(4 * 1024 * 1024),
+            max_image_base64_bytes: num("MAX_IMAGE_BASE64_BYTES").unwrap_or(8 * 1024 * 1024),
+            max_document_base64_bytes: num("MAX_DOCUMENT_BASE64_BYTES").unwrap_or(16 * 1024 * 1024),
+            max_images_per_doc: num("MAX_IMAGES_PER_DOC").unwrap_or(32usize).max(1),
+            image_url_allowed_hosts: var("IMAGE_URL_ALLOWED_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            image_url_timeout_secs: num("IMAGE_URL_TIMEOUT_SECS").unwrap_or(5),
+            image_url_max_bytes: num("IMAGE_URL_MAX_BYTES").unwrap_or(2 * 1024 * 1024),
+            image_url_cache_secs: num("IMAGE_URL_CACHE_SECS").unwrap_or(300),
+
+            log_dir: var("LOG_DIR").unwrap_or_else(|| "logs".to_string()),
+            log_rotation,
+            log_max_files,
+            rust_log: var("RUST_LOG"),
+        })
+    }
+}
+
+static SETTINGS: OnceCell<Arc<Settings>> = OnceCell::new();
+
+/// Parse setting dari env dan pasang sebagai setting proses; dipanggil sekali di awal `main`
+pub fn init() -> anyhow::Result<Arc<Settings>> {
+    let settings = Arc::new(Settings::from_env()?);
+    SETTINGS
+        .set(settings.clone())
+        .map_err(|_| anyhow::anyhow!("settings sudah di-init"))?;
+    Ok(settings)
+}
+
+/// Setting proses untuk modul yang tidak punya akses `AppState`
+pub fn settings() -> &'static Settings {
+    SETTINGS.get().expect("settings::init() belum dipanggil")
+}
+
+/// Setting untuk unit test: env proses apa adanya, tapi config printer di direktori temp
+/// supaya test tidak menyentuh `printers.yaml` di working directory. Env proses tidak diubah.
+#[cfg(test)]
+pub fn init_for_tests() -> Arc<Settings> {
+    /// Direktori temp hidup selama proses test
+    static TEST_DIR: once_cell::sync::Lazy<tempfile::TempDir> = once_cell::sync::Lazy::new(|| tempfile::tempdir().expect("buat direktori temp test"));
+
+    SETTINGS
+        .get_or_init(|| {
+            let mut settings = Settings::from_env().expect("setting test valid");
+            settings.printers_config = TEST_DIR.path().join("printers.yaml").to_string_lossy().into_owned();
+            settings.printers_config_inline = None;
+            Arc::new(settings)
+        })
+        .clone()
+}
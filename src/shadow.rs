@@ -0,0 +1,91 @@
+use crate::{config::Printer, graphics::GraphicRefs, queue::{send_queued, Priority}};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+use tracing::{debug, info, warn};
+
+/// Target salinan job: `SHADOW_PRINTER_ID` (printer di config) dan/atau `SHADOW_FILE` (file append)
+struct ShadowConfig {
+    printer_id: Option<String>,
+    file: Option<String>,
+}
+
+static SHADOW: Lazy<ShadowConfig> = Lazy::new(|| {
+    let s = crate::settings::settings();
+    ShadowConfig {
+        printer_id: s.shadow_printer_id.clone(),
+        file: s.shadow_file.clone(),
+    }
+});
+
+/// Satu penulis shadow file sekaligus supaya record dari job yang bersamaan tidak saling menyela
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Record shadow file: baris header `SHADOW <timestamp> <panjang> <printer_id>\n`, lalu tepat
+/// `<panjang>` bytes ESC/POS dan `\n` penutup. Payload bisa berisi byte apa saja (termasuk `\n`),
+/// jadi pembaca memisahkan job lewat panjang di header, bukan mencari pemisah.
+fn file_record(source_id: &str, payload: &[u8]) -> Vec<u8> {
+    let mut record = format!("SHADOW {} {} {}\n", chrono::Utc::now().to_rfc3339(), payload.len(), source_id).into_bytes();
+    record.extend_from_slice(payload);
+    record.push(b'\n');
+    record
+}
+
+/// Log target shadow saat startup; printer shadow yang tidak ada di config hanya di-warn
+pub fn log_startup(printers: &HashMap<String, Printer>) {
+    if let Some(id) = &SHADOW.printer_id {
+        if printers.contains_key(id) {
+            info!("🪞 Shadow printer: setiap job disalin ke '{}'", id);
+        } else {
+            warn!("⚠️ SHADOW_PRINTER_ID '{}' tidak ada di config, salinan ke printer dilewati", id);
+        }
+    }
+    if let Some(path) = &SHADOW.file {
+        info!("🪞 Shadow file: setiap job ditambahkan ke {}", path);
+    }
+}
+
+/// Salin bytes job ke target shadow di background; gagal hanya di-log, tidak mempengaruhi job asli
+pub fn mirror(printers: &HashMap<String, Printer>, source_id: &str, payload: &[u8]) {
+    if SHADOW.printer_id.is_none() && SHADOW.file.is_none() {
+        return;
+    }
+    let payload = Arc::new(payload.to_vec());
+
+    // Job untuk printer shadow sendiri tidak disalin lagi ke dirinya
+    let shadow_printer = SHADOW
+        .printer_id
+        .as_deref()
+        .filter(|id| *id != source_id)
+        .and_then(|id| printers.get(id).cloned());
+    if let Some(printer) = shadow_printer {
+        let payload = payload.clone();
+        let source_id = source_id.to_string();
+        tokio::spawn(async move {
+            // Salinan tidak boleh mendahului job asli printer shadow
+            match send_queued(&printer, &payload, Priority::Low, GraphicRefs::AsIs).await {
+                Ok(()) => debug!("🪞 Job '{}' disalin ke shadow printer '{}' ({} bytes)", source_id, printer.id, payload.len()),
+                Err(e) => warn!("⚠️ Salinan job '{}' ke shadow printer '{}' gagal: {}", source_id, printer.id, e),
+            }
+        });
+    }
+
+    if let Some(path) = SHADOW.file.clone() {
+        let source_id = source_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            let record = file_record(&source_id, &payload);
+            let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let result = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut f| f.write_all(&record));
+            if let Err(e) = result {
+                warn!("⚠️ Salinan job '{}' ke shadow file {} gagal: {}", source_id, path, e);
+            }
+        });
+    }
+}
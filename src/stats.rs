@@ -0,0 +1,137 @@
+use crate::errors::ProxyError;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+/// Counter operasional sejak startup atau reset terakhir (`/admin/stats`)
+struct Stats {
+    since: Mutex<chrono::DateTime<chrono::Utc>>,
+    jobs_ok: AtomicU64,
+    jobs_failed: AtomicU64,
+    /// Kegagalan per jenis error (`ProxyError::kind`)
+    failures: DashMap<&'static str, u64>,
+    /// (sukses, gagal) per printer
+    per_printer: DashMap<String, (u64, u64)>,
+    pool_reused: AtomicU64,
+    pool_created: AtomicU64,
+    health_cache_hits: AtomicU64,
+    health_cache_misses: AtomicU64,
+}
+
+static STATS: Lazy<Stats> = Lazy::new(|| Stats {
+    since: Mutex::new(chrono::Utc::now()),
+    jobs_ok: AtomicU64::new(0),
+    jobs_failed: AtomicU64::new(0),
+    failures: DashMap::new(),
+    per_printer: DashMap::new(),
+    pool_reused: AtomicU64::new(0),
+    pool_created: AtomicU64::new(0),
+    health_cache_hits: AtomicU64::new(0),
+    health_cache_misses: AtomicU64::new(0),
+});
+
+/// Mulai hitung `since` dari startup, bukan dari job pertama
+pub fn init() {
+    Lazy::force(&STATS);
+}
+
+pub fn record_job(printer_id: &str, result: Result<(), &ProxyError>) {
+    let mut counts = STATS.per_printer.entry(printer_id.to_string()).or_default();
+    match result {
+        Ok(()) => {
+            STATS.jobs_ok.fetch_add(1, Ordering::Relaxed);
+            counts.0 += 1;
+        }
+        Err(e) => {
+            STATS.jobs_failed.fetch_add(1, Ordering::Relaxed);
+            counts.1 += 1;
+            *STATS.failures.entry(e.kind()).or_default() += 1;
+        }
+    }
+}
+
+/// Koneksi diambil dari pool (`reused`) atau dibuka baru
+pub fn record_pool_connection(reused: bool) {
+    let counter = if reused { &STATS.pool_reused } else { &STATS.pool_created };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_health_cache(hit: bool) {
+    let counter = if hit { &STATS.health_cache_hits } else { &STATS.health_cache_misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Rasio a / (a + b), null jika belum ada data
+fn rate(a: u64, b: u64) -> Value {
+    if a + b == 0 {
+        Value::Null
+    } else {
+        json!((a as f64 / (a + b) as f64 * 1000.0).round() / 1000.0)
+    }
+}
+
+pub fn snapshot() -> Value {
+    let since = *STATS.since.lock().unwrap_or_else(|e| e.into_inner());
+    let ok = STATS.jobs_ok.load(Ordering::Relaxed);
+    let failed = STATS.jobs_failed.load(Ordering::Relaxed);
+    let reused = STATS.pool_reused.load(Ordering::Relaxed);
+    let created = STATS.pool_created.load(Ordering::Relaxed);
+    let hits = STATS.health_cache_hits.load(Ordering::Relaxed);
+    let misses = STATS.health_cache_misses.load(Ordering::Relaxed);
+
+    let failures: serde_json::Map<String, Value> =
+        STATS.failures.iter().map(|e| (e.key().to_string(), json!(*e.value()))).collect();
+    let queues: serde_json::Map<String, Value> =
+        crate::queue::depths().into_iter().map(|(id, waiting)| (id, json!(waiting))).collect();
+    let deferred: serde_json::Map<String, Value> =
+        crate::deferred::depths().into_iter().map(|(id, waiting)| (id, json!(waiting))).collect();
+    let printers: serde_json::Map<String, Value> = STATS
+        .per_printer
+        .iter()
+        .map(|e| {
+            let (ok, failed) = *e.value();
+            (e.key().clone(), json!({ "ok": ok, "failed": failed }))
+        })
+        .collect();
+
+    json!({
+        "since": since.to_rfc3339(),
+        "jobs": {
+            "total": ok + failed,
+            "ok": ok,
+            "failed": failed,
+            "failures_by_kind": failures,
+        },
+        "printers": printers,
+        "queues": queues,
+        "deferred": deferred,
+        "serial_writes": crate::pool::serial_progress(),
+        "pool": {
+            "reused": reused,
+            "created": created,
+            "reuse_rate": rate(reused, created),
+        },
+        "health_cache": {
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": rate(hits, misses),
+        },
+    })
+}
+
+/// Nolkan semua counter; `since` diset ke waktu reset
+pub fn reset() {
+    *STATS.since.lock().unwrap_or_else(|e| e.into_inner()) = chrono::Utc::now();
+    STATS.jobs_ok.store(0, Ordering::Relaxed);
+    STATS.jobs_failed.store(0, Ordering::Relaxed);
+    STATS.failures.clear();
+    STATS.per_printer.clear();
+    STATS.pool_reused.store(0, Ordering::Relaxed);
+    STATS.pool_created.store(0, Ordering::Relaxed);
+    STATS.health_cache_hits.store(0, Ordering::Relaxed);
+    STATS.health_cache_misses.store(0, Ordering::Relaxed);
+}
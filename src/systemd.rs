@@ -0,0 +1,66 @@
+//! Integrasi systemd: `READY=1` setelah listener bind dan `WATCHDOG=1` periodik jika `WatchdogSec` di-set.
+//! Semua fungsi no-op jika proses tidak dijalankan oleh systemd (`NOTIFY_SOCKET` tidak ada).
+
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+fn under_systemd() -> bool {
+    crate::settings::settings().systemd_notify
+}
+
+#[cfg(unix)]
+fn notify(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("⚠️ sd_notify failed: {}", e);
+    }
+}
+
+/// Beri tahu systemd bahwa service siap menerima koneksi
+pub fn notify_ready() {
+    if !under_systemd() {
+        return;
+    }
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Ready]);
+    info!("📣 systemd notified: READY=1");
+}
+
+/// Beri tahu systemd bahwa service sedang berhenti (graceful shutdown)
+pub fn notify_stopping() {
+    if !under_systemd() {
+        return;
+    }
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Interval ping watchdog: setengah `WATCHDOG_USEC`, `None` jika watchdog tidak aktif
+fn watchdog_interval() -> Option<Duration> {
+    if !under_systemd() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        let mut usec = 0u64;
+        if sd_notify::watchdog_enabled(false, &mut usec) && usec > 0 {
+            return Some(Duration::from_micros(usec / 2));
+        }
+    }
+    None
+}
+
+/// Background task yang mengirim `WATCHDOG=1`; selama runtime tokio masih jalan systemd tidak me-restart service
+pub async fn start_watchdog_task() {
+    let Some(interval) = watchdog_interval() else {
+        debug!("🐕 systemd watchdog not enabled");
+        return;
+    };
+    info!("🐕 systemd watchdog enabled, pinging every {:?}", interval);
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        #[cfg(unix)]
+        notify(&[sd_notify::NotifyState::Watchdog]);
+    }
+}
@@ -0,0 +1,79 @@
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::fmt::Write;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+/// Satu payload yang ditulis ke printer, untuk `GET /admin/printer/{id}/trace`
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub printer_id: String,
+    pub timestamp: String,
+    /// Jumlah byte yang benar-benar ditulis
+    pub bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Hex dump gaya `xxd`: offset, 16 byte hex, lalu ASCII
+    pub dump: String,
+    /// Dump dipotong di `TRACE_MAX_BYTES`
+    pub truncated: bool,
+}
+
+/// Channel per printer id, hanya ada selama ada tracer yang terhubung
+static TRACERS: Lazy<DashMap<String, broadcast::Sender<TraceEvent>>> = Lazy::new(DashMap::new);
+
+
+/// Mulai menerima payload yang ditulis ke printer `printer_id`
+pub fn subscribe(printer_id: &str) -> broadcast::Receiver<TraceEvent> {
+    TRACERS
+        .entry(printer_id.to_string())
+        .or_insert_with(|| broadcast::channel(64).0)
+        .subscribe()
+}
+
+/// Publikasikan payload ke tracer printer. Tanpa tracer, hanya satu lookup DashMap (tidak ada dump).
+pub fn publish(printer_id: &str, session_id: Option<&str>, payload: &[u8]) {
+    let Some(sender) = TRACERS.get(printer_id).map(|s| s.clone()) else {
+        return;
+    };
+    if sender.receiver_count() == 0 {
+        // Semua tracer sudah putus
+        TRACERS.remove_if(printer_id, |_, s| s.receiver_count() == 0);
+        return;
+    }
+
+    let shown = &payload[..payload.len().min(crate::settings::settings().trace_max_bytes)];
+    debug!("🔍 Tracing {} bytes to '{}'", payload.len(), printer_id);
+    // Error hanya berarti tracer putus di antara cek dan kirim
+    let _ = sender.send(TraceEvent {
+        printer_id: printer_id.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        bytes: payload.len(),
+        session_id: session_id.map(str::to_string),
+        dump: hex_dump(shown),
+        truncated: shown.len() < payload.len(),
+    });
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 + bytes.len() / 16 * 12);
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}  ", i * 16);
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => {
+                    let _ = write!(out, "{:02x} ", b);
+                }
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    out
+}
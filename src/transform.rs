@@ -0,0 +1,123 @@
+use crate::errors::ProxyError;
+use std::{process::Stdio, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{debug, error, info};
+
+/// Batas waktu proses transform (env `TRANSFORM_TIMEOUT_SECS`, default 10)
+fn transform_timeout() -> Duration {
+    Duration::from_secs(crate::settings::settings().transform_timeout_secs)
+}
+
+/// Batas waktu `pre_hook`/`post_hook` (env `HOOK_TIMEOUT_SECS`, default 10)
+fn hook_timeout() -> Duration {
+    Duration::from_secs(crate::settings::settings().hook_timeout_secs)
+}
+
+/// Variabel environment proxy yang diteruskan ke program eksternal; sisanya (token admin, kredensial)
+/// tidak ikut diwariskan
+const INHERITED_ENV: &[&str] = &["PATH", "LANG"];
+
+/// Command dengan environment kosong kecuali `INHERITED_ENV`; variabel job ditambahkan pemanggil
+fn external_command(program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.env_clear();
+    for name in INHERITED_ENV {
+        if let Some(value) = std::env::var_os(name) {
+            cmd.env(name, value);
+        }
+    }
+    cmd
+}
+
+/// Jalankan `pre_hook`/`post_hook` printer tanpa shell. Environment hanya `INHERITED_ENV` ditambah
+/// `PRINTER_ID`, `JOB_ID`, `HOOK` (dan `JOB_BYTES` untuk post_hook). Exit code selain 0 atau timeout = error.
+pub async fn run_hook(hook: &str, command: &str, printer_id: &str, job_id: &str, job_bytes: Option<usize>) -> Result<(), ProxyError> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| ProxyError::Io(format!("{} kosong", hook)))?;
+
+    debug!("🪝 Running {} '{}' for printer '{}' job {}", hook, command, printer_id, job_id);
+    let mut cmd = external_command(program);
+    cmd.args(parts)
+        .env("PRINTER_ID", printer_id)
+        .env("JOB_ID", job_id)
+        .env("HOOK", hook)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(bytes) = job_bytes {
+        cmd.env("JOB_BYTES", bytes.to_string());
+    }
+    let child = cmd
+        .spawn()
+        .map_err(|e| ProxyError::Io(format!("{} '{}' gagal dijalankan: {}", hook, command, e)))?;
+
+    let output = match tokio::time::timeout(hook_timeout(), child.wait_with_output()).await {
+        Ok(result) => result.map_err(|e| ProxyError::Io(format!("{} '{}' gagal: {}", hook, command, e)))?,
+        Err(_) => {
+            error!("⏱️ {} '{}' timed out for printer '{}'", hook, command, printer_id);
+            return Err(ProxyError::Io(format!("{} '{}' timeout", hook, command)));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("❌ {} '{}' exited with {}: {}", hook, command, output.status, stderr.trim());
+        return Err(ProxyError::Io(format!("{} '{}' gagal ({})", hook, command, output.status)));
+    }
+    info!("🪝 {} '{}' finished for printer '{}'", hook, command, printer_id);
+    Ok(())
+}
+
+/// Alirkan bytes ESC/POS lewat stdin `command` dan pakai stdout-nya sebagai payload.
+/// Command dipecah per spasi dan dijalankan langsung tanpa shell; environment hanya `PATH`, `LANG`, dan `PRINTER_ID`.
+pub async fn run_transform(command: &str, printer_id: &str, payload: &[u8]) -> Result<Vec<u8>, ProxyError> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| ProxyError::Io("transform_command kosong".into()))?;
+
+    debug!("🔧 Running transform_command '{}' for printer '{}' ({} bytes)", command, printer_id, payload.len());
+    let mut child = external_command(program)
+        .args(parts)
+        .env("PRINTER_ID", printer_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| ProxyError::Io(format!("transform_command '{}' gagal dijalankan: {}", command, e)))?;
+
+    // Tulis stdin di task terpisah supaya proses yang langsung menulis stdout tidak deadlock
+    let mut stdin = child.stdin.take().expect("stdin piped");
+    let input = payload.to_vec();
+    let writer = tokio::spawn(async move {
+        let result = stdin.write_all(&input).await;
+        drop(stdin);
+        result
+    });
+
+    let output = match tokio::time::timeout(transform_timeout(), child.wait_with_output()).await {
+        Ok(result) => result.map_err(|e| ProxyError::Io(format!("transform_command '{}' gagal: {}", command, e)))?,
+        Err(_) => {
+            error!("⏱️ transform_command '{}' timed out for printer '{}'", command, printer_id);
+            return Err(ProxyError::Io(format!("transform_command '{}' timeout", command)));
+        }
+    };
+    if let Ok(Err(e)) = writer.await {
+        debug!("⚠️ transform_command '{}' closed stdin early: {}", command, e);
+    }
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("❌ transform_command '{}' exited with {}: {}", command, output.status, stderr.trim());
+        return Err(ProxyError::Io(format!("transform_command '{}' gagal ({})", command, output.status)));
+    }
+    if output.stdout.is_empty() {
+        return Err(ProxyError::Io(format!("transform_command '{}' tidak menghasilkan output", command)));
+    }
+
+    info!("🔧 transform_command '{}': {} -> {} bytes", command, payload.len(), output.stdout.len());
+    Ok(output.stdout)
+}